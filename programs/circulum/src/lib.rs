@@ -1,5 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::sysvar::instructions::load_instruction_at_checked;
+use anchor_spl::token_interface::{self, Approve, Mint, TokenAccount, TokenInterface, TransferChecked};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -15,6 +19,21 @@ pub mod circulum {
     /// * `interval_seconds` - Billing interval in seconds (minimum 60)
     /// * `max_subscribers` - Maximum number of allowed subscribers
     /// * `metadata_uri` - URI pointing to plan metadata (max 200 chars)
+    /// * `metadata_hash` - SHA-256 of the metadata JSON (all zeros if unset)
+    /// * `currency_symbol` - Display label for the payment mint, e.g. "USDC"
+    ///   (max 10 bytes, may be empty; `payment_mint` stays authoritative)
+    /// 
+    /// The `payment_mint` and `payout_token_account` accounts pin the plan's
+    /// denomination and the creator account that receives payments. The
+    /// mint's decimals are recorded as `payment_decimals` so `price` can be
+    /// formatted without fetching the mint; `subscribe` and
+    /// `process_payment` fail with `PaymentDecimalsMismatch` if the mint
+    /// passed in disagrees.
+    /// 
+    /// Each plan records a `creation_nonce` from the creator's registry
+    /// (`["creator_registry", creator]`, created on first use), so indexers
+    /// can tell apart plans that reused a `plan_id`.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_subscription_plan(
         ctx: Context<CreateSubscriptionPlan>,
         plan_id: u64,
@@ -22,16 +41,32 @@ pub mod circulum {
         interval_seconds: i64,
         max_subscribers: u32,
         metadata_uri: String,
+        metadata_hash: [u8; 32],
+        currency_symbol: String,
     ) -> Result<()> {
         // Validate inputs
         require!(price > 0, ErrorCode::InvalidPrice);
         require!(interval_seconds >= 60, ErrorCode::IntervalTooShort);
         require!(max_subscribers > 0, ErrorCode::InvalidMaxSubscribers);
         require!(metadata_uri.len() <= 200, ErrorCode::MetadataUriTooLong);
+        require!(currency_symbol.len() <= 10, ErrorCode::CurrencySymbolTooLong);
+        let program_config = ProgramConfig::load_or_default(&ctx.accounts.program_config)?;
+        check_min_price(price, program_config.min_price)?;
 
         let subscription_plan = &mut ctx.accounts.subscription_plan;
         let creator = &ctx.accounts.creator;
-        let clock = Clock::get()?;
+        let now = current_time()?;
+
+        // Collect the deployment's creation fee, if any, before setting up the plan
+        collect_creation_fee(
+            &program_config,
+            &CreatorConfig::load_or_default(&ctx.accounts.creator_config)?,
+            creator,
+            &ctx.accounts.fee_destination,
+            &ctx.accounts.system_program,
+            plan_id,
+            now,
+        )?;
 
         subscription_plan.creator = creator.key();
         subscription_plan.plan_id = plan_id;
@@ -42,15 +77,84 @@ pub mod circulum {
         subscription_plan.is_active = true;
         subscription_plan.is_paused = false;
         subscription_plan.metadata_uri = metadata_uri;
-        subscription_plan.created_at = clock.unix_timestamp;
+        subscription_plan.metadata_hash = metadata_hash;
+        subscription_plan.created_at = now;
         subscription_plan.bump = ctx.bumps.subscription_plan;
+        subscription_plan.early_tolerance_seconds = 0;
+        subscription_plan.maintenance_start = 0;
+        subscription_plan.maintenance_end = 0;
+        subscription_plan.payment_mint = ctx.accounts.payment_mint.key();
+        subscription_plan.payout_token_account = ctx.accounts.payout_token_account.key();
+        subscription_plan.refund_window_seconds = 0;
+        subscription_plan.bill_on_weekday_only = false;
+        subscription_plan.prepay_cycles_on_join = 1;
+        subscription_plan.allow_interval_tightening = false;
+        subscription_plan.successor_plan_id = None;
+        subscription_plan.max_prepaid_cycles = DEFAULT_MAX_PREPAID_CYCLES;
+        subscription_plan.currency_symbol = currency_symbol.clone();
+        subscription_plan.interval_unit = IntervalUnit::Seconds;
+        subscription_plan.interval_count = 0;
+        subscription_plan.prorated_cancel_refund = false;
+        subscription_plan.manager = None;
+        subscription_plan.reminder_lead_seconds = DEFAULT_REMINDER_LEAD_SECONDS;
+        subscription_plan.auto_expand_step = 0;
+        subscription_plan.hard_cap = 0;
+        subscription_plan.comp_subscribers = 0;
+        subscription_plan.jitter_seconds = 0;
+        subscription_plan.setup_fee = 0;
+        subscription_plan.active_count = 0;
+        subscription_plan.paused_count = 0;
+        subscription_plan.expired_count = 0;
+        subscription_plan.billing_anchor_day = 0;
+        subscription_plan.past_grace_policy = PastGracePolicy::Expire;
+        subscription_plan.max_debt_cycles = 0;
+        subscription_plan.activation_threshold = 0;
+        subscription_plan.funding_deadline = 0;
+        subscription_plan.threshold_reached = false;
+        subscription_plan.require_memo = false;
+        subscription_plan.schema_version = CURRENT_SCHEMA_VERSION;
+        subscription_plan.deactivation_notice_seconds = 0;
+        subscription_plan.deactivation_effective_at = 0;
+        subscription_plan.secondary_payment_mint = None;
+        subscription_plan.secondary_payout_token_account = Pubkey::default();
+        subscription_plan.secondary_price = 0;
+        subscription_plan.accrued_revenue = 0;
+        subscription_plan.last_payment_at = 0;
+        subscription_plan.loyalty_discount_bps_per_year = 0;
+        subscription_plan.max_loyalty_discount_bps = 0;
+        subscription_plan.payment_decimals = Some(ctx.accounts.payment_mint.decimals);
+        subscription_plan.max_lifetime_seconds = 0;
+        subscription_plan.new_subscriptions_disabled = false;
+        subscription_plan.min_subscription_seconds = 0;
+        subscription_plan.accepted_tokens = Vec::new();
+        subscription_plan.delisted_tokens = Vec::new();
+        subscription_plan.pay_what_you_want = false;
+        subscription_plan.block_same_tx_cancel = false;
+        subscription_plan.grace_window = GraceWindow::Default;
+        subscription_plan.event_seq = 0;
+        subscription_plan.capacity_change_cooldown_seconds = 0;
+        subscription_plan.last_capacity_change = 0;
+        subscription_plan.verified = false;
+
+        // Distinguishes this plan from any earlier one that reused `plan_id`
+        assign_creation_nonce(
+            subscription_plan,
+            &mut ctx.accounts.creator_registry,
+            creator.key(),
+            ctx.bumps.creator_registry,
+        )?;
 
         emit!(SubscriptionPlanCreated {
             creator: creator.key(),
             plan_id,
             price,
             interval_seconds,
-            timestamp: clock.unix_timestamp,
+            metadata_hash,
+            currency_symbol,
+            timestamp: now,
+            creation_nonce: subscription_plan.creation_nonce,
+            payment_decimals: Some(ctx.accounts.payment_mint.decimals),
+            event_seq: subscription_plan.next_event_seq()?,
         });
 
         Ok(())
@@ -60,123 +164,447 @@ pub mod circulum {
     /// 
     /// # Security
     /// - Validates token accounts belong to correct owners
+    /// - Rejects the same account as payer and payee with `SelfPayment`
+    /// - Token program must own the payment mint (SPL Token or Token-2022)
     /// - Collects first payment immediately
     /// - Verifies plan capacity and active status
+    /// 
+    /// A full plan fails with `PlanFull` and logs `PlanCapacityReached`
+    /// (current/max) in the failed transaction's logs.
+    /// 
+    /// `reference` is an optional invoice/PO reference (all zeros if unused)
+    /// echoed in every `PaymentProcessed` event for reconciliation.
+    /// `ends_at` fixes a hard end date for fixed-term access (0 = open-ended).
+    /// 
+    /// # Memo
+    /// On plans with `require_memo`, fails with `MemoRequired` unless the
+    /// transaction also carries an SPL Memo instruction (e.g. an invoice
+    /// number for off-chain reconciliation). The same applies to
+    /// `process_payment`.
+    /// 
+    /// # Rent
+    /// `rent_payer` funds the subscription account's rent. Pass the
+    /// subscriber itself, or a sponsor (e.g. an app treasury) to onboard
+    /// users without SOL; the subscriber still signs and remains the
+    /// subscription's owner either way.
+    /// 
+    /// # Funding
+    /// Tokens move under `funding_authority`, which must own
+    /// `subscriber_token_account` or be its approved delegate with enough
+    /// allowance left for the charge, else `UnauthorizedFundingAuthority`.
+    /// Usually this is the subscriber; a smart wallet or treasury can pay
+    /// from an account the subscriber doesn't own. The same applies to
+    /// `process_payment`.
+    /// 
+    /// # Idempotency
+    /// The subscription PDA is derived from `subscriber + plan_id` and created
+    /// with `init`, so a retried or duplicate `subscribe` fails atomically with
+    /// the system program's "already in use" error before any transfer. This
+    /// also holds after `cancel_subscription`: the inactive account must be
+    /// removed with `close_subscription` before the subscriber can join again.
+    /// 
+    /// # CPI
+    /// Other programs can subscribe a PDA they own by invoking `subscribe`
+    /// with `invoke_signed` and the PDA as `subscriber`, `rent_payer` and
+    /// `funding_authority`. The PDA signs as rent payer and as token authority, so
+    /// it must be system-owned (no data), hold enough lamports for
+    /// `Subscription::LEN` rent, and own `subscriber_token_account`. See
+    /// `programs/mock_caller`.
+    /// 
+    /// # Return data
+    /// Returns a Borsh-encoded `PaymentReceipt` via `set_return_data`, so
+    /// clients can confirm the schedule without re-fetching the account.
+    /// Anchor clients decode it from the transaction's `returnData`
+    /// (`program.coder.types.decode("PaymentReceipt", ...)`); CPI callers get
+    /// it from `Return::get()`.
+    /// 
+    /// # Payment token
+    /// Pass the plan's `payment_mint`, or one of its `accepted_tokens`
+    /// (failing with `PaymentTokenNotAccepted` otherwise) with matching token
+    /// accounts. The choice is recorded on the subscription and every
+    /// renewal is billed in it at that token's price. Subscriptions in an
+    /// accepted token renew through `process_payment` and `pay_all_due`; the
+    /// other payment and refund instructions only handle the plan's own mint.
+    /// A setup fee is priced in the plan's mint, so plans with one fail with
+    /// `SetupFeeInAcceptedToken` for other tokens.
+    /// 
+    /// # Pay what you want
+    /// On `pay_what_you_want` plans, `chosen_price` (in the plan's mint, at
+    /// least its `price`) replaces the price for this and every later
+    /// charge; see `update_chosen_price`. `None` pays the plan's price.
+    /// Other plans fail with `PayWhatYouWantDisabled` if one is given.
+    /// 
+    /// # Slippage
+    /// `max_token_amount` caps the total charged by this call (cycles plus
+    /// setup fee); if the plan's price moved above it since the transaction
+    /// was signed, fails with `SlippageExceeded`. `None` is unbounded.
     pub fn subscribe(
         ctx: Context<Subscribe>,
         plan_id: u64,
-    ) -> Result<()> {
+        reference: [u8; 32],
+        ends_at: i64,
+        max_token_amount: Option<u64>,
+        chosen_price: Option<u64>,
+    ) -> Result<PaymentReceipt> {
         let subscription_plan = &mut ctx.accounts.subscription_plan;
         let subscription = &mut ctx.accounts.subscription;
         let subscriber = &ctx.accounts.subscriber;
-        let clock = Clock::get()?;
+        let now = current_time()?;
 
         // Check if plan is active, not paused, and has capacity
-        require!(subscription_plan.is_active, ErrorCode::PlanInactive);
-        require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+        check_can_join(subscription_plan, now)?;
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+
+        // Charge the plan's upfront cycles (one unless configured otherwise)
+        // in the chosen token, plus its setup fee
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let mut price = subscription_plan
+            .price_in(&payment_mint)
+            .ok_or(ErrorCode::PaymentTokenNotAccepted)?;
+        if let Some(chosen_price) = chosen_price {
+            check_chosen_price(subscription_plan, &payment_mint, chosen_price)?;
+            subscription.chosen_price = chosen_price;
+            price = chosen_price;
+        }
+        let (prepaid_cycles, cycles_amount) = initial_charge(subscription_plan, price)?;
+        let setup_fee = setup_fee_due(subscription, subscription_plan);
         require!(
-            subscription_plan.current_subscribers < subscription_plan.max_subscribers,
-            ErrorCode::PlanFull
+            setup_fee == 0 || payment_mint == subscription_plan.payment_mint,
+            ErrorCode::SetupFeeInAcceptedToken
+        );
+        let initial_amount = cycles_amount
+            .checked_add(setup_fee)
+            .ok_or(ErrorCode::Overflow)?;
+        check_max_token_amount(initial_amount, max_token_amount)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= initial_amount,
+            ErrorCode::InsufficientFunds
         );
+        check_funding_authority(
+            &ctx.accounts.subscriber_token_account,
+            ctx.accounts.funding_authority.key(),
+            initial_amount,
+        )?;
 
         // Process initial payment
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
             to: ctx.accounts.creator_token_account.to_account_info(),
-            authority: ctx.accounts.subscriber.to_account_info(),
+            authority: ctx.accounts.funding_authority.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         
-        token::transfer(cpi_ctx, subscription_plan.price)?;
+        token_interface::transfer_checked(cpi_ctx, initial_amount, ctx.accounts.payment_mint.decimals)?;
 
         // Initialize subscription
         subscription.subscriber = subscriber.key();
+        subscription.schema_version = CURRENT_SCHEMA_VERSION;
+        subscription.created_at = now;
         subscription.plan_id = plan_id;
         subscription.creator = subscription_plan.creator;
-        subscription.is_active = true;
-        subscription.last_payment = clock.unix_timestamp;
-        subscription.next_payment = clock.unix_timestamp
-            .checked_add(subscription_plan.interval_seconds)
-            .ok_or(ErrorCode::Overflow)?;
-        subscription.total_payments = 1; // Initial payment counts
         subscription.bump = ctx.bumps.subscription;
+        subscription.maintenance_credited_until = 0;
+        subscription.reference = reference;
+        subscription.ends_at = ends_at;
+        subscription.reserved_until = 0;
+        subscription.payment_mint = payment_mint;
+        activate_subscription(
+            subscription,
+            subscription_plan,
+            prepaid_cycles,
+            initial_amount,
+            now,
+        )?;
+        subscription.setup_fee_paid |= setup_fee > 0;
+        // The setup fee isn't part of what a cycle costs
+        subscription.record_cycle_amount(cycles_amount, prepaid_cycles);
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
 
         // Update plan subscriber count with overflow check
-        subscription_plan.current_subscribers = subscription_plan.current_subscribers
-            .checked_add(1)
-            .ok_or(ErrorCode::Overflow)?;
+        take_seat(subscription_plan, now)?;
 
         emit!(SubscriptionCreated {
             subscriber: subscriber.key(),
             creator: subscription_plan.creator,
             plan_id,
-            timestamp: clock.unix_timestamp,
+            prepaid_cycles,
+            timestamp: now,
+            setup_fee,
+            created_at: subscription.created_at,
+            payment_mint,
+            chosen_price: subscription.chosen_price,
         });
 
-        Ok(())
+        Ok(PaymentReceipt::for_subscription(subscription, initial_amount, now))
     }
 
     /// Process recurring payment for an active subscription
     /// 
+    /// # Schedule
+    /// `next_payment` advances from the previous due date, not from the time
+    /// of payment, so late payments within grace keep the original cadence.
+    /// 
+    /// # Term end
+    /// Once `now >= ends_at` on a fixed-term subscription, nothing is charged:
+    /// the subscription is deactivated, its seat freed and `SubscriptionEnded`
+    /// emitted.
+    /// 
     /// # Security
     /// - Validates payment is due within acceptable window
-    /// - Verifies token account ownership and mint
+    /// - Verifies the funding authority may spend the token account, and its mint
+    /// - Rejects the same account as payer and payee with `SelfPayment`
+    /// - Token program must own the payment mint (SPL Token or Token-2022)
     /// - Checks subscription and plan are active
-    pub fn process_payment(
-        ctx: Context<ProcessPayment>,
+    /// - Fails with `InsufficientFunds` before the transfer if the
+    ///   subscriber's balance is below the price
+    /// 
+    /// # Past grace
+    /// On plans with `PastGracePolicy::Expire` (the default) a payment past
+    /// grace fails with `PaymentTooLate`. On `Accumulate` plans it charges
+    /// every missed cycle at once, restoring the original cadence, or fails
+    /// with `MaxDebtExceeded` once more than `max_debt_cycles` are owed.
+    /// 
+    /// Returns a `PaymentReceipt` like `subscribe`; `charged_amount` is 0
+    /// when the call ended a fixed-term subscription instead of charging.
+    /// 
+    /// `max_token_amount` caps the charge as in `subscribe`, in the units of
+    /// whichever token is charged.
+    /// 
+    /// # Secondary token
+    /// On plans with `secondary_payment_mint`, pass the subscriber's
+    /// secondary token account, the secondary mint and the plan's
+    /// `secondary_payout_token_account` as `remaining_accounts`. If the
+    /// primary balance is short, `secondary_price` per cycle is charged from
+    /// that account instead, failing with `AllPaymentTokensInsufficient` if
+    /// it can't cover it either. `PaymentProcessed::payment_mint` records
+    /// which token paid; `total_paid_amount` keeps counting at the primary
+    /// price.
+    /// 
+    /// # Frozen accounts
+    /// If the mint's freeze authority has frozen the subscriber's token
+    /// account, nothing is charged: the subscription is flagged
+    /// `payment_blocked`, `PaymentBlockedFrozenAccount` is emitted and a
+    /// zero receipt returned, so the failure is visible on-chain rather than
+    /// an opaque token-program error. Grace keeps running; once the account
+    /// is thawed, `clear_payment_blocked` or the next successful payment
+    /// clears the flag.
+    pub fn process_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessPayment<'info>>,
+        plan_id: u64,
+        max_token_amount: Option<u64>,
+    ) -> Result<PaymentReceipt> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        if subscription.is_active && subscription.has_ended(now) {
+            end_subscription(subscription, subscription_plan, now)?;
+            return Ok(PaymentReceipt::for_subscription(subscription, 0, now));
+        }
+        if subscription.is_active
+            && subscription.lifetime_reached(subscription_plan.max_lifetime_seconds, now)
+        {
+            end_at_lifetime(subscription, subscription_plan, now)?;
+            return Ok(PaymentReceipt::for_subscription(subscription, 0, now));
+        }
+
+        let cycles = payable_cycles(subscription, subscription_plan, now)?;
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+        let amount = renewal_charge(subscription, subscription_plan, cycles, now)?;
+        emit_if_final_prorated(subscription, subscription_plan, cycles, amount, now)?;
+        let loyalty_discount = if subscription.is_comp {
+            0
+        } else {
+            loyalty_discount_bps(subscription, subscription_plan, now)
+        };
+
+        // Only fall back to the secondary token when the primary is short
+        let secondary = if ctx.accounts.subscriber_token_account.amount < amount
+            && subscription_plan.secondary_payment_mint.is_some()
+            && !ctx.remaining_accounts.is_empty()
+        {
+            Some(load_secondary_payment(
+                ctx.remaining_accounts,
+                subscription_plan,
+                ctx.accounts.token_program.key(),
+            )?)
+        } else {
+            None
+        };
+        let secondary_amount = subscription_plan.secondary_price
+            .checked_mul(cycles as u64)
+            .ok_or(ErrorCode::Overflow)?;
+        check_payment_funds(
+            ctx.accounts.subscriber_token_account.amount,
+            amount,
+            secondary.as_ref().map(|secondary| (secondary.token_account.amount, secondary_amount)),
+        )?;
+
+        // Record a frozen account instead of failing inside the transfer
+        let token_account = &ctx.accounts.subscriber_token_account;
+        if secondary.is_none()
+            && mark_payment_blocked(subscription, token_account, token_account.key())
+        {
+            emit!(PaymentBlockedFrozenAccount {
+                subscriber: subscription.subscriber,
+                plan_id,
+                token_account: token_account.key(),
+                amount,
+                timestamp: now,
+            });
+            return Ok(PaymentReceipt::for_subscription(subscription, 0, now));
+        }
+
+        let (charged_amount, charged_mint) = if let Some(secondary) = secondary {
+            check_max_token_amount(secondary_amount, max_token_amount)?;
+            check_funding_authority(
+                &secondary.token_account,
+                ctx.accounts.funding_authority.key(),
+                secondary_amount,
+            )?;
+
+            // Transfer the secondary-token price instead
+            let cpi_accounts = TransferChecked {
+                from: secondary.token_account.to_account_info(),
+                mint: secondary.mint.to_account_info(),
+                to: secondary.creator_token_account.to_account_info(),
+                authority: ctx.accounts.funding_authority.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+            token_interface::transfer_checked(cpi_ctx, secondary_amount, secondary.mint.decimals)?;
+            (secondary_amount, secondary.mint.key())
+        } else {
+            check_max_token_amount(amount, max_token_amount)?;
+            check_funding_authority(
+                &ctx.accounts.subscriber_token_account,
+                ctx.accounts.funding_authority.key(),
+                amount,
+            )?;
+
+            // Transfer payment from subscriber to creator
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.subscriber_token_account.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: ctx.accounts.creator_token_account.to_account_info(),
+                authority: ctx.accounts.funding_authority.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+            (amount, ctx.accounts.payment_mint.key())
+        };
+
+        // Lifetime spend is kept in primary-token terms either way
+        if cycles == 1 {
+            settle_cycle(subscription, subscription_plan, amount, now)?;
+        } else {
+            settle_cycles(subscription, subscription_plan, cycles, amount, now)?;
+        }
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(PaymentProcessed {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount: charged_amount,
+            payment_number: subscription.total_payments,
+            total_paid_amount: subscription.total_paid_amount,
+            reference: subscription.reference,
+            timestamp: now,
+            payment_mint: charged_mint,
+        });
+        if loyalty_discount > 0 {
+            emit!(LoyaltyDiscountApplied {
+                subscriber: subscription.subscriber,
+                plan_id,
+                discount_bps: loyalty_discount,
+                discounted_amount: amount,
+                timestamp: now,
+            });
+        }
+
+        Ok(PaymentReceipt::for_subscription(subscription, charged_amount, now))
+    }
+
+    /// Charge several missed billing cycles in one transaction
+    /// 
+    /// Charges every cycle that has come due (up to `max_cycles`), transferring
+    /// `price * cycles_charged` and advancing `next_payment` by the same number
+    /// of intervals. The oldest outstanding cycle must still be within grace.
+    pub fn process_payment_catchup(
+        ctx: Context<ProcessPaymentCatchup>,
         plan_id: u64,
+        max_cycles: u32,
     ) -> Result<()> {
         let subscription = &mut ctx.accounts.subscription;
-        let subscription_plan = &ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
 
-        // Verify payment is due (with 7-day grace period)
+        require!(max_cycles > 0, ErrorCode::InvalidMaxCycles);
+        if subscription.is_active && subscription.has_ended(now) {
+            return end_subscription(subscription, subscription_plan, now);
+        }
+        if subscription.is_active
+            && subscription.lifetime_reached(subscription_plan.max_lifetime_seconds, now)
+        {
+            return end_at_lifetime(subscription, subscription_plan, now);
+        }
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+        require!(subscription_plan.is_active_at(now), ErrorCode::PlanInactive);
+        require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
         require!(
-            clock.unix_timestamp >= subscription.next_payment,
-            ErrorCode::PaymentNotDue
+            !subscription_plan.in_maintenance(now),
+            ErrorCode::PlanUnderMaintenance
         );
-        
-        // Verify payment isn't too late (no more than 7 days past due)
-        let max_payment_time = subscription.next_payment
-            .checked_add(7 * 24 * 60 * 60) // 7 days
-            .ok_or(ErrorCode::Overflow)?;
+
+        // Oldest outstanding cycle must not be beyond the grace period
+        let max_payment_time = payment_deadline(subscription, subscription_plan, now)?;
         require!(
-            clock.unix_timestamp <= max_payment_time,
+            now <= max_payment_time,
             ErrorCode::PaymentTooLate
         );
 
-        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
-        require!(subscription_plan.is_active, ErrorCode::PlanInactive);
-        require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+        let cycles_charged = subscription.cycles_due(now, max_cycles)?;
+        require!(cycles_charged > 0, ErrorCode::PaymentNotDue);
+
+        let amount = renewal_charge(subscription, subscription_plan, cycles_charged, now)?;
+        emit_if_final_prorated(subscription, subscription_plan, cycles_charged, amount, now)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= amount,
+            ErrorCode::InsufficientFunds
+        );
 
-        // Transfer payment from subscriber to creator
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
             to: ctx.accounts.creator_token_account.to_account_info(),
             authority: ctx.accounts.subscriber.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        
-        token::transfer(cpi_ctx, subscription_plan.price)?;
 
-        // Update subscription with overflow checks
-        subscription.last_payment = clock.unix_timestamp;
-        subscription.next_payment = clock.unix_timestamp
-            .checked_add(subscription_plan.interval_seconds)
-            .ok_or(ErrorCode::Overflow)?;
-        subscription.total_payments = subscription.total_payments
-            .checked_add(1)
-            .ok_or(ErrorCode::Overflow)?;
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
 
-        emit!(PaymentProcessed {
+        settle_cycles(subscription, subscription_plan, cycles_charged, amount, now)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(CatchUpPaymentProcessed {
             subscriber: subscription.subscriber,
             creator: subscription.creator,
             plan_id,
-            amount: subscription_plan.price,
+            amount,
+            cycles_charged,
             payment_number: subscription.total_payments,
-            timestamp: clock.unix_timestamp,
+            total_paid_amount: subscription.total_paid_amount,
+            reference: subscription.reference,
+            timestamp: now,
         });
 
         Ok(())
@@ -184,6 +612,12 @@ pub mod circulum {
 
     /// Cancel an active subscription
     /// 
+    /// Fails with `MinimumDurationNotMet` until the plan's
+    /// `min_subscription_seconds` have passed since subscribing; `self_refund`
+    /// stays available within the refund window regardless. On plans with
+    /// `block_same_tx_cancel`, fails with `SameTransactionCancel` when the
+    /// same transaction started the subscription.
+    /// 
     /// # Security
     /// - Only subscriber can cancel their own subscription
     /// - Safely decrements subscriber count
@@ -193,22 +627,29 @@ pub mod circulum {
     ) -> Result<()> {
         let subscription = &mut ctx.accounts.subscription;
         let subscription_plan = &mut ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let now = current_time()?;
 
         require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(
+            subscription.min_duration_met(subscription_plan.min_subscription_seconds, now),
+            ErrorCode::MinimumDurationNotMet
+        );
+        check_not_joined_in_tx(
+            subscription_plan,
+            &subscription.key(),
+            &ctx.accounts.instructions_sysvar,
+        )?;
 
-        subscription.is_active = false;
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
         
         // Safely decrement subscriber count
-        subscription_plan.current_subscribers = subscription_plan.current_subscribers
-            .checked_sub(1)
-            .ok_or(ErrorCode::Underflow)?;
+        free_seat(subscription_plan, subscription)?;
 
         emit!(SubscriptionCancelled {
             subscriber: subscription.subscriber,
             creator: subscription.creator,
             plan_id: subscription.plan_id,
-            timestamp: clock.unix_timestamp,
+            timestamp: now,
         });
 
         Ok(())
@@ -219,6 +660,7 @@ pub mod circulum {
     /// # Security
     /// - Only subscriber can close their own subscription
     /// - Subscription must be inactive
+    /// - Any prepaid balance must be refunded first (`cancel_and_refund_prepaid`)
     /// - Rent returned to subscriber
     pub fn close_subscription(
         ctx: Context<CloseSubscription>,
@@ -227,16 +669,42 @@ pub mod circulum {
         let subscription = &ctx.accounts.subscription;
 
         require!(!subscription.is_active, ErrorCode::SubscriptionStillActive);
+        require!(subscription.reserved_until == 0, ErrorCode::ReservationPending);
+        require!(subscription.prepaid_balance == 0, ErrorCode::PrepaidBalanceRemaining);
+        require!(subscription.pledged_amount == 0, ErrorCode::PledgePending);
 
         // Account will be closed automatically due to close constraint
         Ok(())
     }
 
-    /// Update subscription plan parameters (creator only)
+    /// Update subscription plan parameters (creator or manager)
     /// 
     /// # Note
     /// Price changes affect ALL subscribers including existing ones.
     /// Consider implementing versioning for production use.
+    /// 
+    /// `new_early_tolerance` lets crankers settle a payment up to that many
+    /// seconds before it is due (at most a quarter of the interval).
+    /// `new_metadata_hash` should accompany metadata changes; all zeros clears it.
+    /// 
+    /// May be signed by the plan's manager, except for the financial terms
+    /// (`new_price`, `new_interval`), which fail with `Unauthorized` unless
+    /// signed by the creator.
+    /// 
+    /// # Security
+    /// Shortening `interval_seconds` is rejected with `IntervalChangeNotAllowed`
+    /// unless the creator has opted in via `set_allow_interval_tightening`.
+    /// Lengthening the interval is always allowed. Either way, existing
+    /// subscriptions keep the interval they joined at until migrated with
+    /// `migrate_subscription_interval`. `new_interval` only applies to plans
+    /// billed in seconds; day and month plans change theirs with
+    /// `set_interval_unit` (`IntervalSetByUnit`). Settings bounded by the
+    /// interval (tolerance, minimum duration, reminder lead, jitter) must
+    /// still fit the new one.
+    /// 
+    /// Changing `max_subscribers` within `capacity_change_cooldown_seconds`
+    /// of the previous change fails with `CapacityChangeTooSoon`.
+    #[allow(clippy::too_many_arguments)]
     pub fn update_subscription_plan(
         ctx: Context<UpdateSubscriptionPlan>,
         plan_id: u64,
@@ -244,16 +712,43 @@ pub mod circulum {
         new_interval: Option<i64>,
         new_max_subscribers: Option<u32>,
         new_metadata_uri: Option<String>,
+        new_early_tolerance: Option<i64>,
+        new_metadata_hash: Option<[u8; 32]>,
     ) -> Result<()> {
         let subscription_plan = &mut ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let now = current_time()?;
+
+        let old_price = subscription_plan.price;
+        let old_interval_seconds = subscription_plan.interval_seconds;
+        let old_max_subscribers = subscription_plan.max_subscribers;
+
+        if new_price.is_some() || new_interval.is_some() {
+            require_keys_eq!(
+                ctx.accounts.authority.key(),
+                subscription_plan.creator,
+                ErrorCode::Unauthorized
+            );
+        }
 
         if let Some(price) = new_price {
             require!(price > 0, ErrorCode::InvalidPrice);
+            check_min_price(
+                price,
+                ProgramConfig::load_or_default(&ctx.accounts.program_config)?.min_price,
+            )?;
             subscription_plan.price = price;
         }
         if let Some(interval) = new_interval {
+            require!(
+                subscription_plan.interval_unit == IntervalUnit::Seconds,
+                ErrorCode::IntervalSetByUnit
+            );
             require!(interval >= 60, ErrorCode::IntervalTooShort);
+            require!(
+                interval >= subscription_plan.interval_seconds
+                    || subscription_plan.allow_interval_tightening,
+                ErrorCode::IntervalChangeNotAllowed
+            );
             subscription_plan.interval_seconds = interval;
         }
         if let Some(max_subs) = new_max_subscribers {
@@ -262,23 +757,43 @@ pub mod circulum {
                 max_subs >= subscription_plan.current_subscribers,
                 ErrorCode::MaxSubscribersTooLow
             );
+            if max_subs != subscription_plan.max_subscribers {
+                subscription_plan.check_capacity_change(now)?;
+                subscription_plan.last_capacity_change = now;
+            }
             subscription_plan.max_subscribers = max_subs;
         }
         if let Some(metadata) = new_metadata_uri {
             require!(metadata.len() <= 200, ErrorCode::MetadataUriTooLong);
             subscription_plan.metadata_uri = metadata;
         }
+        if let Some(hash) = new_metadata_hash {
+            subscription_plan.metadata_hash = hash;
+        }
+        if let Some(tolerance) = new_early_tolerance {
+            subscription_plan.early_tolerance_seconds = tolerance;
+        }
+        // Re-validate against the (possibly updated) interval
+        subscription_plan.check_interval_bounds()?;
 
         emit!(SubscriptionPlanUpdated {
             creator: subscription_plan.creator,
             plan_id,
-            timestamp: clock.unix_timestamp,
+            metadata_hash: subscription_plan.metadata_hash,
+            old_price,
+            new_price: subscription_plan.price,
+            old_interval_seconds,
+            new_interval_seconds: subscription_plan.interval_seconds,
+            old_max_subscribers,
+            new_max_subscribers: subscription_plan.max_subscribers,
+            timestamp: now,
+            event_seq: subscription_plan.next_event_seq()?,
         });
 
         Ok(())
     }
 
-    /// Pause a subscription plan (creator only)
+    /// Pause a subscription plan (creator or manager)
     /// 
     /// # Effect
     /// - No new subscriptions can be created
@@ -289,7 +804,7 @@ pub mod circulum {
         _plan_id: u64,
     ) -> Result<()> {
         let subscription_plan = &mut ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let now = current_time()?;
         
         require!(!subscription_plan.is_paused, ErrorCode::PlanAlreadyPaused);
         subscription_plan.is_paused = true;
@@ -297,19 +812,20 @@ pub mod circulum {
         emit!(SubscriptionPlanPaused {
             creator: subscription_plan.creator,
             plan_id: subscription_plan.plan_id,
-            timestamp: clock.unix_timestamp,
+            timestamp: now,
+            event_seq: subscription_plan.next_event_seq()?,
         });
 
         Ok(())
     }
 
-    /// Unpause a subscription plan (creator only)
+    /// Unpause a subscription plan (creator or manager)
     pub fn unpause_plan(
         ctx: Context<UnpausePlan>,
         _plan_id: u64,
     ) -> Result<()> {
         let subscription_plan = &mut ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let now = current_time()?;
         
         require!(subscription_plan.is_paused, ErrorCode::PlanNotPaused);
         subscription_plan.is_paused = false;
@@ -317,7 +833,8 @@ pub mod circulum {
         emit!(SubscriptionPlanUnpaused {
             creator: subscription_plan.creator,
             plan_id: subscription_plan.plan_id,
-            timestamp: clock.unix_timestamp,
+            timestamp: now,
+            event_seq: subscription_plan.next_event_seq()?,
         });
 
         Ok(())
@@ -328,391 +845,11105 @@ pub mod circulum {
     /// # Effect
     /// - Plan cannot accept new subscriptions
     /// - Existing subscriptions can still be cancelled
-    /// - Cannot be reactivated
+    /// - Cannot be reactivated by the creator; only the program admin can
+    ///   undo a deactivation, with `reactivate_plan`
+    /// - If `successor_plan_id` is set, subscribers may move to that plan of
+    ///   the same creator with `follow_successor`
+    /// 
+    /// # Notice
+    /// With `deactivation_notice_seconds` set, the deactivation is only
+    /// scheduled: `DeactivationScheduled` is emitted and the plan keeps
+    /// accepting subscribers and payments until `deactivation_effective_at`.
+    /// The creator can call it off before then with `cancel_deactivation`.
     pub fn deactivate_plan(
         ctx: Context<DeactivatePlan>,
-        _plan_id: u64,
+        plan_id: u64,
+        successor_plan_id: Option<u64>,
     ) -> Result<()> {
         let subscription_plan = &mut ctx.accounts.subscription_plan;
-        let clock = Clock::get()?;
+        let now = current_time()?;
         
-        require!(subscription_plan.is_active, ErrorCode::PlanAlreadyInactive);
+        require!(subscription_plan.is_active_at(now), ErrorCode::PlanAlreadyInactive);
+        require!(
+            subscription_plan.deactivation_effective_at == 0,
+            ErrorCode::DeactivationAlreadyScheduled
+        );
+        require!(successor_plan_id != Some(plan_id), ErrorCode::InvalidSuccessorPlan);
+        subscription_plan.successor_plan_id = successor_plan_id;
+
+        if subscription_plan.deactivation_notice_seconds > 0 {
+            let effective_at = now
+                .checked_add(subscription_plan.deactivation_notice_seconds)
+                .ok_or(ErrorCode::Overflow)?;
+            subscription_plan.deactivation_effective_at = effective_at;
+            emit!(DeactivationScheduled::for_plan(subscription_plan, effective_at, now)?);
+            return Ok(());
+        }
         subscription_plan.is_active = false;
 
         emit!(SubscriptionPlanDeactivated {
             creator: subscription_plan.creator,
             plan_id: subscription_plan.plan_id,
-            timestamp: clock.unix_timestamp,
+            timestamp: now,
+            current_subscribers: subscription_plan.current_subscribers,
+            event_seq: subscription_plan.next_event_seq()?,
         });
 
         Ok(())
     }
-}
 
-// ============================================================================
-// Account Structures
-// ============================================================================
+    /// Schedule a maintenance window for a plan (creator only)
+    /// 
+    /// # Effect
+    /// - Payments cannot be processed between `start` and `end`
+    /// - Subscribers whose billing overlapped the window have their next
+    ///   due date shifted by the window length on their next charge
+    /// - Replaces any previously scheduled window that has not started
+    /// - At most `MAX_MAINTENANCE_SECONDS` long
+    pub fn schedule_maintenance(
+        ctx: Context<ScheduleMaintenance>,
+        _plan_id: u64,
+        start: i64,
+        end: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct CreateSubscriptionPlan<'info> {
-    #[account(
-        init,
-        payer = creator,
-        space = SubscriptionPlan::LEN,
-        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
-        bump
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        require!(start < end, ErrorCode::InvalidMaintenanceWindow);
+        require!(start > now, ErrorCode::MaintenanceWindowInPast);
+        require!(
+            end - start <= MAX_MAINTENANCE_SECONDS,
+            ErrorCode::MaintenanceWindowTooLong
+        );
+        require!(
+            !subscription_plan.in_maintenance(now),
+            ErrorCode::PlanUnderMaintenance
+        );
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct Subscribe<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    #[account(
-        init,
-        payer = subscriber,
-        space = Subscription::LEN,
-        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
-        bump
-    )]
-    pub subscription: Account<'info, Subscription>,
-    #[account(mut)]
-    pub subscriber: Signer<'info>,
-    #[account(
-        mut,
-        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
-        constraint = subscriber_token_account.mint == creator_token_account.mint @ ErrorCode::MintMismatch,
-    )]
-    pub subscriber_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = creator_token_account.owner == subscription_plan.creator @ ErrorCode::InvalidTokenAccountOwner,
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+        subscription_plan.maintenance_start = start;
+        subscription_plan.maintenance_end = end;
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct ProcessPayment<'info> {
-    #[account(
-        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    #[account(
-        mut,
-        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription.bump,
-        constraint = subscription.plan_id == plan_id @ ErrorCode::InvalidPlanId,
-        constraint = subscription.subscriber == subscriber.key() @ ErrorCode::InvalidSubscriber,
-    )]
-    pub subscription: Account<'info, Subscription>,
-    #[account(mut)]
-    pub subscriber: Signer<'info>,
-    #[account(
-        mut,
-        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
-        constraint = subscriber_token_account.mint == creator_token_account.mint @ ErrorCode::MintMismatch,
-    )]
-    pub subscriber_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = creator_token_account.owner == subscription_plan.creator @ ErrorCode::InvalidTokenAccountOwner,
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-}
+        emit!(MaintenanceScheduled {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            start,
+            end,
+            timestamp: now,
+        });
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct CancelSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    #[account(
-        mut,
-        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription.bump,
-        has_one = subscriber @ ErrorCode::InvalidSubscriber,
-    )]
-    pub subscription: Account<'info, Subscription>,
-    pub subscriber: Signer<'info>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct CloseSubscription<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription.bump,
-        has_one = subscriber @ ErrorCode::InvalidSubscriber,
-        close = subscriber
-    )]
-    pub subscription: Account<'info, Subscription>,
-    #[account(mut)]
-    pub subscriber: Signer<'info>,
-}
+    /// Rotate the token account that receives plan payments (creator only)
+    /// 
+    /// # Security
+    /// - New account must be owned by the creator
+    /// - New account mint must match the plan's `payment_mint`
+    pub fn set_payout_account(
+        ctx: Context<SetPayoutAccount>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct UpdateSubscriptionPlan<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump,
-        has_one = creator @ ErrorCode::InvalidCreator,
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    pub creator: Signer<'info>,
+        let old_account = subscription_plan.payout_token_account;
+        let new_account = ctx.accounts.new_payout_token_account.key();
+        subscription_plan.payout_token_account = new_account;
+
+        emit!(PayoutAccountChanged {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            old_account,
+            new_account,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Set the self-service refund window for new subscriptions (creator only)
+    /// 
+    /// # Note
+    /// Refunds are paid from the payout account, so the creator must approve
+    /// the plan PDA as an SPL delegate on it for `self_refund` to succeed.
+    pub fn set_refund_window(
+        ctx: Context<SetRefundWindow>,
+        _plan_id: u64,
+        refund_window_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            refund_window_seconds >= 0
+                && refund_window_seconds <= subscription_plan.interval_seconds,
+            ErrorCode::InvalidRefundWindow
+        );
+        subscription_plan.refund_window_seconds = refund_window_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Refund the initial payment and cancel, within the plan's refund window
+    /// 
+    /// Returns what the subscription actually paid to join (`total_paid_amount`,
+    /// setup fee included), not the plan's current price.
+    /// 
+    /// # Security
+    /// - Only the subscriber can request their own refund
+    /// - The subscription must belong to the plan's creator
+    /// - Only available before any renewal and within `refund_window_seconds`
+    ///   of subscribing
+    /// - Funds move from the payout account with the plan PDA as delegate
+    /// - On `block_same_tx_cancel` plans, not in the subscribing transaction
+    pub fn self_refund(
+        ctx: Context<SelfRefund>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+        check_not_joined_in_tx(
+            subscription_plan,
+            &subscription.key(),
+            &ctx.accounts.instructions_sysvar,
+        )?;
+
+        // last_payment is the subscribe time until the first renewal
+        let refund_deadline = subscription.last_payment
+            .checked_add(subscription_plan.refund_window_seconds)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(
+            subscription.total_payments == subscription.cycles_paid_on_join(subscription_plan) as u64
+                && now <= refund_deadline,
+            ErrorCode::RefundWindowExpired
+        );
+
+        let amount = subscription.total_paid_amount;
+        let creator_key = subscription_plan.creator;
+        let plan_id_bytes = plan_id.to_le_bytes();
+        let seeds = &[
+            b"subscription_plan".as_ref(),
+            creator_key.as_ref(),
+            plan_id_bytes.as_ref(),
+            &[subscription_plan.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.creator_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.subscriber_token_account.to_account_info(),
+            authority: subscription_plan.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+        subscription.total_paid_amount = 0;
+        // The setup fee was refunded with the rest, so a rejoin owes it again
+        subscription.setup_fee_paid = false;
+        free_seat(subscription_plan, subscription)?;
+
+        emit!(SelfRefundIssued {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Set the subscription's invoice/PO reference (subscriber only)
+    pub fn set_reference(
+        ctx: Context<SetReference>,
+        _plan_id: u64,
+        reference: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.subscription.reference = reference;
+        Ok(())
+    }
+
+    /// Only bill on business days (creator only)
+    /// 
+    /// When enabled, a due date that falls on a Saturday or Sunday (UTC) is
+    /// pushed to the following Monday at the same time of day. Later cycles
+    /// are anchored to the pushed date. Can't be enabled alongside a
+    /// billing anchor day.
+    pub fn set_bill_on_weekday_only(
+        ctx: Context<SetBillOnWeekdayOnly>,
+        _plan_id: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            !enabled || subscription_plan.billing_anchor_day == 0,
+            ErrorCode::InvalidBillingAnchorDay
+        );
+        subscription_plan.bill_on_weekday_only = enabled;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Succeed only if the subscription is currently paid up (read-only)
+    /// 
+    /// Intended as a CPI target for programs gating access on an active
+    /// subscription. Callers pass, in order:
+    /// 1. `subscription_plan` - the plan PDA `["subscription_plan", creator, plan_id]`
+    /// 2. `subscription` - the subscription PDA `["subscription", subscriber, plan_id]`
+    /// 3. `subscriber` - the wallet being checked (need not sign)
+    /// 
+    /// Fails with `SubscriptionInactive` if cancelled/ended and
+    /// `SubscriptionLapsed` once past `next_payment` plus grace.
+    /// 
+    /// Programs that can't CPI can make the same check by reading the raw
+    /// subscription account with `is_active_subscriber`.
+    pub fn assert_active_subscriber(
+        ctx: Context<AssertActiveSubscriber>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            subscription.is_active && !subscription.has_ended(now),
+            ErrorCode::SubscriptionInactive
+        );
+        require!(
+            now <= payment_deadline(subscription, subscription_plan, now)?,
+            ErrorCode::SubscriptionLapsed
+        );
+
+        Ok(())
+    }
+
+    /// Set how many cycles new subscribers pay upfront at `subscribe` (creator only)
+    /// 
+    /// Upfront cycles are transferred directly to the creator; the first
+    /// recurring charge is due `interval_seconds * cycles` after joining.
+    pub fn set_prepay_cycles_on_join(
+        ctx: Context<SetPrepayCyclesOnJoin>,
+        _plan_id: u64,
+        cycles: u32,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            cycles > 0 && cycles <= MAX_PREPAY_CYCLES_ON_JOIN,
+            ErrorCode::InvalidPrepayCycles
+        );
+        subscription_plan.prepay_cycles_on_join = cycles;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Set how long a reserved seat is held before confirmation (creator only)
+    /// 
+    /// 0 disables reservations; otherwise between one minute and seven days.
+    pub fn set_reservation_window(
+        ctx: Context<SetReservationWindow>,
+        _plan_id: u64,
+        reservation_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            reservation_seconds == 0
+                || (MIN_RESERVATION_SECONDS..=MAX_RESERVATION_SECONDS).contains(&reservation_seconds),
+            ErrorCode::InvalidReservationWindow
+        );
+        subscription_plan.reservation_seconds = reservation_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Reserve a seat on a plan without paying yet
+    /// 
+    /// Creates an inactive subscription that holds a seat until
+    /// `reserved_until`. The subscriber completes it with
+    /// `confirm_subscription`; after expiry anyone may `release_reservation`.
+    pub fn subscribe_reserve(
+        ctx: Context<SubscribeReserve>,
+        plan_id: u64,
+        reference: [u8; 32],
+        ends_at: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let subscriber = &ctx.accounts.subscriber;
+        let now = current_time()?;
+
+        require!(
+            subscription_plan.reservation_seconds > 0,
+            ErrorCode::ReservationsDisabled
+        );
+        check_can_join(subscription_plan, now)?;
+
+        let reserved_until = now
+            .checked_add(subscription_plan.reservation_seconds)
+            .ok_or(ErrorCode::Overflow)?;
+
+        subscription.subscriber = subscriber.key();
+        subscription.schema_version = CURRENT_SCHEMA_VERSION;
+        subscription.created_at = now;
+        subscription.plan_id = plan_id;
+        subscription.creator = subscription_plan.creator;
+        subscription.set_status(SubscriptionStatus::Pending);
+        subscription.bump = ctx.bumps.subscription;
+        subscription.maintenance_credited_until = 0;
+        subscription.reference = reference;
+        subscription.ends_at = ends_at;
+        subscription.reserved_until = reserved_until;
+
+        // The reservation holds a seat until confirmed or released
+        take_seat(subscription_plan, now)?;
+
+        emit!(SeatReserved {
+            subscriber: subscriber.key(),
+            creator: subscription_plan.creator,
+            plan_id,
+            reserved_until,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Pay for a reserved seat and activate the subscription
+    pub fn confirm_subscription(
+        ctx: Context<ConfirmSubscription>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.reserved_until != 0, ErrorCode::NoReservation);
+        require!(
+            now <= subscription.reserved_until,
+            ErrorCode::ReservationExpired
+        );
+        require!(subscription_plan.is_active_at(now), ErrorCode::PlanInactive);
+        require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+
+        let (prepaid_cycles, cycles_amount) =
+            initial_charge(subscription_plan, subscription_plan.price)?;
+        let setup_fee = setup_fee_due(subscription, subscription_plan);
+        let initial_amount = cycles_amount
+            .checked_add(setup_fee)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= initial_amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, initial_amount, ctx.accounts.payment_mint.decimals)?;
+
+        subscription.reserved_until = 0;
+        activate_subscription(
+            subscription,
+            subscription_plan,
+            prepaid_cycles,
+            initial_amount,
+            now,
+        )?;
+        subscription.setup_fee_paid |= setup_fee > 0;
+        // The setup fee isn't part of what a cycle costs
+        subscription.record_cycle_amount(cycles_amount, prepaid_cycles);
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(SubscriptionCreated {
+            subscriber: subscription.subscriber,
+            creator: subscription_plan.creator,
+            plan_id,
+            prepaid_cycles,
+            timestamp: now,
+            setup_fee,
+            created_at: subscription.created_at,
+            payment_mint: subscription.billing_mint(subscription_plan),
+            chosen_price: subscription.chosen_price,
+        });
+
+        Ok(())
+    }
+
+    /// Free the seat held by an expired, unconfirmed reservation
+    /// 
+    /// Callable by anyone once `reserved_until` has passed. The reservation
+    /// account is closed and its rent returned to the subscriber.
+    pub fn release_reservation(
+        ctx: Context<ReleaseReservation>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.reserved_until != 0, ErrorCode::NoReservation);
+        require!(
+            now > subscription.reserved_until,
+            ErrorCode::ReservationNotExpired
+        );
+
+        free_seat(subscription_plan, subscription)?;
+
+        emit!(ReservationReleased {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id: subscription.plan_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Initialize the global program configuration
+    /// 
+    /// # Security
+    /// - Only the program's upgrade authority can initialize the config
+    pub fn initialize_program_config(
+        ctx: Context<InitializeProgramConfig>,
+        admin: Pubkey,
+    ) -> Result<()> {
+        let program_config = &mut ctx.accounts.program_config;
+
+        program_config.admin = admin;
+        program_config.bump = ctx.bumps.program_config;
+        program_config.min_price = 0;
+        program_config.plan_creation_fee = 0;
+        program_config.fee_destination = admin;
+
+        Ok(())
+    }
+
+    /// Create the protocol treasury token account for a mint (admin only)
+    /// 
+    /// The treasury is a PDA-owned token account (`["treasury", mint]`) that
+    /// accumulates platform fees until swept by the admin.
+    pub fn initialize_treasury(
+        _ctx: Context<InitializeTreasury>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Move collected platform fees out of the treasury (admin only)
+    /// 
+    /// # Security
+    /// - Only `ProgramConfig.admin` can sweep
+    /// - Amount cannot exceed the treasury balance
+    /// - Transfer is signed by the treasury PDA
+    pub fn sweep_treasury(
+        ctx: Context<SweepTreasury>,
+        amount: u64,
+    ) -> Result<()> {
+        let treasury = &ctx.accounts.treasury;
+        let now = current_time()?;
+
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(amount <= treasury.amount, ErrorCode::InsufficientTreasuryBalance);
+
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"treasury".as_ref(),
+            mint_key.as_ref(),
+            &[ctx.bumps.treasury],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.treasury.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.treasury.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        emit!(TreasurySwept {
+            admin: ctx.accounts.admin.key(),
+            mint: mint_key,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Allow `update_subscription_plan` to shorten the billing interval (creator only)
+    /// 
+    /// Off by default. Enabling it means existing subscribers may be charged
+    /// more often than when they joined.
+    pub fn set_allow_interval_tightening(
+        ctx: Context<SetAllowIntervalTightening>,
+        _plan_id: u64,
+        allowed: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.allow_interval_tightening = allowed;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Move a subscription onto the plan's current interval (creator only)
+    /// 
+    /// Subscriptions keep the interval they joined at; this opts one into a
+    /// later plan interval change. The new interval applies from the next
+    /// renewal, the already scheduled `next_payment` is left unchanged.
+    /// 
+    /// # Security
+    /// Moving to a shorter interval requires `allow_interval_tightening`.
+    pub fn migrate_subscription_interval(
+        ctx: Context<MigrateSubscriptionInterval>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        let old_interval_seconds = subscription.interval_seconds;
+        require!(
+            subscription_plan.interval_seconds >= old_interval_seconds
+                || subscription_plan.allow_interval_tightening,
+            ErrorCode::IntervalChangeNotAllowed
+        );
+        subscription.interval_seconds = subscription_plan.interval_seconds;
+        subscription.interval_unit = subscription_plan.interval_unit;
+        subscription.interval_count = subscription_plan.interval_count;
+
+        emit!(SubscriptionIntervalMigrated {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            old_interval_seconds,
+            new_interval_seconds: subscription.interval_seconds,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Move from a deactivated plan to its successor in one transaction
+    /// 
+    /// Cancels the subscription to `plan_id` and subscribes to the plan's
+    /// `successor_plan_id`, charging per the successor's rules (price,
+    /// upfront cycles, setup fee, capacity). The invoice reference carries over. The
+    /// old plan's `min_subscription_seconds` and `block_same_tx_cancel`
+    /// apply as in `cancel_subscription`.
+    /// 
+    /// # Security
+    /// - Only the subscriber can move their own subscription
+    /// - The successor must belong to the same creator and be joinable
+    pub fn follow_successor(
+        ctx: Context<FollowSuccessor>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let successor_plan = &mut ctx.accounts.successor_plan;
+        let new_subscription = &mut ctx.accounts.new_subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+        require!(
+            subscription.min_duration_met(subscription_plan.min_subscription_seconds, now),
+            ErrorCode::MinimumDurationNotMet
+        );
+        check_not_joined_in_tx(
+            subscription_plan,
+            &subscription.key(),
+            &ctx.accounts.instructions_sysvar,
+        )?;
+        check_can_join(successor_plan, now)?;
+        check_memo(successor_plan, &ctx.accounts.instructions_sysvar)?;
+
+        let (prepaid_cycles, cycles_amount) = initial_charge(successor_plan, successor_plan.price)?;
+        let setup_fee = setup_fee_due(new_subscription, successor_plan);
+        let initial_amount = cycles_amount
+            .checked_add(setup_fee)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= initial_amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, initial_amount, ctx.accounts.payment_mint.decimals)?;
+
+        // Wind down the old subscription
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+        free_seat(subscription_plan, subscription)?;
+
+        new_subscription.subscriber = subscription.subscriber;
+        new_subscription.schema_version = CURRENT_SCHEMA_VERSION;
+        new_subscription.created_at = now;
+        new_subscription.plan_id = successor_plan.plan_id;
+        new_subscription.creator = successor_plan.creator;
+        new_subscription.bump = ctx.bumps.new_subscription;
+        new_subscription.maintenance_credited_until = 0;
+        new_subscription.reference = subscription.reference;
+        new_subscription.ends_at = 0;
+        new_subscription.reserved_until = 0;
+        activate_subscription(
+            new_subscription,
+            successor_plan,
+            prepaid_cycles,
+            initial_amount,
+            now,
+        )?;
+        new_subscription.setup_fee_paid |= setup_fee > 0;
+        // The setup fee isn't part of what a cycle costs
+        new_subscription.record_cycle_amount(cycles_amount, prepaid_cycles);
+        emit_payment_upcoming(new_subscription, successor_plan, now)?;
+
+        take_seat(successor_plan, now)?;
+
+        emit!(FollowedSuccessor {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            old_plan_id: plan_id,
+            new_plan_id: successor_plan.plan_id,
+            amount: initial_amount,
+            prepaid_cycles,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Create the plan's prepaid vault (creator only)
+    /// 
+    /// The vault is a token account PDA (`["prepaid_vault", plan]`) that is
+    /// its own authority. It pools subscriber prepayments; each subscription
+    /// tracks its share in `prepaid_balance`.
+    pub fn initialize_prepaid_vault(
+        _ctx: Context<InitializePrepaidVault>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Prepay `cycles` billing cycles into the plan's vault
+    /// 
+    /// The vault holds the plan's `payment_mint`, so subscriptions billed in
+    /// another accepted token can't prepay (`PrepaidMintMismatch`).
+    /// 
+    /// # Security
+    /// - Only the subscriber can deposit for their subscription
+    /// - `prepaid_balance` may not exceed `price * max_prepaid_cycles`
+    pub fn deposit_to_vault(
+        ctx: Context<DepositToVault>,
+        plan_id: u64,
+        cycles: u32,
+    ) -> Result<()> {
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(cycles > 0, ErrorCode::InvalidPrepayCycles);
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+        let amount = subscription.cycle_price(subscription_plan.price)
+            .checked_mul(cycles as u64)
+            .ok_or(ErrorCode::Overflow)?;
+        subscription.prepaid_balance = prepaid_balance_after_deposit(
+            subscription_plan,
+            subscription.prepaid_balance,
+            amount,
+        )?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.prepaid_vault.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(PrepaidDeposited {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount,
+            prepaid_balance: subscription.prepaid_balance,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Process a due payment from the subscription's prepaid balance
+    /// 
+    /// Same schedule and due-window rules as `process_payment`, but the
+    /// cycle is paid from the prepaid vault (signed by the vault PDA) and
+    /// needs no subscriber signature, so anyone may crank it.
+    pub fn process_prepaid_payment(
+        ctx: Context<ProcessPrepaidPayment>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        if subscription.is_active && subscription.has_ended(now) {
+            return end_subscription(subscription, subscription_plan, now);
+        }
+        if subscription.is_active
+            && subscription.lifetime_reached(subscription_plan.max_lifetime_seconds, now)
+        {
+            return end_at_lifetime(subscription, subscription_plan, now);
+        }
+        check_payment_due(subscription, subscription_plan, now)?;
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+
+        let amount = renewal_charge(subscription, subscription_plan, 1, now)?;
+        emit_if_final_prorated(subscription, subscription_plan, 1, amount, now)?;
+        subscription.prepaid_balance = subscription.prepaid_balance
+            .checked_sub(amount)
+            .ok_or(ErrorCode::InsufficientPrepaidBalance)?;
+
+        let plan_key = subscription_plan.key();
+        let seeds = &[
+            b"prepaid_vault".as_ref(),
+            plan_key.as_ref(),
+            &[ctx.bumps.prepaid_vault],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.prepaid_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.prepaid_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        settle_cycle(subscription, subscription_plan, amount, now)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(PaymentProcessed {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount,
+            payment_number: subscription.total_payments,
+            total_paid_amount: subscription.total_paid_amount,
+            reference: subscription.reference,
+            timestamp: now,
+            payment_mint: subscription_plan.payment_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Set the most cycles a subscriber may hold prepaid (creator only)
+    /// 
+    /// Bounds the vault balance, and so the creator's refund liability, at
+    /// `price * max_prepaid_cycles` per subscription. Existing balances above
+    /// a lowered cap are kept but cannot be topped up.
+    pub fn set_max_prepaid_cycles(
+        ctx: Context<SetMaxPrepaidCycles>,
+        _plan_id: u64,
+        max_prepaid_cycles: u32,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(max_prepaid_cycles > 0, ErrorCode::InvalidPrepayCycles);
+        subscription_plan.max_prepaid_cycles = max_prepaid_cycles;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Cancel a subscription and return its unused prepaid balance
+    /// 
+    /// Cancels if still active, then transfers whatever `prepaid_balance`
+    /// remains (deposits less cycles already paid from the vault) to
+    /// `destination`, signed by the vault PDA. Also usable after a plain
+    /// `cancel_subscription` to recover a stranded balance. Cancelling is
+    /// subject to `min_subscription_seconds` and `block_same_tx_cancel` as
+    /// in `cancel_subscription`.
+    /// 
+    /// # Security
+    /// - Only the subscriber can cancel and receive the refund
+    /// - `destination` must be a token account of the plan mint owned by the
+    ///   subscriber; any such account works if the original one was closed
+    pub fn cancel_and_refund_prepaid(
+        ctx: Context<CancelAndRefundPrepaid>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let amount = subscription.prepaid_balance;
+        require!(
+            subscription.is_active || amount > 0,
+            ErrorCode::SubscriptionInactive
+        );
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+
+        if subscription.is_active {
+            require!(
+                subscription.min_duration_met(subscription_plan.min_subscription_seconds, now),
+                ErrorCode::MinimumDurationNotMet
+            );
+            check_not_joined_in_tx(
+                subscription_plan,
+                &subscription.key(),
+                &ctx.accounts.instructions_sysvar,
+            )?;
+            transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+            free_seat(subscription_plan, subscription)?;
+
+            emit!(SubscriptionCancelled {
+                subscriber: subscription.subscriber,
+                creator: subscription.creator,
+                plan_id,
+                timestamp: now,
+            });
+        }
+
+        if amount > 0 {
+            let plan_key = subscription_plan.key();
+            let seeds = &[
+                b"prepaid_vault".as_ref(),
+                plan_key.as_ref(),
+                &[ctx.bumps.prepaid_vault],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.prepaid_vault.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.prepaid_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+            subscription.prepaid_balance = 0;
+
+            emit!(PrepaidBalanceRefunded {
+                subscriber: subscription.subscriber,
+                creator: subscription.creator,
+                plan_id,
+                amount,
+                destination: ctx.accounts.destination.key(),
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Recompute and persist a subscription's status (permissionless)
+    /// 
+    /// Moves live subscriptions between Active, Grace and Paused as time and
+    /// plan state change, and to Expired once past grace or the fixed-term
+    /// end, freeing the seat. Emits `SubscriptionStatusChanged` on change.
+    pub fn refresh_status(
+        ctx: Context<RefreshStatus>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let old_status = subscription.status;
+        let new_status = derive_status(subscription, subscription_plan, now)?;
+        if new_status == old_status {
+            return Ok(());
+        }
+
+        if new_status == SubscriptionStatus::Expired {
+            free_seat(subscription_plan, subscription)?;
+        }
+        transition_status(subscription, subscription_plan, new_status)?;
+
+        emit!(SubscriptionStatusChanged {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            old_status,
+            new_status,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Bill in whole days or calendar months (creator only)
+    /// 
+    /// `Days` sets `interval_seconds` to `count` days. `Months` bills on the
+    /// same day of the month (UTC) every `count` months, clamping to the last
+    /// day of shorter months; `interval_seconds` is then a nominal 30 days per
+    /// month used only to bound tolerances and windows. `Seconds` keeps the
+    /// current `interval_seconds`.
+    /// 
+    /// Like `update_subscription_plan`, applies to new subscriptions and
+    /// shortening requires `allow_interval_tightening`; settings bounded by
+    /// the interval must still fit the new one. Leaving `Months` requires
+    /// clearing `billing_anchor_day` first.
+    pub fn set_interval_unit(
+        ctx: Context<SetIntervalUnit>,
+        _plan_id: u64,
+        unit: IntervalUnit,
+        count: u32,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let interval_seconds = match unit {
+            IntervalUnit::Seconds => subscription_plan.interval_seconds,
+            IntervalUnit::Days | IntervalUnit::Months => {
+                require!(count > 0 && count <= MAX_INTERVAL_COUNT, ErrorCode::InvalidIntervalCount);
+                let days = if unit == IntervalUnit::Months { count as i64 * 30 } else { count as i64 };
+                days * SECONDS_PER_DAY
+            }
+        };
+        require!(
+            interval_seconds >= subscription_plan.interval_seconds
+                || subscription_plan.allow_interval_tightening,
+            ErrorCode::IntervalChangeNotAllowed
+        );
+        require!(
+            unit == IntervalUnit::Months || subscription_plan.billing_anchor_day == 0,
+            ErrorCode::InvalidBillingAnchorDay
+        );
+
+        subscription_plan.interval_unit = unit;
+        subscription_plan.interval_count = if unit == IntervalUnit::Seconds { 0 } else { count };
+        subscription_plan.interval_seconds = interval_seconds;
+        subscription_plan.check_interval_bounds()?;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Cancel and refund the unused part of the paid period
+    /// 
+    /// Only on plans with `prorated_cancel_refund` enabled. Refunds
+    /// `paid * (next_payment - now) / interval`, where `paid` is what the
+    /// subscription was charged per cycle at its latest payment
+    /// (`cycle_amount_paid`, discounts included). The refund is zero once
+    /// payment is due and never more than the subscription has paid. It
+    /// comes from the payout account for the subscription's `billing_mint`
+    /// with the plan PDA as delegate, then the subscription is cancelled as
+    /// in `cancel_subscription`, including its `MinimumDurationNotMet` and
+    /// `SameTransactionCancel` checks.
+    pub fn cancel_with_prorated_refund(
+        ctx: Context<CancelWithProratedRefund>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+        require!(subscription_plan.prorated_cancel_refund, ErrorCode::ProratedRefundDisabled);
+        require!(
+            subscription.min_duration_met(subscription_plan.min_subscription_seconds, now),
+            ErrorCode::MinimumDurationNotMet
+        );
+        check_not_joined_in_tx(
+            subscription_plan,
+            &subscription.key(),
+            &ctx.accounts.instructions_sysvar,
+        )?;
+
+        // Subscriptions charged before `cycle_amount_paid` was recorded fall
+        // back to what their next cycle would cost
+        let paid_per_cycle = if subscription.cycle_amount_paid > 0 {
+            subscription.cycle_amount_paid
+        } else {
+            effective_price(subscription, subscription_plan, now)?
+        };
+        let amount = prorated_refund(
+            paid_per_cycle,
+            subscription.next_payment,
+            now,
+            subscription.interval_seconds,
+        )?
+            .min(subscription.total_paid_amount);
+
+        if amount > 0 {
+            let creator_key = subscription_plan.creator;
+            let plan_id_bytes = plan_id.to_le_bytes();
+            let seeds = &[
+                b"subscription_plan".as_ref(),
+                creator_key.as_ref(),
+                plan_id_bytes.as_ref(),
+                &[subscription_plan.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.creator_token_account.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: ctx.accounts.subscriber_token_account.to_account_info(),
+                authority: subscription_plan.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+            token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+            subscription.total_paid_amount = subscription.total_paid_amount
+                .checked_sub(amount)
+                .ok_or(ErrorCode::Underflow)?;
+
+            emit!(ProratedRefundIssued {
+                subscriber: subscription.subscriber,
+                creator: subscription.creator,
+                plan_id,
+                amount,
+                timestamp: now,
+            });
+        }
+
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+        free_seat(subscription_plan, subscription)?;
+
+        emit!(SubscriptionCancelled {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Enable or disable prorated refunds on cancel (creator only)
+    /// 
+    /// # Note
+    /// Refunds are paid from the payout account, so the creator must approve
+    /// the plan PDA as an SPL delegate on it.
+    pub fn set_prorated_cancel_refund(
+        ctx: Context<SetProratedCancelRefund>,
+        _plan_id: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.prorated_cancel_refund = enabled;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Cancel a batch of the plan's subscriptions (creator only)
+    /// 
+    /// Pass up to `MAX_BULK_CANCEL` writable subscription accounts of this
+    /// plan as `remaining_accounts`. Live subscriptions are cancelled and the
+    /// seat count decremented, emitting `SubscriberRemoved` for each; already
+    /// inactive ones are skipped, so batches can safely be retried. Call
+    /// repeatedly to wind down a whole plan.
+    /// 
+    /// # Security
+    /// Every account must be the subscription PDA of this plan, owned by the
+    /// program, or the whole batch fails.
+    pub fn cancel_all_subscribers<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelAllSubscribers<'info>>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BULK_CANCEL,
+            ErrorCode::TooManyAccounts
+        );
+
+        for account_info in ctx.remaining_accounts.iter() {
+            let mut subscription = Account::<Subscription>::try_from(account_info)?;
+            let expected = Pubkey::create_program_address(
+                &[
+                    b"subscription",
+                    subscription.subscriber.as_ref(),
+                    &plan_id.to_le_bytes(),
+                    &[subscription.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidSubscriptionAccount))?;
+            require_keys_eq!(account_info.key(), expected, ErrorCode::InvalidSubscriptionAccount);
+            require_keys_eq!(
+                subscription.creator,
+                subscription_plan.creator,
+                ErrorCode::InvalidSubscriptionAccount
+            );
+
+            if !subscription.status.is_live() {
+                continue;
+            }
+
+            transition_status(&mut subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+            free_seat(subscription_plan, &subscription)?;
+            subscription.exit(&crate::ID)?;
+
+            emit!(SubscriberRemoved {
+                subscriber: subscription.subscriber,
+                creator: subscription_plan.creator,
+                plan_id,
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Lengthen the outage window of a paused plan (creator only)
+    /// 
+    /// Extends the current or upcoming maintenance window by
+    /// `additional_seconds`, or opens one starting now if none is pending, so
+    /// the due-date shift subscribers receive covers the whole outage without
+    /// an unpause/re-pause cycle. The window may not grow beyond
+    /// `MAX_MAINTENANCE_SECONDS`.
+    pub fn extend_pause(
+        ctx: Context<ExtendPause>,
+        _plan_id: u64,
+        additional_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(subscription_plan.is_paused, ErrorCode::PlanNotPaused);
+        require!(additional_seconds > 0, ErrorCode::InvalidMaintenanceWindow);
+
+        if subscription_plan.maintenance_end <= now {
+            subscription_plan.maintenance_start = now;
+            subscription_plan.maintenance_end = now;
+        }
+        subscription_plan.maintenance_end = subscription_plan.maintenance_end
+            .checked_add(additional_seconds)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(
+            subscription_plan.maintenance_end - subscription_plan.maintenance_start
+                <= MAX_MAINTENANCE_SECONDS,
+            ErrorCode::MaintenanceWindowTooLong
+        );
+
+        emit!(PauseExtended {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            additional_seconds,
+            maintenance_start: subscription_plan.maintenance_start,
+            maintenance_end: subscription_plan.maintenance_end,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Amount the subscriber's next recurring charge will take (read-only)
+    /// 
+    /// Returned via return data, computed by the same `renewal_charge` the
+    /// payment instructions use, so a final cycle cut short by the hard end
+    /// shows its prorated amount. Clients can call it with `.view()`.
+    pub fn effective_next_price(
+        ctx: Context<EffectiveNextPrice>,
+        _plan_id: u64,
+    ) -> Result<u64> {
+        let now = current_time()?;
+        renewal_charge(
+            &ctx.accounts.subscription,
+            &ctx.accounts.subscription_plan,
+            1,
+            now,
+        )
+    }
+
+    /// Delegate day-to-day plan administration to a manager (creator only)
+    /// 
+    /// The manager may `pause_plan`, `unpause_plan` and change non-financial
+    /// fields with `update_subscription_plan`. Pricing, payout, refunds and
+    /// every other setting stay creator-only. `None` revokes the manager.
+    pub fn set_manager(
+        ctx: Context<SetManager>,
+        _plan_id: u64,
+        manager: Option<Pubkey>,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.manager = manager;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Set the minimum plan price for this deployment (admin only)
+    /// 
+    /// Checked by `create_subscription_plan` and price changes in
+    /// `update_subscription_plan`. The floor is in smallest token units for
+    /// every mint, so pick a value that is sensible for the mints in use.
+    /// Existing plans priced below a raised floor are unaffected.
+    pub fn set_min_price(
+        ctx: Context<SetMinPrice>,
+        min_price: u64,
+    ) -> Result<()> {
+        ctx.accounts.program_config.min_price = min_price;
+        Ok(())
+    }
+
+    /// Recompute `current_subscribers` from subscription accounts (creator only)
+    /// 
+    /// Operational safety valve for a counter that has drifted. Every
+    /// subscription of the plan must be passed via `remaining_accounts`: the
+    /// stored count is overwritten with the number of passed subscriptions
+    /// that hold a seat, and the per-status counters are rebuilt from their
+    /// statuses, so an incomplete set undercounts. Each account is
+    /// verified against its PDA and may appear only once.
+    pub fn reconcile_subscriber_count<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReconcileSubscriberCount<'info>>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let subscriptions = load_plan_subscriptions(
+            ctx.remaining_accounts,
+            subscription_plan,
+            plan_id,
+        )?;
+        let (count, comp_count) = seat_counts(&subscriptions)?;
+
+        let previous_count = subscription_plan.current_subscribers;
+        subscription_plan.current_subscribers = count;
+        subscription_plan.comp_subscribers = comp_count;
+        subscription_plan.active_count = 0;
+        subscription_plan.paused_count = 0;
+        subscription_plan.expired_count = 0;
+        for subscription in &subscriptions {
+            subscription_plan.count_status_change(None, subscription.status)?;
+        }
+
+        emit!(SubscriberCountReconciled {
+            creator: subscription_plan.creator,
+            plan_id,
+            previous_count,
+            new_count: count,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Define a bundle of the creator's plans sold together (creator only)
+    /// 
+    /// Member plans are passed via `remaining_accounts` in `plan_ids` order.
+    /// They must be distinct, belong to the creator, share the bundle's mint
+    /// and bill on the same interval so member subscriptions can share a
+    /// due date.
+    pub fn create_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateBundle<'info>>,
+        bundle_id: u64,
+        plan_ids: Vec<u64>,
+        price: u64,
+    ) -> Result<()> {
+        require!(
+            (MIN_BUNDLE_PLANS..=MAX_BUNDLE_PLANS).contains(&plan_ids.len()),
+            ErrorCode::InvalidBundleSize
+        );
+        require!(price > 0, ErrorCode::InvalidPrice);
+        require!(
+            ctx.remaining_accounts.len() == plan_ids.len(),
+            ErrorCode::InvalidBundlePlan
+        );
+        for (i, plan_id) in plan_ids.iter().enumerate() {
+            require!(!plan_ids[..i].contains(plan_id), ErrorCode::InvalidBundlePlan);
+        }
+
+        let bundle = &mut ctx.accounts.bundle;
+        bundle.creator = ctx.accounts.creator.key();
+        bundle.bundle_id = bundle_id;
+        bundle.plan_ids = plan_ids;
+        bundle.price = price;
+        bundle.payment_mint = ctx.accounts.payment_mint.key();
+        bundle.payout_token_account = ctx.accounts.payout_token_account.key();
+        bundle.is_active = true;
+        bundle.bump = ctx.bumps.bundle;
+
+        let first = load_bundle_plan(&ctx.remaining_accounts[0], bundle, 0)?;
+        for (i, account_info) in ctx.remaining_accounts.iter().enumerate() {
+            let subscription_plan = load_bundle_plan(account_info, bundle, i)?;
+            require_keys_eq!(
+                subscription_plan.payment_mint,
+                bundle.payment_mint,
+                ErrorCode::MintMismatch
+            );
+            require!(
+                subscription_plan.interval_seconds == first.interval_seconds
+                    && subscription_plan.interval_unit == first.interval_unit
+                    && subscription_plan.interval_count == first.interval_count,
+                ErrorCode::BundleIntervalMismatch
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Join every plan in a bundle with a single payment
+    /// 
+    /// `remaining_accounts` holds, for each member plan in `plan_ids` order,
+    /// the plan (writable) followed by the subscriber's subscription PDA for
+    /// it (writable, uninitialized). The bundle price is charged once and a
+    /// subscription is created on each plan, all sharing the first plan's
+    /// due date. Fails if any member plan cannot take a new subscriber.
+    pub fn subscribe_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SubscribeBundle<'info>>,
+        bundle_id: u64,
+    ) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        let subscriber = ctx.accounts.subscriber.key();
+        let now = current_time()?;
+
+        require!(bundle.is_active, ErrorCode::BundleInactive);
+        require!(
+            ctx.remaining_accounts.len() == bundle.plan_ids.len() * 2,
+            ErrorCode::InvalidBundlePlan
+        );
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= bundle.price,
+            ErrorCode::InsufficientFunds
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, bundle.price, ctx.accounts.payment_mint.decimals)?;
+
+        let shares = bundle_shares(bundle.price, bundle.plan_ids.len())?;
+        let mut next_payment = 0;
+
+        for (i, accounts) in ctx.remaining_accounts.chunks(2).enumerate() {
+            let mut subscription_plan = load_bundle_plan(&accounts[0], bundle, i)?;
+            check_can_join(&mut subscription_plan, now)?;
+
+            let plan_id = bundle.plan_ids[i];
+            let (expected, bump) = Pubkey::find_program_address(
+                &[b"subscription", subscriber.as_ref(), &plan_id.to_le_bytes()],
+                &crate::ID,
+            );
+            require_keys_eq!(accounts[1].key(), expected, ErrorCode::InvalidSubscriptionAccount);
+
+            create_pda_account(
+                &ctx.accounts.subscriber.to_account_info(),
+                &accounts[1],
+                &ctx.accounts.system_program.to_account_info(),
+                Subscription::LEN,
+                &[b"subscription", subscriber.as_ref(), &plan_id.to_le_bytes(), &[bump]],
+            )?;
+
+            let mut subscription = Subscription {
+                subscriber,
+                plan_id,
+                creator: bundle.creator,
+                bump,
+                bundle_id: Some(bundle_id),
+                schema_version: CURRENT_SCHEMA_VERSION,
+                created_at: now,
+                ..Default::default()
+            };
+            activate_subscription(
+                &mut subscription,
+                &mut subscription_plan,
+                1,
+                shares[i],
+                now,
+            )?;
+            if i == 0 {
+                next_payment = subscription.next_payment;
+            }
+            subscription.next_payment = next_payment;
+            emit_payment_upcoming(&subscription, &subscription_plan, now)?;
+            subscription.try_serialize(&mut &mut accounts[1].try_borrow_mut_data()?[..])?;
+
+            take_seat(&mut subscription_plan, now)?;
+            subscription_plan.exit(&crate::ID)?;
+        }
+
+        emit!(BundleSubscribed {
+            subscriber,
+            creator: bundle.creator,
+            bundle_id,
+            plan_ids: bundle.plan_ids.clone(),
+            amount: bundle.price,
+            next_payment,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Renew every subscription in a bundle with a single payment
+    /// 
+    /// Takes the same `remaining_accounts` layout as `subscribe_bundle`, with
+    /// the subscriptions already initialized. Each member subscription must
+    /// be due; all are advanced together and keep a shared due date.
+    pub fn process_bundle_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProcessBundlePayment<'info>>,
+        bundle_id: u64,
+    ) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        let subscriber = ctx.accounts.subscriber.key();
+        let now = current_time()?;
+
+        require!(
+            ctx.remaining_accounts.len() == bundle.plan_ids.len() * 2,
+            ErrorCode::InvalidBundlePlan
+        );
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= bundle.price,
+            ErrorCode::InsufficientFunds
+        );
+
+        let shares = bundle_shares(bundle.price, bundle.plan_ids.len())?;
+        let mut next_payment = 0;
+
+        for (i, accounts) in ctx.remaining_accounts.chunks(2).enumerate() {
+            let mut subscription_plan = load_bundle_plan(&accounts[0], bundle, i)?;
+            let mut subscription = load_bundle_subscription(&accounts[1], bundle, i, &subscriber)?;
+
+            check_payment_due(&subscription, &subscription_plan, now)?;
+            settle_cycle(&mut subscription, &mut subscription_plan, shares[i], now)?;
+            if i == 0 {
+                next_payment = subscription.next_payment;
+            }
+            subscription.next_payment = next_payment;
+            emit_payment_upcoming(&subscription, &subscription_plan, now)?;
+            subscription.exit(&crate::ID)?;
+            subscription_plan.exit(&crate::ID)?;
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, bundle.price, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(BundlePaymentProcessed {
+            subscriber,
+            creator: bundle.creator,
+            bundle_id,
+            amount: bundle.price,
+            next_payment,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel every subscription in a bundle together
+    /// 
+    /// Takes the same `remaining_accounts` layout as `subscribe_bundle`.
+    /// Member subscriptions can't be cancelled individually. Each member
+    /// plan's `min_subscription_seconds` and `block_same_tx_cancel` apply as
+    /// in `cancel_subscription`.
+    pub fn cancel_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelBundle<'info>>,
+        bundle_id: u64,
+    ) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        let subscriber = ctx.accounts.subscriber.key();
+        let now = current_time()?;
+
+        require!(
+            ctx.remaining_accounts.len() == bundle.plan_ids.len() * 2,
+            ErrorCode::InvalidBundlePlan
+        );
+
+        for (i, accounts) in ctx.remaining_accounts.chunks(2).enumerate() {
+            let mut subscription_plan = load_bundle_plan(&accounts[0], bundle, i)?;
+            let mut subscription = load_bundle_subscription(&accounts[1], bundle, i, &subscriber)?;
+            require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+            require!(
+                subscription.min_duration_met(subscription_plan.min_subscription_seconds, now),
+                ErrorCode::MinimumDurationNotMet
+            );
+            check_not_joined_in_tx(
+                &subscription_plan,
+                accounts[1].key,
+                &ctx.accounts.instructions_sysvar,
+            )?;
+
+            transition_status(&mut subscription, &mut subscription_plan, SubscriptionStatus::Cancelled)?;
+            free_seat(&mut subscription_plan, &subscription)?;
+            subscription.exit(&crate::ID)?;
+            subscription_plan.exit(&crate::ID)?;
+        }
+
+        emit!(BundleCancelled {
+            subscriber,
+            creator: bundle.creator,
+            bundle_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Stop selling a bundle (creator only)
+    /// 
+    /// New subscribers are refused with `BundleInactive`; existing members
+    /// keep billing and cancelling through the bundle as before.
+    pub fn deactivate_bundle(ctx: Context<DeactivateBundle>, bundle_id: u64) -> Result<()> {
+        let bundle = &mut ctx.accounts.bundle;
+        let now = current_time()?;
+
+        require!(bundle.is_active, ErrorCode::BundleInactive);
+        bundle.is_active = false;
+
+        emit!(BundleDeactivated {
+            creator: bundle.creator,
+            bundle_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Set how far ahead of a due date subscribers should be reminded (creator only)
+    /// 
+    /// Carried as `remind_at` on every `PaymentUpcoming` event; the program
+    /// itself never sends notifications. Must be between zero and the plan's
+    /// interval.
+    pub fn set_reminder_lead_seconds(
+        ctx: Context<SetReminderLeadSeconds>,
+        _plan_id: u64,
+        reminder_lead_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            reminder_lead_seconds >= 0
+                && reminder_lead_seconds <= subscription_plan.interval_seconds,
+            ErrorCode::InvalidReminderLead
+        );
+        subscription_plan.reminder_lead_seconds = reminder_lead_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Place a dispute or compliance hold on one subscription (creator or admin)
+    /// 
+    /// While frozen, payments, refunds and moves to a successor plan fail
+    /// with `SubscriptionFrozen`, but the subscription keeps its status and
+    /// seat: `refresh_status` won't expire it. Unlike a plan pause, no time
+    /// is credited back, so a subscriber past grace when unfrozen expires on
+    /// the next refresh.
+    pub fn freeze_subscription(
+        ctx: Context<FreezeSubscription>,
+        plan_id: u64,
+        _subscriber: Pubkey,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+        subscription.is_frozen = true;
+
+        emit!(SubscriptionFrozen {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            authority: ctx.accounts.authority.key(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Lift a hold placed with `freeze_subscription` (creator or admin)
+    pub fn unfreeze_subscription(
+        ctx: Context<FreezeSubscription>,
+        plan_id: u64,
+        _subscriber: Pubkey,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_frozen, ErrorCode::SubscriptionNotFrozen);
+        subscription.is_frozen = false;
+
+        emit!(SubscriptionUnfrozen {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            authority: ctx.accounts.authority.key(),
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Configure automatic capacity growth (creator only)
+    /// 
+    /// When a subscribe would fail with `PlanFull`, `max_subscribers` grows
+    /// by `auto_expand_step`, never beyond `hard_cap`, and the subscribe
+    /// proceeds. A step of 0 disables expansion; otherwise `hard_cap` must
+    /// be at least the current `max_subscribers`.
+    pub fn set_auto_expand(
+        ctx: Context<SetAutoExpand>,
+        _plan_id: u64,
+        auto_expand_step: u32,
+        hard_cap: u32,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        if auto_expand_step > 0 {
+            require!(
+                hard_cap >= subscription_plan.max_subscribers,
+                ErrorCode::InvalidAutoExpand
+            );
+            subscription_plan.max_subscribers
+                .checked_add(auto_expand_step)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        subscription_plan.auto_expand_step = auto_expand_step;
+        subscription_plan.hard_cap = hard_cap;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Give one subscription a negotiated grace period (creator only)
+    /// 
+    /// When set, `grace_override_seconds` replaces the default grace in the
+    /// `PaymentTooLate` check and in `refresh_status`'s expiry, so cranks
+    /// honour it too. Capped at `MAX_GRACE_SECONDS`; `None` restores the
+    /// plan's `grace_window`.
+    pub fn set_grace_override(
+        ctx: Context<SetGraceOverride>,
+        plan_id: u64,
+        _subscriber: Pubkey,
+        grace_override_seconds: Option<i64>,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        if let Some(seconds) = grace_override_seconds {
+            require!(
+                (0..=MAX_GRACE_SECONDS).contains(&seconds),
+                ErrorCode::InvalidGraceOverride
+            );
+        }
+        subscription.grace_override_seconds = grace_override_seconds;
+
+        emit!(GraceOverrideSet {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            grace_override_seconds,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Set the plan creation fee and where it is paid (admin only)
+    /// 
+    /// The fee is charged in lamports by `create_subscription_plan`; 0 makes
+    /// plan creation free again.
+    pub fn set_plan_creation_fee(
+        ctx: Context<SetPlanCreationFee>,
+        plan_creation_fee: u64,
+        fee_destination: Pubkey,
+    ) -> Result<()> {
+        let program_config = &mut ctx.accounts.program_config;
+        program_config.plan_creation_fee = plan_creation_fee;
+        program_config.fee_destination = fee_destination;
+        Ok(())
+    }
+
+    /// Exempt a creator from platform fees, or end the exemption (admin only)
+    /// 
+    /// Stored in the creator's config PDA (`["creator_config", creator]`),
+    /// created here on first use with the admin paying rent. Creators
+    /// without one are not exempt. Exempt creators skip the plan creation
+    /// fee in `create_subscription_plan` and `clone_plan`.
+    pub fn set_fee_exemption(
+        ctx: Context<SetFeeExemption>,
+        creator: Pubkey,
+        exempt: bool,
+    ) -> Result<()> {
+        let creator_config = &mut ctx.accounts.creator_config;
+        creator_config.creator = creator;
+        creator_config.fee_exempt = exempt;
+        creator_config.bump = ctx.bumps.creator_config;
+
+        emit!(FeeExemptionChanged {
+            creator,
+            exempt,
+            admin: ctx.accounts.admin.key(),
+            timestamp: current_time()?,
+        });
+
+        Ok(())
+    }
+
+    /// Give `beneficiary` a free subscription (creator only)
+    /// 
+    /// Creates an active subscription that takes a seat but collects nothing;
+    /// `process_payment` renews it at no charge until `revoke_comp`. Fails if
+    /// `beneficiary` already has a subscription account on the plan, active
+    /// or not yet closed.
+    pub fn grant_subscription(
+        ctx: Context<GrantSubscription>,
+        plan_id: u64,
+        beneficiary: Pubkey,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        check_can_join(subscription_plan, now)?;
+
+        subscription.subscriber = beneficiary;
+        subscription.schema_version = CURRENT_SCHEMA_VERSION;
+        subscription.created_at = now;
+        subscription.plan_id = plan_id;
+        subscription.creator = subscription_plan.creator;
+        subscription.bump = ctx.bumps.subscription;
+        subscription.is_comp = true;
+        activate_subscription(subscription, subscription_plan, 1, 0, now)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        take_seat(subscription_plan, now)?;
+        subscription_plan.comp_subscribers = subscription_plan.comp_subscribers
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit!(SubscriptionGranted {
+            beneficiary,
+            creator: subscription_plan.creator,
+            plan_id,
+            next_payment: subscription.next_payment,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// End a subscription granted with `grant_subscription` (creator only)
+    pub fn revoke_comp(
+        ctx: Context<RevokeComp>,
+        plan_id: u64,
+        _beneficiary: Pubkey,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+        free_seat(subscription_plan, subscription)?;
+
+        emit!(SubscriptionCancelled {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Assert a plan's bookkeeping invariants hold (read-only, anyone)
+    /// 
+    /// Every subscription of the plan must be passed via `remaining_accounts`.
+    /// Fails with the error for the first broken invariant:
+    /// - `InvariantPaymentMint`: the payout account isn't in `payment_mint`
+    /// - `InvariantOverdueSubscription`: a live subscription is past its
+    ///   deadline without being expired (its `refresh_status` crank is behind)
+    /// - `InvariantSubscriberCount`: `current_subscribers` or
+    ///   `comp_subscribers` differs from the passed accounts (see
+    ///   `reconcile_subscriber_count`)
+    pub fn check_plan_invariants<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CheckPlanInvariants<'info>>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require_keys_eq!(
+            ctx.accounts.payout_token_account.mint,
+            subscription_plan.payment_mint,
+            ErrorCode::InvariantPaymentMint
+        );
+
+        let subscriptions = load_plan_subscriptions(
+            ctx.remaining_accounts,
+            subscription_plan,
+            plan_id,
+        )?;
+        for subscription in subscriptions.iter() {
+            check_not_overdue(subscription, subscription_plan, now)?;
+        }
+
+        let (count, comp_count) = seat_counts(&subscriptions)?;
+        require!(
+            count == subscription_plan.current_subscribers
+                && comp_count == subscription_plan.comp_subscribers,
+            ErrorCode::InvariantSubscriberCount
+        );
+
+        Ok(())
+    }
+
+    /// Spread new subscribers' first due dates (creator only)
+    /// 
+    /// Each new subscription's first `next_payment` is pushed back by a
+    /// deterministic offset in `[0, jitter_seconds)` derived from the
+    /// subscriber key; later cycles keep that cadence. Must be less than the
+    /// plan interval. Existing subscriptions are unaffected.
+    pub fn set_jitter_seconds(
+        ctx: Context<SetJitterSeconds>,
+        _plan_id: u64,
+        jitter_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            jitter_seconds >= 0 && jitter_seconds < subscription_plan.interval_seconds,
+            ErrorCode::InvalidJitter
+        );
+        subscription_plan.jitter_seconds = jitter_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Count how many of a subscriber's subscriptions are payable now (read-only)
+    /// 
+    /// Pass `(plan, subscription)` pairs via `remaining_accounts`; each must
+    /// belong to `subscriber`. Returns the number `process_payment` would
+    /// accept right now as `u32` return data, so wallets don't have to
+    /// reimplement the due and grace rules. Call with `.view()`; the result
+    /// only covers the accounts passed.
+    pub fn due_subscriptions_count<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DueSubscriptionsCount<'info>>,
+    ) -> Result<u32> {
+        let subscriber = ctx.accounts.subscriber.key();
+        let now = current_time()?;
+
+        let pairs = ctx.remaining_accounts.chunks_exact(2);
+        require!(pairs.remainder().is_empty(), ErrorCode::InvalidSubscriptionAccount);
+
+        let mut due: u32 = 0;
+        for accounts in pairs {
+            let subscription_plan = Account::<SubscriptionPlan>::try_from(&accounts[0])?;
+            let subscription = Account::<Subscription>::try_from(&accounts[1])?;
+            require_keys_eq!(subscription.subscriber, subscriber, ErrorCode::InvalidSubscriber);
+            require!(
+                subscription_plan.creator == subscription.creator
+                    && subscription_plan.plan_id == subscription.plan_id,
+                ErrorCode::InvalidPlanId
+            );
+
+            if is_payment_due(&subscription, &subscription_plan, now) {
+                due = due.checked_add(1).ok_or(ErrorCode::Overflow)?;
+            }
+        }
+
+        Ok(due)
+    }
+
+    /// Set the one-time setup fee charged when subscribing (creator only)
+    /// 
+    /// `subscribe` and `confirm_subscription` charge it on top of the first
+    /// cycle; recurring payments never include it. It is not returned by
+    /// `self_refund`. 0 disables it.
+    pub fn set_setup_fee(
+        ctx: Context<SetSetupFee>,
+        _plan_id: u64,
+        setup_fee: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.setup_fee = setup_fee;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Allow up to `cycles` crank-driven renewals until `expires_at`
+    /// 
+    /// Records the limits on the subscription and approves the plan PDA as
+    /// SPL delegate on `subscriber_token_account` for `cycles` times the
+    /// current price, replacing any earlier approval on that account.
+    /// `process_payment_delegated` refuses once either limit is reached, so
+    /// the subscriber's exposure is bounded even if the price later rises.
+    pub fn authorize_auto_renew(
+        ctx: Context<AuthorizeAutoRenew>,
+        plan_id: u64,
+        cycles: u32,
+        expires_at: i64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            cycles > 0 && expires_at > now,
+            ErrorCode::InvalidAutoRenewAuthorization
+        );
+        let allowance = effective_price(subscription, subscription_plan, now)?
+            .checked_mul(cycles as u64)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let cpi_accounts = Approve {
+            to: ctx.accounts.subscriber_token_account.to_account_info(),
+            delegate: subscription_plan.to_account_info(),
+            authority: ctx.accounts.subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token_interface::approve(CpiContext::new(cpi_program, cpi_accounts), allowance)?;
+
+        subscription.auto_renew_cycles_remaining = cycles;
+        subscription.auto_renew_expires_at = expires_at;
+
+        emit!(AutoRenewAuthorized {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            cycles,
+            expires_at,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Renew a subscription on the subscriber's behalf (anyone)
+    /// 
+    /// Pulls the payment with the plan PDA's delegate authority, within the
+    /// limits set by `authorize_auto_renew`: fails with `AuthorizationExpired`
+    /// after `expires_at` and `AutoRenewExhausted` once the authorized cycles
+    /// are used. Otherwise follows the rules of `process_payment`.
+    pub fn process_payment_delegated(
+        ctx: Context<ProcessPaymentDelegated>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        check_auto_renew(subscription, now)?;
+        check_payment_due(subscription, subscription_plan, now)?;
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+        let amount = renewal_charge(subscription, subscription_plan, 1, now)?;
+        emit_if_final_prorated(subscription, subscription_plan, 1, amount, now)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let creator_key = subscription_plan.creator;
+        let plan_id_bytes = plan_id.to_le_bytes();
+        let seeds = &[
+            b"subscription_plan".as_ref(),
+            creator_key.as_ref(),
+            plan_id_bytes.as_ref(),
+            &[subscription_plan.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: subscription_plan.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        settle_cycle(subscription, subscription_plan, amount, now)?;
+        subscription.auto_renew_cycles_remaining = subscription.auto_renew_cycles_remaining
+            .checked_sub(1)
+            .ok_or(ErrorCode::Underflow)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(PaymentProcessed {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount,
+            payment_number: subscription.total_payments,
+            total_paid_amount: subscription.total_paid_amount,
+            reference: subscription.reference,
+            timestamp: now,
+            payment_mint: ctx.accounts.payment_mint.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Renew a subscription with a permit the subscriber signed off-chain
+    /// (anyone, typically a relayer paying the transaction fee)
+    /// 
+    /// The transaction must also carry an ed25519 program instruction
+    /// verifying the subscriber's signature over `permit.message()`, with
+    /// key, message and signature in that instruction's own data. The
+    /// permit must name this subscription, carry its current `permit_nonce`
+    /// (else `PermitReplay`) and not be past `expires_at` (else
+    /// `PermitExpired`); the charge may not exceed `max_amount`. As with
+    /// `process_payment_delegated`, tokens are pulled with the plan PDA's
+    /// delegate authority, so the subscriber must have approved it on their
+    /// token account. Otherwise follows the rules of `process_payment`,
+    /// including its memo requirement.
+    pub fn process_payment_with_permit(
+        ctx: Context<ProcessPaymentWithPermit>,
+        plan_id: u64,
+        permit: PaymentPermit,
+    ) -> Result<PaymentReceipt> {
+        let subscription = &mut ctx.accounts.subscription;
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(now <= permit.expires_at, ErrorCode::PermitExpired);
+        require_keys_eq!(permit.subscription, subscription.key(), ErrorCode::InvalidPermit);
+        require!(permit.nonce >= subscription.permit_nonce, ErrorCode::PermitReplay);
+        require!(permit.nonce == subscription.permit_nonce, ErrorCode::InvalidPermit);
+        check_permit_signature(
+            &ctx.accounts.instructions_sysvar,
+            subscription.subscriber,
+            &permit.message(),
+        )?;
+        check_memo(subscription_plan, &ctx.accounts.instructions_sysvar)?;
+
+        check_payment_due(subscription, subscription_plan, now)?;
+        let amount = renewal_charge(subscription, subscription_plan, 1, now)?;
+        check_max_token_amount(amount, Some(permit.max_amount))?;
+        emit_if_final_prorated(subscription, subscription_plan, 1, amount, now)?;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let creator_key = subscription_plan.creator;
+        let plan_id_bytes = plan_id.to_le_bytes();
+        let seeds = &[
+            b"subscription_plan".as_ref(),
+            creator_key.as_ref(),
+            plan_id_bytes.as_ref(),
+            &[subscription_plan.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: subscription_plan.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        settle_cycle(subscription, subscription_plan, amount, now)?;
+        subscription.permit_nonce = subscription.permit_nonce
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(PaymentProcessed {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            amount,
+            payment_number: subscription.total_payments,
+            total_paid_amount: subscription.total_paid_amount,
+            reference: subscription.reference,
+            timestamp: now,
+            payment_mint: ctx.accounts.payment_mint.key(),
+        });
+
+        Ok(PaymentReceipt::for_subscription(subscription, amount, now))
+    }
+
+    /// Create a plan as a copy of another of the creator's plans
+    /// 
+    /// Copies pricing, interval, capacity, metadata, payout and every policy
+    /// and fee setting from `source_plan_id`. The clone starts active and
+    /// unpaused with no subscribers, no maintenance window, successor or
+    /// manager, and its own creation nonce. Creation fee and price floor
+    /// apply as in `create_subscription_plan`.
+    pub fn clone_plan(
+        ctx: Context<ClonePlan>,
+        _source_plan_id: u64,
+        new_plan_id: u64,
+    ) -> Result<()> {
+        let source_plan = &ctx.accounts.source_plan;
+        let creator = &ctx.accounts.creator;
+        let now = current_time()?;
+
+        let program_config = ProgramConfig::load_or_default(&ctx.accounts.program_config)?;
+        check_min_price(source_plan.price, program_config.min_price)?;
+        collect_creation_fee(
+            &program_config,
+            &CreatorConfig::load_or_default(&ctx.accounts.creator_config)?,
+            creator,
+            &ctx.accounts.fee_destination,
+            &ctx.accounts.system_program,
+            new_plan_id,
+            now,
+        )?;
+
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        subscription_plan.set_inner(SubscriptionPlan {
+            plan_id: new_plan_id,
+            current_subscribers: 0,
+            comp_subscribers: 0,
+            is_active: true,
+            is_paused: false,
+            created_at: now,
+            bump: ctx.bumps.subscription_plan,
+            maintenance_start: 0,
+            maintenance_end: 0,
+            successor_plan_id: None,
+            manager: None,
+            active_count: 0,
+            paused_count: 0,
+            expired_count: 0,
+            activation_threshold: 0,
+            funding_deadline: 0,
+            threshold_reached: false,
+            schema_version: CURRENT_SCHEMA_VERSION,
+            deactivation_effective_at: 0,
+            accrued_revenue: 0,
+            last_payment_at: 0,
+            event_seq: 0,
+            last_capacity_change: 0,
+            verified: false,
+            ..SubscriptionPlan::clone(source_plan)
+        });
+        assign_creation_nonce(
+            subscription_plan,
+            &mut ctx.accounts.creator_registry,
+            creator.key(),
+            ctx.bumps.creator_registry,
+        )?;
+
+        emit!(SubscriptionPlanCreated {
+            creator: creator.key(),
+            plan_id: new_plan_id,
+            price: subscription_plan.price,
+            interval_seconds: subscription_plan.interval_seconds,
+            metadata_hash: subscription_plan.metadata_hash,
+            currency_symbol: subscription_plan.currency_symbol.clone(),
+            timestamp: now,
+            creation_nonce: subscription_plan.creation_nonce,
+            payment_decimals: subscription_plan.payment_decimals,
+            event_seq: subscription_plan.next_event_seq()?,
+        });
+
+        Ok(())
+    }
+
+    /// Bill on a fixed day of the month (creator only)
+    /// 
+    /// With `billing_anchor_day` set (1-28), every due date snaps forward to
+    /// the next occurrence of that day of the month (UTC), keeping the time
+    /// of day, so the first payment after joining lands on the anchor and
+    /// monthly cycles stay on it. Only `Months` plans can set an anchor,
+    /// since snapping a shorter cycle forward to the anchor would skip
+    /// whole cycles; 0 falls back to plain interval scheduling. Can't be
+    /// combined with `bill_on_weekday_only`. Applies from each
+    /// subscription's next scheduled payment.
+    pub fn set_billing_anchor_day(
+        ctx: Context<SetBillingAnchorDay>,
+        _plan_id: u64,
+        billing_anchor_day: u8,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            billing_anchor_day <= MAX_BILLING_ANCHOR_DAY
+                && (billing_anchor_day == 0
+                    || (subscription_plan.interval_unit == IntervalUnit::Months
+                        && !subscription_plan.bill_on_weekday_only)),
+            ErrorCode::InvalidBillingAnchorDay
+        );
+        subscription_plan.billing_anchor_day = billing_anchor_day;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Choose what happens past grace (creator only)
+    /// 
+    /// `Expire` refuses late payments and lets `refresh_status` lapse the
+    /// subscription and free its seat. `Accumulate` keeps the subscription
+    /// in Grace and lets `process_payment` collect all missed cycles, up to
+    /// `max_debt_cycles` (1-36); beyond that it expires as under `Expire`.
+    /// `max_debt_cycles` must be 0 for `Expire`.
+    pub fn set_past_grace_policy(
+        ctx: Context<SetPastGracePolicy>,
+        _plan_id: u64,
+        policy: PastGracePolicy,
+        max_debt_cycles: u32,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let valid = match policy {
+            PastGracePolicy::Expire => max_debt_cycles == 0,
+            PastGracePolicy::Accumulate => max_debt_cycles > 0 && max_debt_cycles <= MAX_DEBT_CYCLES,
+        };
+        require!(valid, ErrorCode::InvalidMaxCycles);
+        subscription_plan.past_grace_policy = policy;
+        subscription_plan.max_debt_cycles = max_debt_cycles;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Undo a plan's deactivation (program admin only)
+    /// 
+    /// An operator escape hatch for accidental deactivations; creators
+    /// cannot reactivate their own plans. The plan accepts subscriptions and
+    /// payments again and its successor pointer is cleared. Emits
+    /// `SubscriptionPlanReactivated` naming the admin.
+    pub fn reactivate_plan(
+        ctx: Context<ReactivatePlan>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(!subscription_plan.is_active_at(now), ErrorCode::PlanAlreadyActive);
+        subscription_plan.is_active = true;
+        subscription_plan.deactivation_effective_at = 0;
+        subscription_plan.successor_plan_id = None;
+
+        emit!(SubscriptionPlanReactivated::for_plan(
+            subscription_plan,
+            ctx.accounts.admin.key(),
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Require a minimum number of pledges before the plan goes live (creator only)
+    /// 
+    /// With `activation_threshold` set, joining works through `pledge`: each
+    /// pledge escrows one cycle in the plan's pledge vault and holds a seat
+    /// as a Pending subscription. Once pledges reach the threshold,
+    /// `ThresholdReached` is emitted and each pledge can be turned into a
+    /// live subscription with `activate_pledge`. If the threshold isn't met
+    /// by `funding_deadline`, pledgers reclaim their escrow with
+    /// `refund_pledge`.
+    /// 
+    /// Only before anyone has joined. The threshold must fit within
+    /// `max_subscribers` and the deadline lie in the future; 0 and 0 remove
+    /// the funding round.
+    pub fn set_activation_threshold(
+        ctx: Context<SetActivationThreshold>,
+        _plan_id: u64,
+        activation_threshold: u32,
+        funding_deadline: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let valid = if activation_threshold == 0 {
+            funding_deadline == 0
+        } else {
+            activation_threshold <= subscription_plan.max_subscribers && funding_deadline > now
+        };
+        require!(
+            valid && subscription_plan.current_subscribers == 0,
+            ErrorCode::InvalidActivationThreshold
+        );
+        subscription_plan.activation_threshold = activation_threshold;
+        subscription_plan.funding_deadline = funding_deadline;
+        subscription_plan.threshold_reached = false;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Create the plan's pledge escrow (creator only)
+    /// 
+    /// A token account PDA (`["pledge_vault", plan]`) that is its own
+    /// authority, holding pledges until they are activated or refunded.
+    pub fn initialize_pledge_vault(
+        _ctx: Context<InitializePledgeVault>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Commit to a plan that is still collecting pledges
+    /// 
+    /// Escrows one cycle in the pledge vault and creates a Pending
+    /// subscription holding a seat; setup fees don't apply to pledges. The pledge that meets the
+    /// plan's `activation_threshold` emits `ThresholdReached`. Fails with
+    /// `FundingClosed` once the round has succeeded or its deadline passed.
+    pub fn pledge(
+        ctx: Context<Pledge>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let subscriber = &ctx.accounts.subscriber;
+        let now = current_time()?;
+
+        require!(subscription_plan.is_active_at(now), ErrorCode::PlanInactive);
+        require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+        require!(
+            subscription_plan.is_funding() && now <= subscription_plan.funding_deadline,
+            ErrorCode::FundingClosed
+        );
+        require!(
+            subscription_plan.current_subscribers < subscription_plan.max_subscribers,
+            ErrorCode::PlanFull
+        );
+
+        let amount = subscription_plan.price;
+        require!(
+            ctx.accounts.subscriber_token_account.amount >= amount,
+            ErrorCode::InsufficientFunds
+        );
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.subscriber_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.pledge_vault.to_account_info(),
+            authority: subscriber.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        subscription.subscriber = subscriber.key();
+        subscription.schema_version = CURRENT_SCHEMA_VERSION;
+        subscription.created_at = now;
+        subscription.plan_id = plan_id;
+        subscription.creator = subscription_plan.creator;
+        subscription.bump = ctx.bumps.subscription;
+        subscription.set_status(SubscriptionStatus::Pending);
+        subscription.pledged_amount = amount;
+
+        take_seat(subscription_plan, now)?;
+
+        emit!(PledgeMade {
+            subscriber: subscriber.key(),
+            creator: subscription_plan.creator,
+            plan_id,
+            amount,
+            pledges: subscription_plan.current_subscribers,
+            activation_threshold: subscription_plan.activation_threshold,
+            timestamp: now,
+        });
+
+        if subscription_plan.current_subscribers >= subscription_plan.activation_threshold {
+            subscription_plan.threshold_reached = true;
+            emit!(ThresholdReached {
+                creator: subscription_plan.creator,
+                plan_id,
+                pledges: subscription_plan.current_subscribers,
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Turn a pledge into a live subscription once the threshold is reached
+    /// (permissionless)
+    /// 
+    /// Releases the escrowed pledge to the creator's payout account and
+    /// activates the subscription as if it had subscribed now.
+    /// 
+    /// # Overpayment
+    /// A pledge escrows the price at pledge time. If it is more than the
+    /// subscription's `effective_price` now, only that goes to the creator
+    /// and the difference moves to the plan's prepaid vault, credited to the
+    /// subscription's `prepaid_balance` (outside the `max_prepaid_cycles`
+    /// cap) and reported with `Overpayment`. The vault must then be passed
+    /// as the only `remaining_accounts` entry.
+    pub fn activate_pledge<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ActivatePledge<'info>>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription_plan.threshold_reached, ErrorCode::FundingInProgress);
+        let pledged = subscription.pledged_amount;
+        require!(
+            pledged > 0 && subscription.status == SubscriptionStatus::Pending,
+            ErrorCode::NoPledge
+        );
+        let overpaid = pledged.saturating_sub(effective_price(subscription, subscription_plan, now)?);
+        let prepaid_vault = if overpaid > 0 {
+            Some(load_prepaid_vault(ctx.remaining_accounts, subscription_plan.key())?)
+        } else {
+            None
+        };
+        let amount = pledged - overpaid;
+
+        let plan_key = subscription_plan.key();
+        let seeds = &[
+            b"pledge_vault".as_ref(),
+            plan_key.as_ref(),
+            &[ctx.bumps.pledge_vault],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.pledge_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_token_account.to_account_info(),
+            authority: ctx.accounts.pledge_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        if let Some(prepaid_vault) = prepaid_vault {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.pledge_vault.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: prepaid_vault.to_account_info(),
+                authority: ctx.accounts.pledge_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+            token_interface::transfer_checked(cpi_ctx, overpaid, ctx.accounts.payment_mint.decimals)?;
+            subscription.prepaid_balance = subscription.prepaid_balance
+                .checked_add(overpaid)
+                .ok_or(ErrorCode::Overflow)?;
+
+            emit!(Overpayment {
+                subscriber: subscription.subscriber,
+                creator: subscription.creator,
+                plan_id,
+                amount: overpaid,
+                credited_to_prepaid: true,
+                prepaid_balance: subscription.prepaid_balance,
+                timestamp: now,
+            });
+        }
+
+        subscription.pledged_amount = 0;
+        activate_subscription(subscription, subscription_plan, 1, amount, now)?;
+        emit_payment_upcoming(subscription, subscription_plan, now)?;
+
+        emit!(SubscriptionCreated {
+            subscriber: subscription.subscriber,
+            creator: subscription_plan.creator,
+            plan_id,
+            prepaid_cycles: 1,
+            timestamp: now,
+            setup_fee: 0,
+            created_at: subscription.created_at,
+            payment_mint: subscription.billing_mint(subscription_plan),
+            chosen_price: subscription.chosen_price,
+        });
+
+        Ok(())
+    }
+
+    /// Reclaim a pledge after the plan's funding round failed
+    /// 
+    /// Once `funding_deadline` has passed without reaching the threshold,
+    /// returns the escrow to the subscriber, cancels the pending
+    /// subscription and frees its seat. The first refund deactivates the
+    /// plan and emits `FundingFailed`.
+    pub fn refund_pledge(
+        ctx: Context<RefundPledge>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(
+            subscription_plan.is_funding() && now > subscription_plan.funding_deadline,
+            ErrorCode::FundingNotFailed
+        );
+        let amount = subscription.pledged_amount;
+        require!(amount > 0, ErrorCode::NoPledge);
+
+        let plan_key = subscription_plan.key();
+        let seeds = &[
+            b"pledge_vault".as_ref(),
+            plan_key.as_ref(),
+            &[ctx.bumps.pledge_vault],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.pledge_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.subscriber_token_account.to_account_info(),
+            authority: ctx.accounts.pledge_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        if subscription_plan.is_active {
+            subscription_plan.is_active = false;
+            emit!(FundingFailed {
+                creator: subscription_plan.creator,
+                plan_id,
+                pledges: subscription_plan.current_subscribers,
+                activation_threshold: subscription_plan.activation_threshold,
+                timestamp: now,
+            });
+        }
+
+        subscription.pledged_amount = 0;
+        transition_status(subscription, subscription_plan, SubscriptionStatus::Cancelled)?;
+        free_seat(subscription_plan, subscription)?;
+
+        emit!(PledgeRefunded {
+            subscriber: subscription.subscriber,
+            creator: subscription_plan.creator,
+            plan_id,
+            amount,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Require a memo on every payment (creator only)
+    /// 
+    /// When enabled, every instruction that charges the subscriber only
+    /// succeeds in a transaction that includes an SPL Memo instruction, for
+    /// creators whose accounting reconciles payments by memo. That covers
+    /// joining (`subscribe`, `confirm_subscription`, `follow_successor` into
+    /// this plan) and renewing (`process_payment` and its catch-up,
+    /// delegated, permit and prepaid variants, and `pay_all_due`).
+    pub fn set_require_memo(
+        ctx: Context<SetRequireMemo>,
+        _plan_id: u64,
+        require_memo: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.require_memo = require_memo;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Grow a subscription created under an older layout to the current size
+    /// 
+    /// Reallocates the account to `Subscription::LEN`, zero-initializing the
+    /// fields added since it was created, so older subscriptions can use new
+    /// features without cancelling and re-subscribing. `payer` (the
+    /// subscriber or a sponsor) tops up the rent. Fails with
+    /// `AccountAlreadyCurrent` for accounts already on the current layout;
+    /// accounts are never shrunk. Subscriptions that predate a stored
+    /// interval take it from their plan, passed as the only
+    /// `remaining_accounts` entry. `migrate_account` does the same for any
+    /// versioned account type.
+    pub fn migrate_subscription_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateSubscriptionAccount<'info>>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let info = ctx.accounts.subscription.to_account_info();
+        let (subscription, _, old_len) = migrate_versioned::<Subscription>(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            ctx.remaining_accounts,
+        )?;
+        require!(subscription.plan_id == plan_id, ErrorCode::InvalidSubscriptionAccount);
+
+        emit!(SubscriptionAccountMigrated {
+            subscriber: subscription.subscriber,
+            plan_id,
+            old_len: old_len as u32,
+            new_len: Subscription::LEN as u32,
+            timestamp: current_time()?,
+        });
+
+        Ok(())
+    }
+
+    /// Upgrade a plan or subscription to the current layout
+    ///
+    /// Detects the account type from its discriminator, grows it to the
+    /// current size and applies each schema version step in turn (see
+    /// `VersionedAccount`). Permissionless, since the upgrade only fills in
+    /// fields the account never had; `payer` tops up the rent. Accounts
+    /// below `MIN_SUPPORTED_SCHEMA_VERSION` are refused by other
+    /// instructions with `SchemaTooOld` until migrated.
+    /// 
+    /// Some v1 accounts need more through `remaining_accounts`:
+    /// - a subscription without a stored interval: its plan
+    /// - a plan without a payment mint: the creator (signer), the mint and
+    ///   the creator's payout token account in it
+    pub fn migrate_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateAccount<'info>>,
+    ) -> Result<()> {
+        let info = ctx.accounts.account.to_account_info();
+        let discriminator: [u8; 8] = info
+            .try_borrow_data()?
+            .get(..8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(ErrorCode::UnsupportedAccountType)?;
+
+        let (old_version, old_len) = if discriminator == SubscriptionPlan::DISCRIMINATOR {
+            let (_, version, len) = migrate_versioned::<SubscriptionPlan>(
+                &info,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                ctx.remaining_accounts,
+            )?;
+            (version, len)
+        } else if discriminator == Subscription::DISCRIMINATOR {
+            let (_, version, len) = migrate_versioned::<Subscription>(
+                &info,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                ctx.remaining_accounts,
+            )?;
+            (version, len)
+        } else {
+            return err!(ErrorCode::UnsupportedAccountType);
+        };
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            old_version,
+            new_version: CURRENT_SCHEMA_VERSION,
+            old_len: old_len as u32,
+            new_len: info.data_len() as u32,
+            timestamp: current_time()?,
+        });
+
+        Ok(())
+    }
+
+    /// Set the notice period a later `deactivate_plan` must give (creator only)
+    /// 
+    /// Up to `MAX_DEACTIVATION_NOTICE_SECONDS`; 0 deactivates immediately.
+    /// Doesn't move a deactivation that is already scheduled.
+    pub fn set_deactivation_notice(
+        ctx: Context<SetDeactivationNotice>,
+        _plan_id: u64,
+        notice_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            (0..=MAX_DEACTIVATION_NOTICE_SECONDS).contains(&notice_seconds),
+            ErrorCode::InvalidDeactivationNotice
+        );
+        subscription_plan.deactivation_notice_seconds = notice_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Call off a scheduled deactivation before it takes effect (creator only)
+    /// 
+    /// Also clears the successor given to `deactivate_plan`.
+    pub fn cancel_deactivation(
+        ctx: Context<CancelDeactivation>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            subscription_plan.deactivation_effective_at != 0
+                && now < subscription_plan.deactivation_effective_at,
+            ErrorCode::NoDeactivationScheduled
+        );
+        subscription_plan.deactivation_effective_at = 0;
+        subscription_plan.successor_plan_id = None;
+
+        emit!(DeactivationCancelled::for_plan(subscription_plan, now)?);
+
+        Ok(())
+    }
+
+    /// Accept a second token as a fallback payment method (creator only)
+    /// 
+    /// When a subscriber's primary balance can't cover a renewal,
+    /// `process_payment` charges `secondary_price` per cycle in
+    /// `secondary_mint` instead, into `secondary_payout_token_account`.
+    /// The mint must differ from the plan's `payment_mint` and be owned by
+    /// the same token program, since payments pass a single `token_program`.
+    pub fn set_secondary_payment(
+        ctx: Context<SetSecondaryPayment>,
+        _plan_id: u64,
+        secondary_price: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(secondary_price > 0, ErrorCode::InvalidPrice);
+        subscription_plan.secondary_payment_mint = Some(ctx.accounts.secondary_mint.key());
+        subscription_plan.secondary_payout_token_account =
+            ctx.accounts.secondary_payout_token_account.key();
+        subscription_plan.secondary_price = secondary_price;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Stop accepting the secondary token (creator only)
+    pub fn clear_secondary_payment(
+        ctx: Context<ClearSecondaryPayment>,
+        _plan_id: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            subscription_plan.secondary_payment_mint.is_some(),
+            ErrorCode::NoSecondaryPayment
+        );
+        subscription_plan.secondary_payment_mint = None;
+        subscription_plan.secondary_payout_token_account = Pubkey::default();
+        subscription_plan.secondary_price = 0;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Summarize a plan's health for dashboards (read-only)
+    /// 
+    /// Returns a Borsh-encoded `PlanHealth` via `set_return_data`:
+    /// utilization, status, days since the last payment and revenue to date,
+    /// so monitors can poll many plans without decoding each account.
+    /// Clients can call it with `.view()`.
+    pub fn plan_health(ctx: Context<PlanHealthQuery>, _plan_id: u64) -> Result<PlanHealth> {
+        Ok(PlanHealth::for_plan(&ctx.accounts.subscription_plan, current_time()?))
+    }
+
+    /// Reward tenure with a growing renewal discount (creator only)
+    /// 
+    /// Each full year since a subscription's `created_at` takes
+    /// `bps_per_year` off its recurring price, up to `max_bps`; both 0
+    /// disables it. `max_bps` may be at most 10_000 (free) and
+    /// `bps_per_year` no more than `max_bps`.
+    pub fn set_loyalty_discount(
+        ctx: Context<SetLoyaltyDiscount>,
+        _plan_id: u64,
+        bps_per_year: u16,
+        max_bps: u16,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            max_bps as u64 <= BPS_DENOMINATOR && bps_per_year <= max_bps,
+            ErrorCode::InvalidLoyaltyDiscount
+        );
+        subscription_plan.loyalty_discount_bps_per_year = bps_per_year;
+        subscription_plan.max_loyalty_discount_bps = max_bps;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Cap how long any subscription on the plan may run (creator only)
+    /// 
+    /// Once `max_lifetime_seconds` have passed since a subscription's
+    /// `created_at`, renewals are refused: `process_payment` ends the
+    /// subscription instead of charging and emits
+    /// `SubscriptionLifetimeReached`. The cycle already paid for runs out
+    /// normally. Must exceed one `interval_seconds`; 0 removes the cap.
+    pub fn set_max_lifetime(
+        ctx: Context<SetMaxLifetime>,
+        _plan_id: u64,
+        max_lifetime_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            max_lifetime_seconds == 0 || max_lifetime_seconds > subscription_plan.interval_seconds,
+            ErrorCode::InvalidMaxLifetime
+        );
+        subscription_plan.max_lifetime_seconds = max_lifetime_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Open or close the plan to new subscribers (creator only)
+    /// 
+    /// Unlike `pause_plan`, closing only stops joins (`subscribe`, reserve,
+    /// bundle, successor and comp grants fail with
+    /// `NewSubscriptionsDisabled`); existing subscribers keep renewing, so a
+    /// plan can be sunset gracefully. Independent of `is_paused` and
+    /// `is_active`.
+    pub fn set_new_subscriptions_enabled(
+        ctx: Context<SetNewSubscriptionsEnabled>,
+        plan_id: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.new_subscriptions_disabled = !enabled;
+
+        emit!(NewSubscriptionsToggled {
+            creator: subscription_plan.creator,
+            plan_id,
+            enabled,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Pay every due subscription a subscriber holds, across plans and creators
+    /// 
+    /// `remaining_accounts` holds, for each of up to `MAX_PAY_ALL_DUE`
+    /// subscriptions, `[subscription, plan, payment_mint,
+    /// subscriber_token_account, creator_token_account]`; subscriptions on
+    /// different mints just pass different token accounts, and one token
+    /// account may be repeated across groups. Each subscription is charged
+    /// like `process_payment` and gets its own `PaymentProcessed`.
+    /// Subscriptions that can't be charged right now (not yet due, inactive,
+    /// frozen, past grace) are skipped rather than failing the batch.
+    /// Returns the number of subscriptions charged.
+    pub fn pay_all_due<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PayAllDue<'info>>,
+    ) -> Result<u32> {
+        let subscriber = &ctx.accounts.subscriber;
+        let now = current_time()?;
+
+        let groups = ctx.remaining_accounts.chunks_exact(PAY_ALL_DUE_GROUP_LEN);
+        require!(
+            groups.remainder().is_empty() && (1..=MAX_PAY_ALL_DUE).contains(&groups.len()),
+            ErrorCode::InvalidPayAllDueAccounts
+        );
+
+        let mut charged = 0;
+        for accounts in groups {
+            let mut subscription = Account::<Subscription>::try_from(&accounts[0])?;
+            let mut subscription_plan = Account::<SubscriptionPlan>::try_from(&accounts[1])?;
+            subscription.check_address(accounts[0].key())?;
+            subscription_plan.check_address(accounts[1].key())?;
+            require_keys_eq!(subscription.subscriber, subscriber.key(), ErrorCode::InvalidSubscriber);
+            require!(
+                subscription.plan_id == subscription_plan.plan_id
+                    && subscription.creator == subscription_plan.creator,
+                ErrorCode::InvalidPlanId
+            );
+            require!(subscription.bundle_id.is_none(), ErrorCode::BundleMemberSubscription);
+            subscription.check_schema()?;
+            subscription_plan.check_schema()?;
+
+            if !is_payment_due(&subscription, &subscription_plan, now) {
+                continue;
+            }
+            let cycles = payable_cycles(&subscription, &subscription_plan, now)?;
+            check_memo(&subscription_plan, &ctx.accounts.instructions_sysvar)?;
+            let amount = renewal_charge(&subscription, &subscription_plan, cycles, now)?;
+            emit_if_final_prorated(&subscription, &subscription_plan, cycles, amount, now)?;
+
+            let payment_mint = InterfaceAccount::<Mint>::try_from(&accounts[2])?;
+            let subscriber_token_account = InterfaceAccount::<TokenAccount>::try_from(&accounts[3])?;
+            let creator_token_account = InterfaceAccount::<TokenAccount>::try_from(&accounts[4])?;
+            let billing_mint = subscription.billing_mint(&subscription_plan);
+            require_keys_eq!(payment_mint.key(), billing_mint, ErrorCode::MintMismatch);
+            require!(
+                subscription_plan.matches_decimals(&billing_mint, payment_mint.decimals),
+                ErrorCode::PaymentDecimalsMismatch
+            );
+            require_keys_eq!(subscriber_token_account.mint, payment_mint.key(), ErrorCode::MintMismatch);
+            require_keys_eq!(
+                *accounts[2].owner,
+                ctx.accounts.token_program.key(),
+                ErrorCode::WrongTokenProgram
+            );
+            require!(
+                subscription_plan.payout_account_for(&billing_mint) == Some(creator_token_account.key()),
+                ErrorCode::InvalidPayoutAccount
+            );
+            require!(
+                creator_token_account.key() != subscriber_token_account.key(),
+                ErrorCode::SelfPayment
+            );
+            require!(subscriber_token_account.amount >= amount, ErrorCode::InsufficientFunds);
+            check_funding_authority(&subscriber_token_account, subscriber.key(), amount)?;
+            if mark_payment_blocked(
+                &mut subscription,
+                &subscriber_token_account,
+                subscriber_token_account.key(),
+            ) {
+                emit!(PaymentBlockedFrozenAccount {
+                    subscriber: subscription.subscriber,
+                    plan_id: subscription.plan_id,
+                    token_account: subscriber_token_account.key(),
+                    amount,
+                    timestamp: now,
+                });
+                subscription.exit(&crate::ID)?;
+                continue;
+            }
+
+            let cpi_accounts = TransferChecked {
+                from: subscriber_token_account.to_account_info(),
+                mint: payment_mint.to_account_info(),
+                to: creator_token_account.to_account_info(),
+                authority: subscriber.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+            token_interface::transfer_checked(cpi_ctx, amount, payment_mint.decimals)?;
+
+            if cycles == 1 {
+                settle_cycle(&mut subscription, &mut subscription_plan, amount, now)?;
+            } else {
+                settle_cycles(&mut subscription, &mut subscription_plan, cycles, amount, now)?;
+            }
+            emit_payment_upcoming(&subscription, &subscription_plan, now)?;
+
+            emit!(PaymentProcessed {
+                subscriber: subscription.subscriber,
+                creator: subscription.creator,
+                plan_id: subscription.plan_id,
+                amount,
+                payment_number: subscription.total_payments,
+                total_paid_amount: subscription.total_paid_amount,
+                reference: subscription.reference,
+                timestamp: now,
+                payment_mint: payment_mint.key(),
+            });
+
+            subscription.exit(&crate::ID)?;
+            subscription_plan.exit(&crate::ID)?;
+            charged += 1;
+        }
+
+        Ok(charged)
+    }
+
+    /// Clear `payment_blocked` once the frozen token account is thawed
+    /// (subscriber only)
+    /// 
+    /// `token_account` must be the account recorded when the renewal was
+    /// blocked; fails with `TokenAccountFrozen` while it is still frozen.
+    /// A successful payment also clears the flag.
+    pub fn clear_payment_blocked(
+        ctx: Context<ClearPaymentBlocked>,
+        plan_id: u64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+
+        require!(subscription.payment_blocked, ErrorCode::PaymentNotBlocked);
+        require!(!ctx.accounts.token_account.is_frozen(), ErrorCode::TokenAccountFrozen);
+        subscription.payment_blocked = false;
+
+        emit!(PaymentBlockCleared {
+            subscriber: subscription.subscriber,
+            plan_id,
+            token_account: ctx.accounts.token_account.key(),
+            timestamp: current_time()?,
+        });
+
+        Ok(())
+    }
+
+    /// Point several of the creator's plans at new metadata in one go
+    /// 
+    /// Pass up to `MAX_METADATA_BATCH` writable plan accounts as
+    /// `remaining_accounts`. Each gets `new_metadata_uri` (max 200 chars, the
+    /// room every plan is allocated with) and, if given, `new_metadata_hash`,
+    /// emitting `SubscriptionPlanUpdated` per plan.
+    /// 
+    /// # Security
+    /// Every account must be a plan PDA created by the signer, or the whole
+    /// batch fails with `InvalidCreator` (`InvalidPlanAccount` for accounts
+    /// that aren't plans at all).
+    pub fn update_metadata_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateMetadataBatch<'info>>,
+        new_metadata_uri: String,
+        new_metadata_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let creator = &ctx.accounts.creator;
+        let now = current_time()?;
+
+        require!(
+            (1..=MAX_METADATA_BATCH).contains(&ctx.remaining_accounts.len()),
+            ErrorCode::TooManyAccounts
+        );
+        require!(new_metadata_uri.len() <= 200, ErrorCode::MetadataUriTooLong);
+
+        for account_info in ctx.remaining_accounts.iter() {
+            let mut subscription_plan = Account::<SubscriptionPlan>::try_from(account_info)?;
+            subscription_plan.check_address(account_info.key())?;
+            require_keys_eq!(subscription_plan.creator, creator.key(), ErrorCode::InvalidCreator);
+            subscription_plan.check_schema()?;
+
+            subscription_plan.metadata_uri = new_metadata_uri.clone();
+            if let Some(hash) = new_metadata_hash {
+                subscription_plan.metadata_hash = hash;
+            }
+            let event = SubscriptionPlanUpdated::settings_changed(&mut subscription_plan, now)?;
+            subscription_plan.exit(&crate::ID)?;
+
+            emit!(event);
+        }
+
+        Ok(())
+    }
+
+    /// Set how long subscribers must stay before cancelling (creator only)
+    /// 
+    /// Deters subscribe/cancel churn. At most one billing interval, so a
+    /// subscriber is never locked in past the cycle they paid for; 0 removes
+    /// the minimum. Applies to existing subscriptions too, counted from their
+    /// `created_at`.
+    pub fn set_min_subscription_duration(
+        ctx: Context<SetMinSubscriptionDuration>,
+        _plan_id: u64,
+        min_subscription_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(
+            min_subscription_seconds >= 0
+                && min_subscription_seconds <= subscription_plan.interval_seconds,
+            ErrorCode::InvalidMinSubscriptionDuration
+        );
+        subscription_plan.min_subscription_seconds = min_subscription_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Record the plan's subscriber count and revenue in an immutable snapshot (creator only)
+    /// 
+    /// Writes a `PlanSnapshot` PDA (`["plan_snapshot", plan, snapshot_index]`)
+    /// that governance programs can vote against. Snapshots are never
+    /// modified or closed; reusing a `snapshot_index` fails because the
+    /// account already exists.
+    pub fn snapshot_plan(
+        ctx: Context<SnapshotPlan>,
+        plan_id: u64,
+        snapshot_index: u64,
+    ) -> Result<()> {
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        ctx.accounts.plan_snapshot.set_inner(PlanSnapshot {
+            plan: subscription_plan.key(),
+            creator: subscription_plan.creator,
+            plan_id,
+            snapshot_index,
+            current_subscribers: subscription_plan.current_subscribers,
+            total_revenue: subscription_plan.accrued_revenue,
+            timestamp: now,
+            bump: ctx.bumps.plan_snapshot,
+        });
+
+        emit!(PlanSnapshotCreated {
+            creator: subscription_plan.creator,
+            plan_id,
+            snapshot_index,
+            current_subscribers: subscription_plan.current_subscribers,
+            total_revenue: subscription_plan.accrued_revenue,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Let subscribers choose to pay in another token (creator only)
+    /// 
+    /// Adds `mint` to the plan's `accepted_tokens` at `price` per cycle, paid
+    /// into `payout_token_account`, or updates its price and payout if it is
+    /// already listed. At most `MAX_ACCEPTED_TOKENS`; the plan's own
+    /// `payment_mint` can't be listed. Existing subscriptions keep the token
+    /// they chose and renew at its current price. Relisting a removed token
+    /// replaces the terms it was delisted with.
+    pub fn add_accepted_token(
+        ctx: Context<AddAcceptedToken>,
+        _plan_id: u64,
+        price: u64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(price > 0, ErrorCode::InvalidPrice);
+        let accepted = AcceptedToken {
+            mint: ctx.accounts.mint.key(),
+            price,
+            decimals: ctx.accounts.mint.decimals,
+            payout_token_account: ctx.accounts.payout_token_account.key(),
+        };
+        match subscription_plan
+            .accepted_tokens
+            .iter_mut()
+            .find(|token| token.mint == accepted.mint)
+        {
+            Some(token) => *token = accepted,
+            None => {
+                require!(
+                    subscription_plan.accepted_tokens.len() < MAX_ACCEPTED_TOKENS,
+                    ErrorCode::TooManyAcceptedTokens
+                );
+                subscription_plan.accepted_tokens.push(accepted);
+            }
+        }
+        subscription_plan.delisted_tokens.retain(|token| token.mint != accepted.mint);
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Stop accepting a token added with `add_accepted_token` (creator only)
+    /// 
+    /// New subscribers can no longer choose it. Subscriptions that already
+    /// did keep renewing in it at its last price and into its last payout
+    /// account, which move to `delisted_tokens`. Only the most recent
+    /// `MAX_ACCEPTED_TOKENS` removals are kept; subscriptions on an older
+    /// one can no longer renew.
+    pub fn remove_accepted_token(
+        ctx: Context<RemoveAcceptedToken>,
+        _plan_id: u64,
+        mint: Pubkey,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        let index = subscription_plan
+            .accepted_tokens
+            .iter()
+            .position(|token| token.mint == mint)
+            .ok_or(ErrorCode::PaymentTokenNotAccepted)?;
+        let removed = subscription_plan.accepted_tokens.remove(index);
+        if subscription_plan.delisted_tokens.len() == MAX_ACCEPTED_TOKENS {
+            subscription_plan.delisted_tokens.remove(0);
+        }
+        subscription_plan.delisted_tokens.push(removed);
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Let subscribers choose their own price above the plan's (creator only)
+    /// 
+    /// With the mode on, `price` becomes a floor: `subscribe` and
+    /// `update_chosen_price` take any price at or above it. Turning it off
+    /// stops new choices; existing subscribers keep the price they chose.
+    pub fn set_pay_what_you_want(
+        ctx: Context<SetPayWhatYouWant>,
+        _plan_id: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.pay_what_you_want = enabled;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Raise or lower a pay-what-you-want contribution (subscriber only)
+    /// 
+    /// `new_price` applies from the next charge and may not go below the
+    /// plan's current `price`.
+    pub fn update_chosen_price(
+        ctx: Context<UpdateChosenPrice>,
+        plan_id: u64,
+        new_price: u64,
+    ) -> Result<()> {
+        let subscription_plan = &ctx.accounts.subscription_plan;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = current_time()?;
+
+        require!(subscription.is_active, ErrorCode::SubscriptionInactive);
+        check_chosen_price(
+            subscription_plan,
+            &subscription.billing_mint(subscription_plan),
+            new_price,
+        )?;
+        let old_price = subscription.cycle_price(subscription_plan.price);
+        subscription.chosen_price = new_price;
+
+        emit!(ChosenPriceUpdated {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id,
+            old_price,
+            new_price,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    /// Reject cancels in the transaction that started the subscription (creator only)
+    /// 
+    /// Closes off atomic subscribe-then-cancel, which could otherwise collect
+    /// join-time incentives without ever holding the subscription. Covers
+    /// every way out: `cancel_subscription`, `self_refund`,
+    /// `cancel_with_prorated_refund`, `cancel_and_refund_prepaid`,
+    /// `cancel_bundle` and `follow_successor`.
+    pub fn set_block_same_tx_cancel(
+        ctx: Context<SetBlockSameTxCancel>,
+        _plan_id: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.block_same_tx_cancel = enabled;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Set how long payments are accepted after they fall due (creator only)
+    /// 
+    /// Either a fixed number of seconds or a number of billing cycles (see
+    /// `GraceWindow`); a fixed window is capped at `MAX_GRACE_SECONDS`.
+    /// Applies to existing subscriptions from their next check; a
+    /// subscription's `grace_override_seconds` still takes precedence.
+    pub fn set_grace_window(
+        ctx: Context<SetGraceWindow>,
+        _plan_id: u64,
+        grace_window: GraceWindow,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        if let GraceWindow::Seconds { seconds } = grace_window {
+            require!(
+                (0..=MAX_GRACE_SECONDS).contains(&seconds),
+                ErrorCode::InvalidGraceWindow
+            );
+        }
+        subscription_plan.grace_window = grace_window;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Set the minimum time between capacity changes (creator only)
+    /// 
+    /// `update_subscription_plan` then rejects a new `max_subscribers` within
+    /// `cooldown_seconds` of the previous change, keeping capacity steady for
+    /// waitlists and indexers. Automatic growth set up with `set_auto_expand`
+    /// isn't limited. 0 removes the cooldown. The cooldown can't be lowered
+    /// while it is still running after a change, so it can't be cleared to
+    /// slip in another one.
+    pub fn set_capacity_change_cooldown(
+        ctx: Context<SetCapacityChangeCooldown>,
+        _plan_id: u64,
+        cooldown_seconds: i64,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        require!(cooldown_seconds >= 0, ErrorCode::InvalidCapacityChangeCooldown);
+        if cooldown_seconds < subscription_plan.capacity_change_cooldown_seconds {
+            subscription_plan.check_capacity_change(now)?;
+        }
+        subscription_plan.capacity_change_cooldown_seconds = cooldown_seconds;
+
+        emit!(SubscriptionPlanUpdated::settings_changed(
+            subscription_plan,
+            now,
+        )?);
+
+        Ok(())
+    }
+
+    /// Mark a plan as verified, or clear the mark (program admin only)
+    /// 
+    /// A curation flag for marketplaces surfacing trusted plans. Creators
+    /// can't set it, `update_subscription_plan` leaves it alone, and clones
+    /// start unverified. Emits `PlanVerificationChanged` naming the admin.
+    pub fn set_plan_verified(
+        ctx: Context<SetPlanVerified>,
+        _plan_id: u64,
+        verified: bool,
+    ) -> Result<()> {
+        let subscription_plan = &mut ctx.accounts.subscription_plan;
+        let now = current_time()?;
+
+        subscription_plan.verified = verified;
+
+        emit!(PlanVerificationChanged {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            admin: ctx.accounts.admin.key(),
+            verified,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Current unix time, read through this one function so tests can control it.
+///
+/// On-chain this is the `Clock` sysvar. Unit tests have no sysvar, so under
+/// `cfg(test)` it returns the per-thread value set with `set_test_time`.
+/// Builds with the `test-clock` feature return the `TestClock` account's time
+/// when the instruction was handed one (see `entry_with_test_clock`) and fall
+/// back to the sysvar otherwise.
+#[cfg(not(any(test, feature = "test-clock")))]
+pub fn current_time() -> Result<i64> {
+    Ok(Clock::get()?.unix_timestamp)
+}
+
+#[cfg(any(test, feature = "test-clock"))]
+thread_local! {
+    static TEST_TIME: std::cell::Cell<Option<i64>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(any(test, feature = "test-clock"))]
+pub fn current_time() -> Result<i64> {
+    match TEST_TIME.with(|time| time.get()) {
+        Some(now) => Ok(now),
+        None if cfg!(test) => Ok(0),
+        None => Ok(Clock::get()?.unix_timestamp),
+    }
+}
+
+/// Set the time `current_time` returns on this test thread
+#[cfg(test)]
+pub fn set_test_time(now: i64) {
+    TEST_TIME.with(|time| time.set(Some(now)));
+}
+
+/// Clock override read by `test-clock` builds
+///
+/// Lives at the `[b"test_clock"]` PDA of this program. No instruction writes
+/// it: the test harness creates and warps it directly, e.g. with
+/// `ProgramTestContext::set_account` or a validator `--account` fixture.
+#[cfg(feature = "test-clock")]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TestClock {
+    /// Time `current_time` returns while this account is passed
+    pub unix_timestamp: i64,
+}
+
+/// Split a trailing `TestClock` account off an instruction's accounts
+///
+/// Returns the clock's time, if the last account is the program's
+/// `TestClock` PDA, together with the accounts the instruction itself takes.
+#[cfg(feature = "test-clock")]
+pub fn split_test_clock<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+) -> Result<(Option<i64>, &'a [AccountInfo<'info>])> {
+    let (test_clock_key, _) = Pubkey::find_program_address(&[b"test_clock"], program_id);
+    match accounts.split_last() {
+        Some((last, rest)) if last.key() == test_clock_key && last.owner == program_id => {
+            let clock = TestClock::deserialize(&mut &last.try_borrow_data()?[..])?;
+            Ok((Some(clock.unix_timestamp), rest))
+        }
+        _ => Ok((None, accounts)),
+    }
+}
+
+/// Entrypoint for `test-clock` builds run natively under `solana-program-test`
+///
+/// Register this in place of `entry` (`processor!(circulum::entry_with_test_clock)`).
+/// Appending the `TestClock` account to any instruction pins `current_time`
+/// to its value for that instruction; the account is removed before
+/// dispatch, so it never shows up in `remaining_accounts`.
+#[cfg(feature = "test-clock")]
+pub fn entry_with_test_clock<'info>(
+    program_id: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+    data: &[u8],
+) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+    let (now, accounts) = split_test_clock(program_id, accounts)?;
+    TEST_TIME.with(|time| time.set(now));
+    entry(program_id, accounts, data)
+}
+
+/// How long after `next_payment` a payment is still accepted (7 days)
+pub const GRACE_PERIOD_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// Longest grace a creator can negotiate for one subscription (90 days)
+pub const MAX_GRACE_SECONDS: i64 = 90 * 24 * 60 * 60;
+
+/// Early tolerance may be at most `interval_seconds / MAX_EARLY_TOLERANCE_DIVISOR`
+pub const MAX_EARLY_TOLERANCE_DIVISOR: i64 = 4;
+
+/// Compute the next due date after a payment made at `now`.
+///
+/// The schedule is anchored to the previous due date (`scheduled + interval`)
+/// rather than to `now`, so paying early, on time, or late within grace never
+/// shifts the billing cadence. If the anchored date would still be more than
+/// one interval behind `now`, whole intervals are skipped so the cadence is
+/// kept while the subscription is never left more than one cycle in arrears.
+pub fn next_payment_after(scheduled: i64, now: i64, interval_seconds: i64) -> Result<i64> {
+    require!(interval_seconds > 0, ErrorCode::IntervalTooShort);
+    let anchored = scheduled
+        .checked_add(interval_seconds)
+        .ok_or(ErrorCode::Overflow)?;
+    let floor = now
+        .checked_sub(interval_seconds)
+        .ok_or(ErrorCode::Underflow)?;
+    if anchored >= floor {
+        return Ok(anchored);
+    }
+
+    let behind = floor.checked_sub(anchored).ok_or(ErrorCode::Underflow)?;
+    let skipped = behind
+        .checked_add(interval_seconds - 1)
+        .ok_or(ErrorCode::Overflow)?
+        / interval_seconds;
+    skipped
+        .checked_mul(interval_seconds)
+        .and_then(|offset| anchored.checked_add(offset))
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Number of billing cycles due at `now` starting from `next_payment`,
+/// capped at `max_cycles`.
+pub fn due_cycles(next_payment: i64, now: i64, interval_seconds: i64, max_cycles: u32) -> Result<u32> {
+    require!(interval_seconds > 0, ErrorCode::IntervalTooShort);
+    if now < next_payment {
+        return Ok(0);
+    }
+    let elapsed = now.checked_sub(next_payment).ok_or(ErrorCode::Underflow)?;
+    let due = (elapsed / interval_seconds)
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(due.min(max_cycles as i64) as u32)
+}
+
+/// Upper bound on cycles charged upfront at `subscribe`
+pub const MAX_PREPAY_CYCLES_ON_JOIN: u32 = 36;
+
+/// Upper bound on `max_debt_cycles` for `Accumulate` plans
+pub const MAX_DEBT_CYCLES: u32 = 36;
+
+/// Most tokens a plan accepts besides its `payment_mint`
+pub const MAX_ACCEPTED_TOKENS: usize = 3;
+
+/// Default cap on cycles a subscriber may hold in the prepaid vault
+pub const DEFAULT_MAX_PREPAID_CYCLES: u32 = 12;
+
+/// Bounds for `reservation_seconds` when reservations are enabled
+pub const MIN_RESERVATION_SECONDS: i64 = 60;
+pub const MAX_RESERVATION_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Most subscriptions `cancel_all_subscribers` handles per transaction
+pub const MAX_BULK_CANCEL: usize = 20;
+
+/// Upper bound on `interval_count` for day and month units
+pub const MAX_INTERVAL_COUNT: u32 = 366;
+
+/// Reminder lead time new plans start with (3 days)
+pub const DEFAULT_REMINDER_LEAD_SECONDS: i64 = 3 * SECONDS_PER_DAY;
+
+/// Bounds on the number of member plans in a bundle
+pub const MIN_BUNDLE_PLANS: usize = 2;
+pub const MAX_BUNDLE_PLANS: usize = 5;
+
+/// Latest `billing_anchor_day`, so the anchor exists in every month
+pub const MAX_BILLING_ANCHOR_DAY: u8 = 28;
+
+/// Most plans `update_metadata_batch` updates in one transaction
+pub const MAX_METADATA_BATCH: usize = 10;
+
+/// Most subscriptions `pay_all_due` settles in one transaction
+pub const MAX_PAY_ALL_DUE: usize = 4;
+/// Accounts per subscription in `pay_all_due`'s `remaining_accounts`
+pub const PAY_ALL_DUE_GROUP_LEN: usize = 5;
+
+/// Length of a tenure year for loyalty discounts
+pub const SECONDS_PER_YEAR: i64 = 365 * SECONDS_PER_DAY;
+
+/// Basis-point denominator: 10_000 bps = 100%
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Longest maintenance window a plan can schedule
+pub const MAX_MAINTENANCE_SECONDS: i64 = 7 * SECONDS_PER_DAY;
+
+/// Longest notice a creator can promise before a deactivation takes effect
+pub const MAX_DEACTIVATION_NOTICE_SECONDS: i64 = 365 * SECONDS_PER_DAY;
+
+/// Layout version written to new plans and subscriptions. Accounts created
+/// before `schema_version` existed read it as 0 and are treated as version 1.
+pub const CURRENT_SCHEMA_VERSION: u8 = 3;
+/// Oldest layout instructions accept; older accounts must be migrated first
+pub const MIN_SUPPORTED_SCHEMA_VERSION: u8 = 2;
+
+/// SPL Memo program ids (v2 and legacy v1) accepted by `require_memo`
+pub const MEMO_PROGRAM_IDS: [Pubkey; 2] = [
+    anchor_lang::solana_program::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"),
+    anchor_lang::solana_program::pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo"),
+];
+
+/// Prefix of every message signed for `process_payment_with_permit`, so a
+/// permit signature can't be mistaken for any other signed message
+pub const PERMIT_DOMAIN: &[u8] = b"circulum:payment-permit:v1";
+
+/// Offset of the first signature-offsets entry in ed25519 program data,
+/// after the signature count and a padding byte
+const ED25519_OFFSETS_START: usize = 2;
+/// Size of one ed25519 signature-offsets entry: seven little-endian u16s
+const ED25519_OFFSETS_LEN: usize = 14;
+
+/// Day of the week for a unix timestamp in UTC, with Monday = 0 ... Sunday = 6.
+///
+/// The unix epoch (1970-01-01) was a Thursday.
+pub fn weekday(timestamp: i64) -> u8 {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    ((days + 3).rem_euclid(7)) as u8
+}
+
+/// Push a Saturday/Sunday (UTC) timestamp to the following Monday,
+/// keeping the time of day. Weekday timestamps are returned unchanged.
+pub fn next_business_day(timestamp: i64) -> Result<i64> {
+    let days_to_add = match weekday(timestamp) {
+        5 => 2, // Saturday
+        6 => 1, // Sunday
+        _ => 0,
+    };
+    timestamp
+        .checked_add(days_to_add * SECONDS_PER_DAY)
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (UTC).
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let shifted_month = (month as i64 + 9) % 12; // March = 0
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// `(year, month, day)` for a count of days since 1970-01-01 (UTC).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = (if days >= 0 { days } else { days - 146_096 }) / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // March = 0
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = (if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 }) as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Earliest timestamp at or after `timestamp` that falls on day `anchor_day`
+/// of a month (UTC), keeping the time of day.
+pub fn next_anchor_day(timestamp: i64, anchor_day: u8) -> Result<i64> {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let time_of_day = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let (year, month) = match (day <= anchor_day as u32, month) {
+        (true, _) => (year, month),
+        (false, 12) => (year + 1, 1),
+        (false, _) => (year, month + 1),
+    };
+
+    days_from_civil(year, month, anchor_day as u32)
+        .checked_mul(SECONDS_PER_DAY)
+        .and_then(|t| t.checked_add(time_of_day))
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Add calendar months to a timestamp in UTC, keeping the time of day.
+///
+/// A day of month past the end of the target month is clamped to its last
+/// day (Jan 31 + 1 month = Feb 28, or Feb 29 in a leap year).
+pub fn add_months(timestamp: i64, months: u32) -> Result<i64> {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let time_of_day = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let month_index = (year * 12 + month as i64 - 1)
+        .checked_add(months as i64)
+        .ok_or(ErrorCode::Overflow)?;
+    let new_year = month_index.div_euclid(12);
+    let new_month = (month_index.rem_euclid(12) + 1) as u32;
+    let new_day = day.min(days_in_month(new_year, new_month));
+
+    days_from_civil(new_year, new_month, new_day)
+        .checked_mul(SECONDS_PER_DAY)
+        .and_then(|t| t.checked_add(time_of_day))
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Ensure a plan can take a new subscriber: active, not paused and not full.
+///
+/// A full plan with auto-expansion enabled grows by `auto_expand_step` (up to
+/// `hard_cap`) and emits `CapacityAutoExpanded`. Otherwise a full plan logs
+/// `PlanCapacityReached` before failing with `PlanFull`.
+///
+/// When several conditions fail, the one reported follows `join_rejection`'s
+/// precedence. Every rejection logs `SubscribeRejected` with the plan's full
+/// state, so clients can explain it even though the transaction fails.
+pub fn check_can_join(subscription_plan: &mut SubscriptionPlan, now: i64) -> Result<()> {
+    if let Some(reason) = join_rejection(subscription_plan, now) {
+        if reason == SubscribeRejectReason::PlanFull {
+            // Logged even though the transaction fails, so clients can back off
+            emit!(PlanCapacityReached {
+                creator: subscription_plan.creator,
+                plan_id: subscription_plan.plan_id,
+                current_subscribers: subscription_plan.current_subscribers,
+                max_subscribers: subscription_plan.max_subscribers,
+                timestamp: now,
+            });
+        }
+        emit!(SubscribeRejected {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            reason,
+            is_active: subscription_plan.is_active_at(now),
+            is_paused: subscription_plan.is_paused,
+            current_subscribers: subscription_plan.current_subscribers,
+            max_subscribers: subscription_plan.max_subscribers,
+            timestamp: now,
+        });
+        return Err(reason.error().into());
+    }
+    if subscription_plan.current_subscribers >= subscription_plan.max_subscribers {
+        if let Some(new_max) = expanded_capacity(subscription_plan) {
+            emit!(CapacityAutoExpanded {
+                creator: subscription_plan.creator,
+                plan_id: subscription_plan.plan_id,
+                old_max_subscribers: subscription_plan.max_subscribers,
+                new_max_subscribers: new_max,
+                hard_cap: subscription_plan.hard_cap,
+                timestamp: now,
+            });
+            subscription_plan.max_subscribers = new_max;
+        }
+    }
+    Ok(())
+}
+
+/// Why a plan can't take a new subscriber, if it can't.
+///
+/// Checked in a fixed order so overlapping conditions always report the
+/// same reason: plan inactive, then plan paused, then new subscriptions
+/// disabled, then an open funding round (join with `pledge` instead), then
+/// capacity (a full plan that can still auto-expand isn't rejected).
+pub fn join_rejection(subscription_plan: &SubscriptionPlan, now: i64) -> Option<SubscribeRejectReason> {
+    if !subscription_plan.is_active_at(now) {
+        Some(SubscribeRejectReason::PlanInactive)
+    } else if subscription_plan.is_paused {
+        Some(SubscribeRejectReason::PlanPaused)
+    } else if !subscription_plan.new_subscriptions_enabled() {
+        Some(SubscribeRejectReason::NewSubscriptionsDisabled)
+    } else if subscription_plan.is_funding() {
+        Some(SubscribeRejectReason::FundingInProgress)
+    } else if subscription_plan.current_subscribers >= subscription_plan.max_subscribers
+        && expanded_capacity(subscription_plan).is_none()
+    {
+        Some(SubscribeRejectReason::PlanFull)
+    } else {
+        None
+    }
+}
+
+/// Capacity a full plan auto-expands to, or `None` if expansion is disabled
+/// or `hard_cap` has been reached.
+pub fn expanded_capacity(subscription_plan: &SubscriptionPlan) -> Option<u32> {
+    let current_max = subscription_plan.max_subscribers;
+    if subscription_plan.auto_expand_step == 0 || current_max >= subscription_plan.hard_cap {
+        return None;
+    }
+    Some(
+        current_max
+            .saturating_add(subscription_plan.auto_expand_step)
+            .min(subscription_plan.hard_cap),
+    )
+}
+
+/// Count a new subscriber against the plan's capacity.
+///
+/// Emits `PlanSoldOut` when this seat fills the plan. Callers run
+/// `check_can_join` first, so the count is always below `max_subscribers`
+/// here and the event fires exactly once per transition to full.
+pub fn take_seat(subscription_plan: &mut SubscriptionPlan, now: i64) -> Result<()> {
+    subscription_plan.current_subscribers = subscription_plan.current_subscribers
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    if subscription_plan.current_subscribers == subscription_plan.max_subscribers {
+        emit!(PlanSoldOut {
+            creator: subscription_plan.creator,
+            plan_id: subscription_plan.plan_id,
+            max_subscribers: subscription_plan.max_subscribers,
+            timestamp: now,
+        });
+    }
+    Ok(())
+}
+
+/// Release the seat held by `subscription`, keeping the comp count in step.
+pub fn free_seat(subscription_plan: &mut SubscriptionPlan, subscription: &Subscription) -> Result<()> {
+    subscription_plan.current_subscribers = subscription_plan.current_subscribers
+        .checked_sub(1)
+        .ok_or(ErrorCode::Underflow)?;
+    if subscription.is_comp {
+        subscription_plan.comp_subscribers = subscription_plan.comp_subscribers
+            .checked_sub(1)
+            .ok_or(ErrorCode::Underflow)?;
+    }
+    Ok(())
+}
+
+/// Validate a pay-what-you-want price: the plan must allow it, and it must
+/// be in the plan's own mint and at least its `price`
+pub fn check_chosen_price(
+    subscription_plan: &SubscriptionPlan,
+    payment_mint: &Pubkey,
+    chosen_price: u64,
+) -> Result<()> {
+    require!(subscription_plan.pay_what_you_want, ErrorCode::PayWhatYouWantDisabled);
+    require_keys_eq!(*payment_mint, subscription_plan.payment_mint, ErrorCode::PaymentTokenNotAccepted);
+    require!(chosen_price >= subscription_plan.price, ErrorCode::ChosenPriceBelowFloor);
+    Ok(())
+}
+
+/// Cycles and token amount charged when a subscription starts, at `price` per cycle
+pub fn initial_charge(subscription_plan: &SubscriptionPlan, price: u64) -> Result<(u32, u64)> {
+    let cycles = subscription_plan.cycles_on_join();
+    let amount = price
+        .checked_mul(cycles as u64)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok((cycles, amount))
+}
+
+/// Deterministic offset in `[0, jitter_seconds)` for `subscriber`'s first
+/// due date, spreading subscribers who join together across the interval.
+pub fn subscriber_jitter(subscriber: &Pubkey, jitter_seconds: i64) -> i64 {
+    if jitter_seconds <= 0 {
+        return 0;
+    }
+    let digest = anchor_lang::solana_program::hash::hashv(&[b"jitter", subscriber.as_ref()]);
+    let mut seed = [0u8; 8];
+    seed.copy_from_slice(&digest.to_bytes()[..8]);
+    (u64::from_le_bytes(seed) % jitter_seconds as u64) as i64
+}
+
+/// Setup fee owed when `subscription` (re)joins: the plan's fee unless it
+/// has already been paid on this subscription.
+pub fn setup_fee_due(subscription: &Subscription, subscription_plan: &SubscriptionPlan) -> u64 {
+    if subscription.setup_fee_paid {
+        0
+    } else {
+        subscription_plan.setup_fee
+    }
+}
+
+/// Mark a subscription active after its initial charge of `prepaid_cycles`
+/// (`initial_amount` tokens), scheduling the first recurring payment.
+pub fn activate_subscription(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    prepaid_cycles: u32,
+    initial_amount: u64,
+    now: i64,
+) -> Result<()> {
+    subscription.interval_seconds = subscription_plan.interval_seconds;
+    subscription.interval_unit = subscription_plan.interval_unit;
+    subscription.interval_count = subscription_plan.interval_count;
+
+    // New and Pending subscriptions aren't counted in any status yet
+    subscription_plan.count_status_change(None, SubscriptionStatus::Active)?;
+    subscription.set_status(SubscriptionStatus::Active);
+    subscription.last_payment = now;
+    subscription.next_payment = subscription_plan.billing_date(
+        subscription.add_intervals(now, prepaid_cycles)?
+            .checked_add(subscriber_jitter(&subscription.subscriber, subscription_plan.jitter_seconds))
+            .ok_or(ErrorCode::Overflow)?,
+    )?;
+    require!(
+        subscription.ends_at == 0 || subscription.ends_at > subscription.next_payment,
+        ErrorCode::InvalidEndDate
+    );
+    subscription.total_payments = prepaid_cycles as u64; // Upfront cycles count
+    subscription.join_cycles = prepaid_cycles;
+    subscription.total_paid_amount = 0;
+    subscription.record_cycle_amount(initial_amount, prepaid_cycles);
+    subscription_plan.record_revenue(initial_amount, now)?;
+    subscription.record_payment(initial_amount)
+}
+
+/// Deactivate a fixed-term subscription whose end date has passed and free
+/// its seat on the plan.
+pub fn end_subscription(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    transition_status(subscription, subscription_plan, SubscriptionStatus::Expired)?;
+    free_seat(subscription_plan, subscription)?;
+
+    emit!(SubscriptionEnded {
+        subscriber: subscription.subscriber,
+        creator: subscription.creator,
+        plan_id: subscription.plan_id,
+        ends_at: subscription.ends_at,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// End a subscription that has reached its plan's `max_lifetime_seconds`,
+/// as `end_subscription` does for a fixed term.
+pub fn end_at_lifetime(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    end_subscription(subscription, subscription_plan, now)?;
+
+    emit!(SubscriptionLifetimeReached {
+        subscriber: subscription.subscriber,
+        creator: subscription.creator,
+        plan_id: subscription.plan_id,
+        created_at: subscription.created_at,
+        max_lifetime_seconds: subscription_plan.max_lifetime_seconds,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Last moment a subscription's outstanding payment is accepted: its due
+/// date plus grace, extended by any unpaid-for maintenance window that has
+/// started by `now`.
+pub fn payment_deadline(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<i64> {
+    let maintenance_shift = subscription_plan.maintenance_shift(subscription, now)?;
+    subscription.next_payment
+        .checked_add(subscription.grace_period(subscription_plan)?)
+        .and_then(|t| t.checked_add(maintenance_shift))
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Validate that a single-cycle payment may be taken now: due (allowing the
+/// plan's early tolerance), within grace, and both subscription and plan
+/// billable.
+pub fn check_payment_due(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    // Verify payment is due (allowing the plan's early tolerance)
+    let earliest_payment_time = subscription.next_payment
+        .checked_sub(subscription_plan.early_tolerance_seconds)
+        .ok_or(ErrorCode::Underflow)?;
+    require!(now >= earliest_payment_time, ErrorCode::PaymentNotDue);
+
+    // Verify payment isn't too late (past due by no more than grace)
+    let max_payment_time = payment_deadline(subscription, subscription_plan, now)?;
+    require!(now <= max_payment_time, ErrorCode::PaymentTooLate);
+
+    check_billable(subscription, subscription_plan, now)
+}
+
+/// Validate that subscription and plan are in a state that can be charged:
+/// live, not frozen, and the plan active, unpaused and out of maintenance.
+pub fn check_billable(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    require!(
+        subscription.is_active && subscription.status.is_live(),
+        ErrorCode::SubscriptionInactive
+    );
+    require!(!subscription.is_frozen, ErrorCode::SubscriptionFrozen);
+    require!(
+        !subscription.lifetime_reached(subscription_plan.max_lifetime_seconds, now),
+        ErrorCode::LifetimeCapReached
+    );
+    require!(subscription_plan.is_active_at(now), ErrorCode::PlanInactive);
+    require!(!subscription_plan.is_paused, ErrorCode::PlanPaused);
+    require!(
+        !subscription_plan.in_maintenance(now),
+        ErrorCode::PlanUnderMaintenance
+    );
+    Ok(())
+}
+
+/// Cycles `process_payment` would charge now: one when due within grace, or
+/// on `Accumulate` plans past grace, every missed cycle up to
+/// `max_debt_cycles`.
+pub fn payable_cycles(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<u32> {
+    if subscription_plan.past_grace_policy == PastGracePolicy::Accumulate
+        && now > payment_deadline(subscription, subscription_plan, now)?
+    {
+        check_billable(subscription, subscription_plan, now)?;
+        let cycles = subscription.cycles_due(now, u32::MAX)?;
+        require!(cycles <= subscription_plan.max_debt_cycles, ErrorCode::MaxDebtExceeded);
+        return Ok(cycles);
+    }
+    check_payment_due(subscription, subscription_plan, now)?;
+    Ok(1)
+}
+
+/// Record one cycle paid with `amount` and schedule the next payment.
+pub fn settle_cycle(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    // Subscribers whose billing was blocked by maintenance get the
+    // window length added to both their grace deadline and schedule
+    let maintenance_shift = subscription_plan.maintenance_shift(subscription, now)?;
+
+    subscription.last_payment = now;
+    subscription.next_payment = subscription_plan.billing_date(
+        subscription.next_due_after(subscription.next_payment, now)?
+            .checked_add(maintenance_shift)
+            .ok_or(ErrorCode::Overflow)?,
+    )?;
+    if maintenance_shift > 0 {
+        subscription.maintenance_credited_until = subscription_plan.maintenance_end;
+    }
+    subscription.total_payments = subscription.total_payments
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    transition_status(subscription, subscription_plan, SubscriptionStatus::Active)?;
+    subscription.payment_blocked = false;
+    subscription.record_cycle_amount(amount, 1);
+    subscription_plan.record_revenue(amount, now)?;
+    subscription.record_payment(amount)
+}
+
+/// Record `cycles` consecutive cycles paid with `amount` in total, moving
+/// `next_payment` forward by exactly that many intervals, plus any
+/// maintenance shift owed as in `settle_cycle`.
+pub fn settle_cycles(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    cycles: u32,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let maintenance_shift = subscription_plan.maintenance_shift(subscription, now)?;
+
+    subscription.last_payment = now;
+    subscription.next_payment = subscription_plan.billing_date(
+        subscription.add_intervals(subscription.next_payment, cycles)?
+            .checked_add(maintenance_shift)
+            .ok_or(ErrorCode::Overflow)?,
+    )?;
+    if maintenance_shift > 0 {
+        subscription.maintenance_credited_until = subscription_plan.maintenance_end;
+    }
+    subscription.total_payments = subscription.total_payments
+        .checked_add(cycles as u64)
+        .ok_or(ErrorCode::Overflow)?;
+    transition_status(subscription, subscription_plan, SubscriptionStatus::Active)?;
+    subscription.payment_blocked = false;
+    subscription.record_cycle_amount(amount, cycles);
+    subscription_plan.record_revenue(amount, now)?;
+    subscription.record_payment(amount)
+}
+
+/// Amount the next single-cycle charge on `subscription` will take.
+///
+/// Every recurring charge is priced here (via `renewal_charge`) so
+/// `effective_next_price` and the payment instructions can't disagree.
+/// Pricing rules layer into this function: comp subscriptions renew free,
+/// others pay the plan's current price in the subscription's
+/// `billing_mint` (or their higher `chosen_price` on pay-what-you-want
+/// plans).
+pub fn effective_price(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<u64> {
+    if subscription.is_comp {
+        return Ok(0);
+    }
+    let price = subscription_plan
+        .renewal_price_in(&subscription.billing_mint(subscription_plan))
+        .ok_or(ErrorCode::PaymentTokenNotAccepted)?;
+    let discount_bps = loyalty_discount_bps(subscription, subscription_plan, now);
+    apply_discount_bps(subscription.cycle_price(price), discount_bps)
+}
+
+/// Amount a renewal of `cycles` cycles from `next_payment` will take.
+///
+/// Each cycle costs `effective_price`, except a last cycle that runs past the
+/// subscription's `hard_end`: that one is charged only for the time left
+/// before the hard end, with the same rounding as `prorated_refund`. The
+/// subscription then ends at the first crank on or after the hard end.
+pub fn renewal_charge(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    cycles: u32,
+    now: i64,
+) -> Result<u64> {
+    let price = effective_price(subscription, subscription_plan, now)?;
+    let full = price.checked_mul(cycles as u64).ok_or(ErrorCode::Overflow)?;
+    let Some(hard_end) = subscription.hard_end(subscription_plan.max_lifetime_seconds) else {
+        return Ok(full);
+    };
+    if cycles == 0 {
+        return Ok(0);
+    }
+    let last_start = subscription.add_intervals(subscription.next_payment, cycles - 1)?;
+    let last_end = subscription.add_intervals(last_start, 1)?;
+    if hard_end >= last_end {
+        return Ok(full);
+    }
+    let last = prorated_refund(price, hard_end, last_start, last_end - last_start)?;
+    price
+        .checked_mul((cycles - 1) as u64)
+        .and_then(|earlier| earlier.checked_add(last))
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Emit `FinalPaymentProrated` if `amount`, the `renewal_charge` for
+/// `cycles`, was cut short by the subscription's hard end.
+pub fn emit_if_final_prorated(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    cycles: u32,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let full_amount = effective_price(subscription, subscription_plan, now)?
+        .checked_mul(cycles as u64)
+        .ok_or(ErrorCode::Overflow)?;
+    if amount < full_amount {
+        emit!(FinalPaymentProrated {
+            subscriber: subscription.subscriber,
+            creator: subscription.creator,
+            plan_id: subscription.plan_id,
+            full_amount,
+            amount,
+            ends_at: subscription
+                .hard_end(subscription_plan.max_lifetime_seconds)
+                .unwrap_or_default(),
+            timestamp: now,
+        });
+    }
+    Ok(())
+}
+
+/// Loyalty discount a subscription has earned: `loyalty_discount_bps_per_year`
+/// for each full year since `created_at`, up to `max_loyalty_discount_bps`.
+/// Subscriptions from before `created_at` was recorded earn none.
+pub fn loyalty_discount_bps(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> u16 {
+    if subscription.created_at == 0 || now <= subscription.created_at {
+        return 0;
+    }
+    let years = (now - subscription.created_at) / SECONDS_PER_YEAR;
+    let earned = (subscription_plan.loyalty_discount_bps_per_year as i64).saturating_mul(years);
+    earned.min(subscription_plan.max_loyalty_discount_bps as i64) as u16
+}
+
+/// `price` less `discount_bps`, rounding the discount down so the creator
+/// never receives less than the exact discounted amount
+pub fn apply_discount_bps(price: u64, discount_bps: u16) -> Result<u64> {
+    require!(discount_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidLoyaltyDiscount);
+    let discount = (price as u128 * discount_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+    Ok(price - discount)
+}
+
+/// Most a subscription may hold prepaid: `price * max_prepaid_cycles`.
+pub fn prepaid_cap(subscription_plan: &SubscriptionPlan) -> Result<u64> {
+    subscription_plan.price
+        .checked_mul(subscription_plan.max_prepaid_cycles as u64)
+        .ok_or_else(|| error!(ErrorCode::Overflow))
+}
+
+/// Prepaid balance after depositing `amount`, rejecting anything above the
+/// plan's cap.
+pub fn prepaid_balance_after_deposit(
+    subscription_plan: &SubscriptionPlan,
+    prepaid_balance: u64,
+    amount: u64,
+) -> Result<u64> {
+    let new_balance = prepaid_balance
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(
+        new_balance <= prepaid_cap(subscription_plan)?,
+        ErrorCode::PrepayLimitExceeded
+    );
+    Ok(new_balance)
+}
+
+/// Status a live subscription should be in at `now`. Cancelled, expired and
+/// pending subscriptions only change through their own instructions.
+pub fn derive_status(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<SubscriptionStatus> {
+    if !subscription.status.is_live() || subscription.is_frozen {
+        return Ok(subscription.status);
+    }
+    if subscription.has_ended(now) {
+        return Ok(SubscriptionStatus::Expired);
+    }
+    if now > payment_deadline(subscription, subscription_plan, now)? {
+        // Accumulate plans keep an overdue subscription until its debt
+        // outgrows `max_debt_cycles`
+        let within_debt = subscription_plan.past_grace_policy == PastGracePolicy::Accumulate
+            && subscription.cycles_due(now, u32::MAX)? <= subscription_plan.max_debt_cycles;
+        if !within_debt {
+            return Ok(SubscriptionStatus::Expired);
+        }
+        return Ok(SubscriptionStatus::Grace);
+    }
+    if subscription_plan.is_paused {
+        return Ok(SubscriptionStatus::Paused);
+    }
+    if now > subscription.next_payment {
+        return Ok(SubscriptionStatus::Grace);
+    }
+    Ok(SubscriptionStatus::Active)
+}
+
+/// Unused share of a paid period: `price * (next_payment - now) / interval`,
+/// zero once `now >= next_payment`. Computed in u128 so the product cannot
+/// overflow; a result above `u64::MAX` is reported as `Overflow`.
+pub fn prorated_refund(price: u64, next_payment: i64, now: i64, interval_seconds: i64) -> Result<u64> {
+    require!(interval_seconds > 0, ErrorCode::IntervalTooShort);
+    if now >= next_payment {
+        return Ok(0);
+    }
+    let remaining = (next_payment as i128 - now as i128) as u128;
+    let refund = (price as u128)
+        .checked_mul(remaining)
+        .ok_or(ErrorCode::Overflow)?
+        / interval_seconds as u128;
+    u64::try_from(refund).map_err(|_| error!(ErrorCode::Overflow))
+}
+
+/// Enforce the deployment-wide price floor (`min_price` of 0 disables it).
+pub fn check_min_price(price: u64, min_price: u64) -> Result<()> {
+    require!(price >= min_price, ErrorCode::PriceBelowMinimum);
+    Ok(())
+}
+
+/// Load member plan `index` of `bundle`, verifying the account is that plan's PDA.
+pub fn load_bundle_plan<'info>(
+    info: &'info AccountInfo<'info>,
+    bundle: &Bundle,
+    index: usize,
+) -> Result<Account<'info, SubscriptionPlan>> {
+    let subscription_plan = Account::<SubscriptionPlan>::try_from(info)?;
+    let expected = Pubkey::create_program_address(
+        &[
+            b"subscription_plan",
+            bundle.creator.as_ref(),
+            &bundle.plan_ids[index].to_le_bytes(),
+            &[subscription_plan.bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidBundlePlan))?;
+    require_keys_eq!(info.key(), expected, ErrorCode::InvalidBundlePlan);
+    Ok(subscription_plan)
+}
+
+/// Load `subscriber`'s subscription to member plan `index` of `bundle`,
+/// verifying its PDA and that it was created through the bundle.
+pub fn load_bundle_subscription<'info>(
+    info: &'info AccountInfo<'info>,
+    bundle: &Bundle,
+    index: usize,
+    subscriber: &Pubkey,
+) -> Result<Account<'info, Subscription>> {
+    let subscription = Account::<Subscription>::try_from(info)?;
+    let expected = Pubkey::create_program_address(
+        &[
+            b"subscription",
+            subscriber.as_ref(),
+            &bundle.plan_ids[index].to_le_bytes(),
+            &[subscription.bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidSubscriptionAccount))?;
+    require_keys_eq!(info.key(), expected, ErrorCode::InvalidSubscriptionAccount);
+    require!(
+        subscription.bundle_id == Some(bundle.bundle_id),
+        ErrorCode::InvalidSubscriptionAccount
+    );
+    Ok(subscription)
+}
+
+/// Create `account` as a program-owned PDA of `space` bytes, paid by
+/// `payer`, the way Anchor's `init` does: an address that was already sent
+/// lamports is topped up to rent exemption, then allocated and assigned,
+/// since `create_account` refuses any address with a balance.
+pub fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer = &[signer_seeds];
+    if account.lamports() == 0 {
+        return anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+                signer,
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        );
+    }
+
+    let top_up = rent.saturating_sub(account.lamports());
+    if top_up > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            signer,
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            signer,
+        ),
+        &crate::ID,
+    )
+}
+
+/// Split a bundle charge across its member subscriptions for per-plan
+/// accounting. Shares are equal; the first member absorbs the remainder.
+pub fn bundle_shares(price: u64, members: usize) -> Result<Vec<u64>> {
+    require!(members > 0, ErrorCode::InvalidBundlePlan);
+    let share = price / members as u64;
+    let remainder = price % members as u64;
+    let mut shares = vec![share; members];
+    shares[0] = share.checked_add(remainder).ok_or(ErrorCode::Overflow)?;
+    Ok(shares)
+}
+
+/// Announce a subscription's newly scheduled payment.
+///
+/// Called wherever `next_payment` is set so notification services can key
+/// off a single event instead of polling subscription accounts.
+pub fn emit_payment_upcoming(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    emit!(PaymentUpcoming {
+        subscriber: subscription.subscriber,
+        creator: subscription.creator,
+        plan_id: subscription.plan_id,
+        amount: renewal_charge(subscription, subscription_plan, 1, now)?,
+        next_payment: subscription.next_payment,
+        remind_at: subscription.next_payment
+            .checked_sub(subscription_plan.reminder_lead_seconds)
+            .ok_or(ErrorCode::Underflow)?,
+        reference: subscription.reference,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+/// Load every subscription account in `accounts` for one plan, verifying
+/// each against its PDA and rejecting duplicates so none is counted twice.
+pub fn load_plan_subscriptions<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    subscription_plan: &SubscriptionPlan,
+    plan_id: u64,
+) -> Result<Vec<Account<'info, Subscription>>> {
+    let mut subscriptions: Vec<Account<'info, Subscription>> = Vec::with_capacity(accounts.len());
+    for account_info in accounts.iter() {
+        require!(
+            subscriptions.iter().all(|s| s.key() != account_info.key()),
+            ErrorCode::DuplicateSubscriptionAccount
+        );
+
+        let subscription = Account::<Subscription>::try_from(account_info)?;
+        let expected = Pubkey::create_program_address(
+            &[
+                b"subscription",
+                subscription.subscriber.as_ref(),
+                &plan_id.to_le_bytes(),
+                &[subscription.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidSubscriptionAccount))?;
+        require_keys_eq!(account_info.key(), expected, ErrorCode::InvalidSubscriptionAccount);
+        require_keys_eq!(
+            subscription.creator,
+            subscription_plan.creator,
+            ErrorCode::InvalidSubscriptionAccount
+        );
+        subscriptions.push(subscription);
+    }
+    Ok(subscriptions)
+}
+
+/// Seats held by `subscriptions`, and how many of those are comps.
+pub fn seat_counts(subscriptions: &[Account<Subscription>]) -> Result<(u32, u32)> {
+    let mut count: u32 = 0;
+    let mut comp_count: u32 = 0;
+    for subscription in subscriptions.iter().filter(|s| s.status.holds_seat()) {
+        count = count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        if subscription.is_comp {
+            comp_count = comp_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        }
+    }
+    Ok((count, comp_count))
+}
+
+/// Fail if a live subscription should already have expired: past its
+/// payment deadline or term end but never moved on by `refresh_status`.
+pub fn check_not_overdue(
+    subscription: &Subscription,
+    subscription_plan: &SubscriptionPlan,
+    now: i64,
+) -> Result<()> {
+    require!(
+        derive_status(subscription, subscription_plan, now)? != SubscriptionStatus::Expired
+            || !subscription.status.is_live(),
+        ErrorCode::InvariantOverdueSubscription
+    );
+    Ok(())
+}
+
+/// Whether `subscription` can be charged right now under the same rules
+/// `process_payment` applies.
+pub fn is_payment_due(subscription: &Subscription, subscription_plan: &SubscriptionPlan, now: i64) -> bool {
+    payable_cycles(subscription, subscription_plan, now).is_ok()
+}
+
+/// Ensure a delegated renewal is within the subscriber's authorization:
+/// cycles left and not past its expiry.
+pub fn check_auto_renew(subscription: &Subscription, now: i64) -> Result<()> {
+    require!(now <= subscription.auto_renew_expires_at, ErrorCode::AuthorizationExpired);
+    require!(
+        subscription.auto_renew_cycles_remaining > 0,
+        ErrorCode::AutoRenewExhausted
+    );
+    Ok(())
+}
+
+/// Plan creation fee `creator_config`'s creator owes: the configured fee,
+/// or nothing for fee-exempt creators.
+pub fn creation_fee_due(program_config: &ProgramConfig, creator_config: &CreatorConfig) -> u64 {
+    if creator_config.fee_exempt {
+        return 0;
+    }
+    program_config.plan_creation_fee
+}
+
+/// Charge the deployment's plan creation fee, if configured and the creator
+/// isn't exempt, to `creator`.
+pub fn collect_creation_fee<'info>(
+    program_config: &ProgramConfig,
+    creator_config: &CreatorConfig,
+    creator: &Signer<'info>,
+    fee_destination: &UncheckedAccount<'info>,
+    system_program: &Program<'info, System>,
+    plan_id: u64,
+    now: i64,
+) -> Result<()> {
+    let fee = creation_fee_due(program_config, creator_config);
+    if fee == 0 {
+        return Ok(());
+    }
+    require_keys_eq!(
+        fee_destination.key(),
+        program_config.fee_destination,
+        ErrorCode::InvalidFeeDestination
+    );
+    require!(creator.lamports() >= fee, ErrorCode::InsufficientFeeBalance);
+    anchor_lang::system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: creator.to_account_info(),
+                to: fee_destination.to_account_info(),
+            },
+        ),
+        fee,
+    )?;
+
+    emit!(CreationFeeCollected {
+        creator: creator.key(),
+        plan_id,
+        fee,
+        fee_destination: program_config.fee_destination,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+/// Stamp a new plan with the creator's next creation nonce.
+pub fn assign_creation_nonce(
+    subscription_plan: &mut SubscriptionPlan,
+    creator_registry: &mut CreatorRegistry,
+    creator: Pubkey,
+    registry_bump: u8,
+) -> Result<()> {
+    creator_registry.creator = creator;
+    creator_registry.bump = registry_bump;
+    subscription_plan.creation_nonce = creator_registry.plans_created;
+    creator_registry.plans_created = creator_registry.plans_created
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(())
+}
+
+/// Reject a charge above the subscriber's `max_token_amount`, if given.
+pub fn check_max_token_amount(amount: u64, max_token_amount: Option<u64>) -> Result<()> {
+    if let Some(max) = max_token_amount {
+        require!(amount <= max, ErrorCode::SlippageExceeded);
+    }
+    Ok(())
+}
+
+/// Move `subscription` to `status`, keeping the plan's per-status counts in
+/// step.
+pub fn transition_status(
+    subscription: &mut Subscription,
+    subscription_plan: &mut SubscriptionPlan,
+    status: SubscriptionStatus,
+) -> Result<()> {
+    subscription_plan.count_status_change(Some(subscription.status), status)?;
+    subscription.set_status(status);
+    Ok(())
+}
+
+/// On plans with `require_memo`, fail with `MemoRequired` unless the
+/// transaction includes an SPL Memo instruction, found by introspecting the
+/// instructions sysvar.
+pub fn check_memo(subscription_plan: &SubscriptionPlan, instructions_sysvar: &AccountInfo) -> Result<()> {
+    if !subscription_plan.require_memo {
+        return Ok(());
+    }
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if MEMO_PROGRAM_IDS.contains(&instruction.program_id) {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(ErrorCode::MemoRequired)
+}
+
+/// Instructions that start a subscription, with the position of the
+/// subscription account among their accounts (`None` for `subscribe_bundle`,
+/// whose member subscriptions may sit anywhere in `remaining_accounts`)
+const JOIN_INSTRUCTIONS: [([u8; 8], Option<usize>); 4] = [
+    (instruction::Subscribe::DISCRIMINATOR, Some(1)),
+    (instruction::ConfirmSubscription::DISCRIMINATOR, Some(1)),
+    (instruction::FollowSuccessor::DISCRIMINATOR, Some(3)),
+    (instruction::SubscribeBundle::DISCRIMINATOR, None),
+];
+
+/// On plans with `block_same_tx_cancel`, fail with `SameTransactionCancel`
+/// if the transaction also starts `subscription` (subscribing, confirming a
+/// reservation, following a successor or joining a bundle), found by
+/// introspecting the instructions sysvar.
+///
+/// Only top-level instructions are visible, so a subscribe made by CPI from
+/// another program isn't caught.
+pub fn check_not_joined_in_tx(
+    subscription_plan: &SubscriptionPlan,
+    subscription: &Pubkey,
+    instructions_sysvar: &AccountInfo,
+) -> Result<()> {
+    if !subscription_plan.block_same_tx_cancel {
+        return Ok(());
+    }
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        let joins = instruction.program_id == crate::ID
+            && JOIN_INSTRUCTIONS.iter().any(|(discriminator, account_index)| {
+                instruction.data.starts_with(discriminator)
+                    && match account_index {
+                        Some(index) => instruction.accounts.get(*index)
+                            .is_some_and(|meta| meta.pubkey == *subscription),
+                        None => instruction.accounts.iter().any(|meta| meta.pubkey == *subscription),
+                    }
+            });
+        require!(!joins, ErrorCode::SameTransactionCancel);
+        index += 1;
+    }
+    Ok(())
+}
+
+/// Account types with a versioned layout that `migrate_account` can upgrade
+/// in place.
+///
+/// New fields are only ever appended, so an older account decodes under the
+/// current layout once padded with zeros (see `decode_legacy`); `upgrade`
+/// then fills in anything whose zero value isn't the right default, taking
+/// values it can't derive from the account itself from `UpgradeInputs`.
+pub trait VersionedAccount: AccountSerialize + AccountDeserialize + Discriminator {
+    /// Size of the current layout
+    const SPACE: usize;
+
+    /// Stored layout version, with pre-versioning accounts reported as 1
+    fn schema_version(&self) -> u8;
+
+    /// Read what `upgrade` needs beyond the account itself from
+    /// `remaining_accounts`
+    fn upgrade_inputs<'info>(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<UpgradeInputs>;
+
+    /// Apply each version step in turn up to `CURRENT_SCHEMA_VERSION`
+    fn upgrade(&mut self, inputs: &UpgradeInputs);
+
+    /// Fail unless `key` is the PDA this account's own fields derive
+    fn check_address(&self, key: Pubkey) -> Result<()>;
+
+    /// Refuse accounts below `MIN_SUPPORTED_SCHEMA_VERSION`
+    fn check_schema(&self) -> Result<()> {
+        require!(
+            self.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION,
+            ErrorCode::SchemaTooOld
+        );
+        Ok(())
+    }
+}
+
+/// Values a version step needs from outside the account being upgraded
+#[derive(Clone, Default)]
+pub struct UpgradeInputs {
+    /// A subscription's plan, for a subscription that predates
+    /// `interval_seconds`
+    pub plan: Option<SubscriptionPlan>,
+    /// Mint, its decimals and payout account chosen by the creator, for a
+    /// plan that predates `payment_mint`
+    pub payout: Option<(Pubkey, u8, Pubkey)>,
+}
+
+impl VersionedAccount for SubscriptionPlan {
+    const SPACE: usize = SubscriptionPlan::LEN;
+
+    fn schema_version(&self) -> u8 {
+        self.schema_version.max(1)
+    }
+
+    fn upgrade_inputs<'info>(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<UpgradeInputs> {
+        if self.schema_version() >= 2 || self.payment_mint != Pubkey::default() {
+            return Ok(UpgradeInputs::default());
+        }
+        // Only the creator can pick where an original plan gets paid
+        let [creator, payment_mint, payout_token_account] = remaining_accounts else {
+            return err!(ErrorCode::MigrationAccountsMissing);
+        };
+        require!(
+            creator.is_signer && creator.key() == self.creator,
+            ErrorCode::InvalidCreator
+        );
+        let mint = InterfaceAccount::<Mint>::try_from(payment_mint)?;
+        let payout = InterfaceAccount::<TokenAccount>::try_from(payout_token_account)?;
+        require_keys_eq!(payout.owner, self.creator, ErrorCode::InvalidTokenAccountOwner);
+        require_keys_eq!(payout.mint, mint.key(), ErrorCode::MintMismatch);
+        Ok(UpgradeInputs {
+            payout: Some((mint.key(), mint.decimals, payout.key())),
+            ..Default::default()
+        })
+    }
+
+    fn upgrade(&mut self, inputs: &UpgradeInputs) {
+        if self.schema_version() < 2 {
+            // v1 is every layout before `schema_version`, back to the
+            // original plan, which had no payment mint or status counters
+            if let Some((payment_mint, decimals, payout_token_account)) = inputs.payout {
+                self.payment_mint = payment_mint;
+                self.payment_decimals = Some(decimals);
+                self.payout_token_account = payout_token_account;
+            }
+            if self.max_prepaid_cycles == 0 {
+                self.max_prepaid_cycles = DEFAULT_MAX_PREPAID_CYCLES;
+            }
+            if self.active_count == 0 && self.paused_count == 0 && self.expired_count == 0 {
+                // Plans older than the status counters couldn't pause, so
+                // every seat they hold is an active subscription
+                self.active_count = self.current_subscribers;
+            }
+            self.schema_version = 2;
+        }
+        if self.schema_version() < 3 {
+            // v3's plan fields (deactivation notice through loyalty
+            // discount) are all off at zero
+            self.schema_version = 3;
+        }
+    }
+
+    fn check_address(&self, key: Pubkey) -> Result<()> {
+        let expected = Pubkey::create_program_address(
+            &[
+                b"subscription_plan",
+                self.creator.as_ref(),
+                &self.plan_id.to_le_bytes(),
+                &[self.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidPlanAccount))?;
+        require_keys_eq!(key, expected, ErrorCode::InvalidPlanAccount);
+        Ok(())
+    }
+}
+
+impl VersionedAccount for Subscription {
+    const SPACE: usize = Subscription::LEN;
+
+    fn schema_version(&self) -> u8 {
+        self.schema_version.max(1)
+    }
+
+    fn upgrade_inputs<'info>(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<UpgradeInputs> {
+        if self.schema_version() >= 2 || self.interval_seconds != 0 {
+            return Ok(UpgradeInputs::default());
+        }
+        let [plan_info] = remaining_accounts else {
+            return err!(ErrorCode::MigrationAccountsMissing);
+        };
+        require_keys_eq!(*plan_info.owner, crate::ID, ErrorCode::InvalidPlanAccount);
+        let plan = decode_legacy::<SubscriptionPlan>(&plan_info.try_borrow_data()?)?;
+        plan.check_address(plan_info.key())?;
+        require!(
+            plan.creator == self.creator && plan.plan_id == self.plan_id,
+            ErrorCode::InvalidPlanAccount
+        );
+        Ok(UpgradeInputs {
+            plan: Some(plan),
+            ..Default::default()
+        })
+    }
+
+    fn upgrade(&mut self, inputs: &UpgradeInputs) {
+        if self.schema_version() < 2 {
+            // v1 is every layout before `schema_version`, back to the
+            // original subscription, which only had `is_active`: a zero
+            // status reads as Active, so cancelled ones need it set
+            if !self.is_active && self.status == SubscriptionStatus::Active {
+                self.set_status(SubscriptionStatus::Cancelled);
+            }
+            if let Some(plan) = &inputs.plan {
+                self.interval_seconds = plan.interval_seconds;
+                self.interval_unit = plan.interval_unit;
+                self.interval_count = plan.interval_count;
+            }
+            self.schema_version = 2;
+        }
+        if self.schema_version() < 3 {
+            // v3 added `created_at`. Only the latest payment survives on
+            // older subscriptions, so it stands in as a lower bound on their
+            // age, flagged so `min_duration_met` still exempts them
+            if self.created_at == 0 {
+                self.created_at = self.last_payment;
+                self.created_at_estimated = true;
+            }
+            self.schema_version = 3;
+        }
+    }
+
+    fn check_address(&self, key: Pubkey) -> Result<()> {
+        let expected = Pubkey::create_program_address(
+            &[
+                b"subscription",
+                self.subscriber.as_ref(),
+                &self.plan_id.to_le_bytes(),
+                &[self.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidSubscriptionAccount))?;
+        require_keys_eq!(key, expected, ErrorCode::InvalidSubscriptionAccount);
+        Ok(())
+    }
+}
+
+/// Decode an account written under an older, shorter layout.
+///
+/// Fields added since are appended at the end of the struct and all decode
+/// from zero bytes to their defaults (0, false, `None`, first enum
+/// variant), so padding the old data with zeros to the current size reads
+/// it under the current layout.
+pub fn decode_legacy<T: VersionedAccount>(data: &[u8]) -> Result<T> {
+    let mut padded = data.to_vec();
+    padded.resize(T::SPACE.max(data.len()), 0);
+    T::try_deserialize(&mut &padded[..])
+}
+
+/// Upgrade a versioned account in place and return it with its old version
+/// and size.
+///
+/// Decodes the account under the current layout, checks it lives at its
+/// PDA, reads its `UpgradeInputs` from `remaining_accounts`, tops up rent
+/// from `payer`, grows it to `T::SPACE` and writes back the upgraded data.
+/// Accounts are never shrunk.
+pub fn migrate_versioned<'info, T: VersionedAccount>(
+    info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<(T, u8, usize)> {
+    let old_len = info.data_len();
+    let mut account = decode_legacy::<T>(&info.try_borrow_data()?)?;
+    account.check_address(info.key())?;
+    let old_version = account.schema_version();
+    require!(
+        old_len < T::SPACE || old_version < CURRENT_SCHEMA_VERSION,
+        ErrorCode::AccountAlreadyCurrent
+    );
+    let inputs = account.upgrade_inputs(remaining_accounts)?;
+
+    let rent_due = Rent::get()?
+        .minimum_balance(T::SPACE)
+        .saturating_sub(info.lamports());
+    if rent_due > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: info.clone(),
+                },
+            ),
+            rent_due,
+        )?;
+    }
+    if old_len < T::SPACE {
+        info.realloc(T::SPACE, true)?;
+    }
+
+    account.upgrade(&inputs);
+    account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok((account, old_version, old_len))
+}
+
+/// Check `authority` may move `amount` out of `token_account`: it either
+/// owns the account or is its approved delegate with enough allowance left
+pub fn check_funding_authority(
+    token_account: &TokenAccount,
+    authority: Pubkey,
+    amount: u64,
+) -> Result<()> {
+    let is_owner = token_account.owner == authority;
+    let is_delegate = token_account.delegate == COption::Some(authority)
+        && token_account.delegated_amount >= amount;
+    require!(is_owner || is_delegate, ErrorCode::UnauthorizedFundingAuthority);
+    Ok(())
+}
+
+/// Flag `subscription` as blocked if `token_account` (at `key`) is frozen.
+///
+/// A transfer out of a frozen account fails inside the token program with
+/// an opaque error that rolls back anything recorded alongside it, so the
+/// renewal paths check first and return whether to skip the charge.
+pub fn mark_payment_blocked(
+    subscription: &mut Subscription,
+    token_account: &TokenAccount,
+    key: Pubkey,
+) -> bool {
+    if !token_account.is_frozen() {
+        return false;
+    }
+    subscription.payment_blocked = true;
+    subscription.blocked_token_account = key;
+    true
+}
+
+/// Public key and message of an ed25519 program instruction holding exactly
+/// one signature whose key, message and signature all live in its own data.
+///
+/// The runtime only executes the transaction if the ed25519 program verified
+/// the signature, so a match here proves the key signed the message. Entries
+/// pointing into other instructions (index other than `u16::MAX`) are
+/// rejected, since their bytes could be swapped independently.
+pub fn ed25519_signed_message(data: &[u8]) -> Option<(Pubkey, &[u8])> {
+    let offsets = data.get(ED25519_OFFSETS_START..ED25519_OFFSETS_START + ED25519_OFFSETS_LEN)?;
+    if data[0] != 1 {
+        return None;
+    }
+    let field = |i: usize| u16::from_le_bytes([offsets[2 * i], offsets[2 * i + 1]]);
+    let signature_instruction = field(1);
+    let public_key_offset = field(2) as usize;
+    let public_key_instruction = field(3);
+    let message_offset = field(4) as usize;
+    let message_len = field(5) as usize;
+    let message_instruction = field(6);
+    if [signature_instruction, public_key_instruction, message_instruction]
+        .iter()
+        .any(|&index| index != u16::MAX)
+    {
+        return None;
+    }
+
+    let public_key = data.get(public_key_offset..public_key_offset + 32)?;
+    let message = data.get(message_offset..message_offset + message_len)?;
+    Some((Pubkey::try_from(public_key).ok()?, message))
+}
+
+/// Fail with `InvalidPermitSignature` unless the transaction carries an
+/// ed25519 program instruction verifying `signer`'s signature over
+/// `message`, found by introspecting the instructions sysvar.
+pub fn check_permit_signature(
+    instructions_sysvar: &AccountInfo,
+    signer: Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if instruction.program_id == ed25519_program::ID
+            && ed25519_signed_message(&instruction.data) == Some((signer, message))
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    err!(ErrorCode::InvalidPermitSignature)
+}
+
+/// Secondary-token accounts `process_payment` takes from `remaining_accounts`
+pub struct SecondaryPayment<'info> {
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+/// Check the subscriber can pay `amount` in the primary token or, failing
+/// that, in the secondary token given as `(balance, amount)`
+pub fn check_payment_funds(
+    primary_balance: u64,
+    amount: u64,
+    secondary: Option<(u64, u64)>,
+) -> Result<()> {
+    if primary_balance >= amount {
+        return Ok(());
+    }
+    match secondary {
+        Some((balance, secondary_amount)) => {
+            require!(balance >= secondary_amount, ErrorCode::AllPaymentTokensInsufficient);
+            Ok(())
+        }
+        None => err!(ErrorCode::InsufficientFunds),
+    }
+}
+
+/// Load `[subscriber token account, mint, creator payout account]` for the
+/// plan's secondary token, checking each against the plan's settings.
+pub fn load_secondary_payment<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    subscription_plan: &SubscriptionPlan,
+    token_program: Pubkey,
+) -> Result<SecondaryPayment<'info>> {
+    let secondary_mint = subscription_plan
+        .secondary_payment_mint
+        .ok_or(ErrorCode::NoSecondaryPayment)?;
+    require!(accounts.len() == 3, ErrorCode::InvalidSecondaryPaymentAccounts);
+    let token_account = InterfaceAccount::<TokenAccount>::try_from(&accounts[0])?;
+    let mint = InterfaceAccount::<Mint>::try_from(&accounts[1])?;
+    let creator_token_account = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+
+    require_keys_eq!(mint.key(), secondary_mint, ErrorCode::MintMismatch);
+    require_keys_eq!(token_account.mint, secondary_mint, ErrorCode::MintMismatch);
+    require_keys_eq!(
+        creator_token_account.key(),
+        subscription_plan.secondary_payout_token_account,
+        ErrorCode::InvalidPayoutAccount
+    );
+    require_keys_eq!(*accounts[1].owner, token_program, ErrorCode::WrongTokenProgram);
+    Ok(SecondaryPayment { token_account, mint, creator_token_account })
+}
+
+/// Offset of `Subscription::subscriber` in the raw account (after the discriminator)
+pub const SUBSCRIPTION_SUBSCRIBER_OFFSET: usize = 8;
+/// Offset of `Subscription::plan_id` (u64, little-endian)
+pub const SUBSCRIPTION_PLAN_ID_OFFSET: usize = 40;
+/// Offset of `Subscription::creator`
+pub const SUBSCRIPTION_CREATOR_OFFSET: usize = 48;
+/// Offset of `Subscription::is_active` (one byte, 0 or 1)
+pub const SUBSCRIPTION_IS_ACTIVE_OFFSET: usize = 80;
+/// Offset of `Subscription::next_payment` (i64, little-endian)
+pub const SUBSCRIPTION_NEXT_PAYMENT_OFFSET: usize = 89;
+/// Offset of `Subscription::ends_at` (i64, little-endian; 0 = open-ended)
+pub const SUBSCRIPTION_ENDS_AT_OFFSET: usize = 146;
+/// Offset of `Subscription::status` (one byte, `SubscriptionStatus` variant index)
+pub const SUBSCRIPTION_STATUS_OFFSET: usize = 186;
+/// Offset of `Subscription::bundle_id`, the first field whose size varies
+/// (a Borsh `Option`: one tag byte, then 8 bytes if `Some`)
+pub const SUBSCRIPTION_BUNDLE_ID_OFFSET: usize = 192;
+
+/// Whether a raw `Subscription` account is currently paid up, for programs
+/// that gate access by reading the account instead of calling
+/// `assert_active_subscriber`.
+///
+/// # Layout
+/// `Subscription` fields are only ever appended, so everything up to
+/// `SUBSCRIPTION_BUNDLE_ID_OFFSET` sits at a fixed offset (the
+/// `SUBSCRIPTION_*_OFFSET` constants) that will not move; a gating program
+/// can copy those constants rather than depend on this crate. Callers must
+/// still check the account is owned by this program and is the PDA
+/// `["subscription", subscriber, plan_id]` they expect.
+///
+/// Applies the same test as `assert_active_subscriber`: active, not past a
+/// fixed-term end and not past `next_payment` plus grace (the subscription's
+/// `grace_override_seconds`, else `GRACE_PERIOD_SECONDS`). The plan's
+/// `grace_window` and credit for a maintenance window aren't visible from
+/// the subscription alone, so on plans that set them a subscription may read
+/// as lapsed earlier or later than the instruction would report. Anything
+/// that doesn't decode as a subscription is `false`.
+pub fn is_active_subscriber(account_data: &[u8], now: i64) -> bool {
+    decode_active_subscriber(account_data, now).unwrap_or(false)
+}
+
+fn decode_active_subscriber(data: &[u8], now: i64) -> Option<bool> {
+    if data.get(..8)? != Subscription::DISCRIMINATOR {
+        return None;
+    }
+    // Accounts older than a field read it as zero, as in `decode_legacy`
+    let byte = |offset: usize| data.get(offset).copied().unwrap_or(0);
+    let read_i64 = |offset: usize| i64::from_le_bytes(std::array::from_fn(|i| byte(offset + i)));
+
+    let is_active = byte(SUBSCRIPTION_IS_ACTIVE_OFFSET) == 1;
+    let next_payment = read_i64(SUBSCRIPTION_NEXT_PAYMENT_OFFSET);
+    let ends_at = read_i64(SUBSCRIPTION_ENDS_AT_OFFSET);
+
+    // Step over `bundle_id` and `is_frozen` to `grace_override_seconds`
+    let mut offset = SUBSCRIPTION_BUNDLE_ID_OFFSET;
+    offset += if byte(offset) == 1 { 1 + 8 } else { 1 };
+    offset += 1;
+    let grace = if byte(offset) == 1 {
+        read_i64(offset + 1)
+    } else {
+        GRACE_PERIOD_SECONDS
+    };
+
+    let has_ended = ends_at != 0 && now >= ends_at;
+    Some(is_active && !has_ended && now <= next_payment.saturating_add(grace))
+}
+
+/// Load the plan's prepaid vault, passed as the only remaining account.
+pub fn load_prepaid_vault<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    plan_key: Pubkey,
+) -> Result<InterfaceAccount<'info, TokenAccount>> {
+    require!(accounts.len() == 1, ErrorCode::InvalidPrepaidVault);
+    let (vault_key, _) =
+        Pubkey::find_program_address(&[b"prepaid_vault", plan_key.as_ref()], &crate::ID);
+    require_keys_eq!(accounts[0].key(), vault_key, ErrorCode::InvalidPrepaidVault);
+    InterfaceAccount::<TokenAccount>::try_from(&accounts[0])
+}
+
+// ============================================================================
+// Account Structures
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CreateSubscriptionPlan<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = SubscriptionPlan::LEN,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = CreatorRegistry::LEN,
+        seeds = [b"creator_registry", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_registry: Account<'info, CreatorRegistry>,
+    /// CHECK: Program config PDA; may be uninitialized, in which case no price floor applies
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: UncheckedAccount<'info>,
+    /// CHECK: Creator config PDA; may be uninitialized, in which case the creator isn't fee-exempt
+    #[account(seeds = [b"creator_config", creator.key().as_ref()], bump)]
+    pub creator_config: UncheckedAccount<'info>,
+    /// CHECK: Must match `ProgramConfig::fee_destination` when a creation fee is charged
+    #[account(mut)]
+    pub fee_destination: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = payout_token_account.owner == creator.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = payout_token_account.mint == payment_mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub payout_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct Subscribe<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = rent_payer,
+        space = Subscription::LEN,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    /// Pays the subscription's rent; the subscriber itself unless sponsored
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+    /// Token authority for the charge: owner or approved delegate of `subscriber_token_account`
+    pub funding_authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.mint == creator_token_account.mint @ ErrorCode::MintMismatch,
+        constraint = subscriber_token_account.mint == payment_mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = subscription_plan.price_in(&payment_mint.key()).is_some() @ ErrorCode::PaymentTokenNotAccepted,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() != subscriber_token_account.key() @ ErrorCode::SelfPayment,
+        constraint = creator_token_account.owner == subscription_plan.creator @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ProcessPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.plan_id == plan_id @ ErrorCode::InvalidPlanId,
+        constraint = subscription.subscriber == subscriber.key() @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+        constraint = subscription.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    /// Token authority for the charge: owner or approved delegate of `subscriber_token_account`
+    pub funding_authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.mint == creator_token_account.mint @ ErrorCode::MintMismatch,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() != subscriber_token_account.key() @ ErrorCode::SelfPayment,
+        constraint = creator_token_account.owner == subscription_plan.creator @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ProcessPaymentCatchup<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.plan_id == plan_id @ ErrorCode::InvalidPlanId,
+        constraint = subscription.subscriber == subscriber.key() @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == creator_token_account.mint @ ErrorCode::MintMismatch,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == subscription_plan.creator @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CancelSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same transaction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CloseSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        close = subscriber
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct UpdateSubscriptionPlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.is_creator_or_manager(&authority.key()) @ ErrorCode::NotCreatorOrManager,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    /// CHECK: Program config PDA; may be uninitialized, in which case no price floor applies
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: UncheckedAccount<'info>,
+    /// Plan creator or its delegated manager
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct PausePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.is_creator_or_manager(&authority.key()) @ ErrorCode::NotCreatorOrManager,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    /// Plan creator or its delegated manager
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct UnpausePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.is_creator_or_manager(&authority.key()) @ ErrorCode::NotCreatorOrManager,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    /// Plan creator or its delegated manager
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct DeactivatePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ScheduleMaintenance<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetPayoutAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+    #[account(
+        constraint = new_payout_token_account.owner == creator.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = new_payout_token_account.mint == subscription_plan.payment_mint @ ErrorCode::MintMismatch,
+    )]
+    pub new_payout_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetRefundWindow<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SelfRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+        constraint = !subscription.is_comp @ ErrorCode::CompSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same transaction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetReference<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetBillOnWeekdayOnly<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct AssertActiveSubscriber<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    /// CHECK: Only used as a seed and compared against `subscription.subscriber`
+    pub subscriber: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetPrepayCyclesOnJoin<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetReservationWindow<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SubscribeReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = subscriber,
+        space = Subscription::LEN,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ConfirmSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ReleaseReservation<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        close = subscriber
+    )]
+    pub subscription: Account<'info, Subscription>,
+    /// CHECK: Receives the reservation's rent; must match `subscription.subscriber`
+    #[account(mut)]
+    pub subscriber: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProgramConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = ProgramConfig::LEN,
+        seeds = [b"program_config"],
+        bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Circulum>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ ErrorCode::InvalidAdmin)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = treasury,
+        token::token_program = token_program,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepTreasury<'info> {
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = destination.mint == mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub admin: Signer<'info>,
+    #[account(
+        constraint = token_program.key() == *mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetAllowIntervalTightening<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct MigrateSubscriptionInterval<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == creator.key() @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct FollowSuccessor<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+    )]
+    pub subscription_plan: Box<Account<'info, SubscriptionPlan>>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &successor_plan.plan_id.to_le_bytes()],
+        bump = successor_plan.bump,
+        constraint = subscription_plan.successor_plan_id == Some(successor_plan.plan_id) @ ErrorCode::InvalidSuccessorPlan,
+    )]
+    pub successor_plan: Box<Account<'info, SubscriptionPlan>>,
+    #[account(
+        init,
+        payer = subscriber,
+        space = Subscription::LEN,
+        seeds = [b"subscription", subscriber.key().as_ref(), &successor_plan.plan_id.to_le_bytes()],
+        bump
+    )]
+    pub new_subscription: Box<Account<'info, Subscription>>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == successor_plan.payment_mint @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = successor_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() == successor_plan.payout_token_account @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same
+    /// transaction and a memo on `require_memo` successor plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct InitializePrepaidVault<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"prepaid_vault", subscription_plan.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = prepaid_vault,
+        token::token_program = token_program,
+    )]
+    pub prepaid_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct DepositToVault<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        seeds = [b"prepaid_vault", subscription_plan.key().as_ref()],
+        bump,
+        constraint = prepaid_vault.mint == payment_mint.key() @ ErrorCode::PrepaidMintMismatch,
+    )]
+    pub prepaid_vault: InterfaceAccount<'info, TokenAccount>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ProcessPrepaidPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        seeds = [b"prepaid_vault", subscription_plan.key().as_ref()],
+        bump,
+    )]
+    pub prepaid_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() == subscription_plan.payout_token_account @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetMaxPrepaidCycles<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CancelAndRefundPrepaid<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        seeds = [b"prepaid_vault", subscription_plan.key().as_ref()],
+        bump,
+    )]
+    pub prepaid_vault: InterfaceAccount<'info, TokenAccount>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = destination.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = destination.mint == subscription_plan.payment_mint @ ErrorCode::MintMismatch,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same transaction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct RefreshStatus<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetIntervalUnit<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CancelWithProratedRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same transaction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetProratedCancelRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CancelAllSubscribers<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ExtendPause<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct EffectiveNextPrice<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetManager<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinPrice<'info> {
+    #[account(
+        mut,
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ReconcileSubscriberCount<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct CreateBundle<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = Bundle::LEN,
+        seeds = [b"bundle", creator.key().as_ref(), &bundle_id.to_le_bytes()],
+        bump
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = payout_token_account.owner == creator.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = payout_token_account.mint == payment_mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub payout_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct SubscribeBundle<'info> {
+    #[account(
+        seeds = [b"bundle", bundle.creator.as_ref(), &bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == bundle.payment_mint @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = bundle.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() == bundle.payout_token_account @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct ProcessBundlePayment<'info> {
+    #[account(
+        seeds = [b"bundle", bundle.creator.as_ref(), &bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+    )]
+    pub bundle: Account<'info, Bundle>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == bundle.payment_mint @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = bundle.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() == bundle.payout_token_account @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct CancelBundle<'info> {
+    #[account(
+        seeds = [b"bundle", bundle.creator.as_ref(), &bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+    )]
+    pub bundle: Account<'info, Bundle>,
+    pub subscriber: Signer<'info>,
+    /// CHECK: Instructions sysvar, read to find a subscribe in the same transaction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct DeactivateBundle<'info> {
+    #[account(
+        mut,
+        seeds = [b"bundle", creator.key().as_ref(), &bundle_id.to_le_bytes()],
+        bump = bundle.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub bundle: Account<'info, Bundle>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetReminderLeadSeconds<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64, subscriber: Pubkey)]
+pub struct FreezeSubscription<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidSubscriptionAccount,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    /// CHECK: Program config PDA; may be uninitialized, in which case only the creator may act
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: UncheckedAccount<'info>,
+    #[account(
+        constraint = authority.key() == subscription_plan.creator
+            || authority.key() == ProgramConfig::load_or_default(&program_config)?.admin
+            @ ErrorCode::Unauthorized,
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetAutoExpand<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64, subscriber: Pubkey)]
+pub struct SetGraceOverride<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == creator.key() @ ErrorCode::InvalidSubscriptionAccount,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPlanCreationFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(creator: Pubkey)]
+pub struct SetFeeExemption<'info> {
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = CreatorConfig::LEN,
+        seeds = [b"creator_config", creator.as_ref()],
+        bump
+    )]
+    pub creator_config: Account<'info, CreatorConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64, beneficiary: Pubkey)]
+pub struct GrantSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = creator,
+        space = Subscription::LEN,
+        seeds = [b"subscription", beneficiary.as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64, beneficiary: Pubkey)]
+pub struct RevokeComp<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", beneficiary.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == creator.key() @ ErrorCode::InvalidCreator,
+        constraint = subscription.is_comp @ ErrorCode::NotCompSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CheckPlanInvariants<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(address = subscription_plan.payout_token_account @ ErrorCode::InvalidPayoutAccount)]
+    pub payout_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetJitterSeconds<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DueSubscriptionsCount<'info> {
+    /// CHECK: Only compared against each subscription's `subscriber`
+    pub subscriber: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetSetupFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct AuthorizeAutoRenew<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *subscriber_token_account.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ProcessPaymentDelegated<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+        constraint = subscription.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscription.subscriber @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ProcessPaymentWithPermit<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        constraint = subscription_plan.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+        constraint = subscription.bundle_id.is_none() @ ErrorCode::BundleMemberSubscription,
+        constraint = subscription.schema_version() >= MIN_SUPPORTED_SCHEMA_VERSION @ ErrorCode::SchemaTooOld,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscription.subscriber @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        address = subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+        constraint = subscription_plan.matches_decimals(&payment_mint.key(), payment_mint.decimals) @ ErrorCode::PaymentDecimalsMismatch,
+    )]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = subscription_plan.payout_account_for(&payment_mint.key()) == Some(creator_token_account.key()) @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find the permit's ed25519 signature
+    /// check and, on `require_memo` plans, a memo
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(source_plan_id: u64, new_plan_id: u64)]
+pub struct ClonePlan<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &source_plan_id.to_le_bytes()],
+        bump = source_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub source_plan: Box<Account<'info, SubscriptionPlan>>,
+    #[account(
+        init,
+        payer = creator,
+        space = SubscriptionPlan::LEN,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &new_plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription_plan: Box<Account<'info, SubscriptionPlan>>,
+    #[account(
+        init_if_needed,
+        payer = creator,
+        space = CreatorRegistry::LEN,
+        seeds = [b"creator_registry", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_registry: Account<'info, CreatorRegistry>,
+    /// CHECK: Program config PDA; may be uninitialized, in which case no price floor applies
+    #[account(seeds = [b"program_config"], bump)]
+    pub program_config: UncheckedAccount<'info>,
+    /// CHECK: Creator config PDA; may be uninitialized, in which case the creator isn't fee-exempt
+    #[account(seeds = [b"creator_config", creator.key().as_ref()], bump)]
+    pub creator_config: UncheckedAccount<'info>,
+    /// CHECK: Must match `ProgramConfig::fee_destination` when a creation fee is charged
+    #[account(mut)]
+    pub fee_destination: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetBillingAnchorDay<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetPastGracePolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ReactivatePlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetActivationThreshold<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct InitializePledgeVault<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"pledge_vault", subscription_plan.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = pledge_vault,
+        token::token_program = token_program,
+    )]
+    pub pledge_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct Pledge<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = subscriber,
+        space = Subscription::LEN,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"pledge_vault", subscription_plan.key().as_ref()],
+        bump,
+    )]
+    pub pledge_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ActivatePledge<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.subscriber.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(
+        mut,
+        seeds = [b"pledge_vault", subscription_plan.key().as_ref()],
+        bump,
+    )]
+    pub pledge_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = creator_token_account.key() == subscription_plan.payout_token_account @ ErrorCode::InvalidPayoutAccount,
+    )]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct RefundPledge<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(
+        mut,
+        constraint = subscriber_token_account.owner == subscriber.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = subscriber_token_account.mint == subscription.billing_mint(&subscription_plan) @ ErrorCode::MintMismatch,
+    )]
+    pub subscriber_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"pledge_vault", subscription_plan.key().as_ref()],
+        bump,
+    )]
+    pub pledge_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = subscription_plan.payment_mint @ ErrorCode::MintMismatch)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = token_program.key() == *payment_mint.to_account_info().owner @ ErrorCode::WrongTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetRequireMemo<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateSubscriptionAccount<'info> {
+    /// CHECK: May predate the current layout, so it is decoded and its PDA
+    /// verified in the handler
+    #[account(mut, owner = crate::ID)]
+    pub subscription: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    /// CHECK: May predate the current layout, so its type is read from the
+    /// discriminator and its PDA verified in the handler
+    #[account(mut, owner = crate::ID)]
+    pub account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetDeactivationNotice<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CancelDeactivation<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetSecondaryPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+    #[account(
+        constraint = secondary_mint.key() != subscription_plan.payment_mint @ ErrorCode::InvalidSecondaryMint,
+    )]
+    pub secondary_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = secondary_payout_token_account.owner == creator.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = secondary_payout_token_account.mint == secondary_mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub secondary_payout_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ClearSecondaryPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct PlanHealthQuery<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetLoyaltyDiscount<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetMaxLifetime<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetNewSubscriptionsEnabled<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PayAllDue<'info> {
+    pub subscriber: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, read to find a memo on `require_memo` plans
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct ClearPaymentBlocked<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+    #[account(address = subscription.blocked_token_account @ ErrorCode::InvalidBlockedTokenAccount)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMetadataBatch<'info> {
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetMinSubscriptionDuration<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64, snapshot_index: u64)]
+pub struct SnapshotPlan<'info> {
+    #[account(
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        init,
+        payer = creator,
+        space = PlanSnapshot::LEN,
+        seeds = [b"plan_snapshot", subscription_plan.key().as_ref(), &snapshot_index.to_le_bytes()],
+        bump
+    )]
+    pub plan_snapshot: Account<'info, PlanSnapshot>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct AddAcceptedToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+    #[account(
+        constraint = mint.key() != subscription_plan.payment_mint @ ErrorCode::InvalidAcceptedToken,
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = payout_token_account.owner == creator.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = payout_token_account.mint == mint.key() @ ErrorCode::MintMismatch,
+    )]
+    pub payout_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct RemoveAcceptedToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetPayWhatYouWant<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct UpdateChosenPrice<'info> {
+    #[account(
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        mut,
+        seeds = [b"subscription", subscriber.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription.bump,
+        has_one = subscriber @ ErrorCode::InvalidSubscriber,
+        constraint = subscription.creator == subscription_plan.creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription: Account<'info, Subscription>,
+    pub subscriber: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetBlockSameTxCancel<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetGraceWindow<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetCapacityChangeCooldown<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+        has_one = creator @ ErrorCode::InvalidCreator,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct SetPlanVerified<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription_plan", subscription_plan.creator.as_ref(), &plan_id.to_le_bytes()],
+        bump = subscription_plan.bump,
+    )]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    #[account(
+        seeds = [b"program_config"],
+        bump = program_config.bump,
+        has_one = admin @ ErrorCode::InvalidAdmin,
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+#[account]
+#[derive(Default)]
+pub struct ProgramConfig {
+    /// Protocol administrator
+    pub admin: Pubkey,
+    /// PDA bump seed
+    pub bump: u8,
+    /// Lowest plan price allowed, in smallest token units (0 = no floor)
+    pub min_price: u64,
+    /// Lamports charged to create a plan (0 = free)
+    pub plan_creation_fee: u64,
+    /// Account receiving plan creation fees
+    pub fee_destination: Pubkey,
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // admin
+        1 + // bump
+        8 + // min_price
+        8 + // plan_creation_fee
+        32; // fee_destination
+
+    /// Read the config from its PDA, or defaults if it hasn't been initialized
+    pub fn load_or_default(info: &AccountInfo) -> Result<Self> {
+        if info.data_is_empty() {
+            return Ok(Self::default());
+        }
+        let data = info.try_borrow_data()?;
+        Self::try_deserialize(&mut &data[..])
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct CreatorConfig {
+    /// Creator these settings apply to
+    pub creator: Pubkey,
+    /// Waives the platform's plan creation fee for this creator
+    pub fee_exempt: bool,
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl CreatorConfig {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // creator
+        1 + // fee_exempt
+        1; // bump
+
+    /// Read the config from its PDA, or defaults (not exempt) if the admin
+    /// hasn't created it
+    pub fn load_or_default(info: &AccountInfo) -> Result<Self> {
+        if info.data_is_empty() {
+            return Ok(Self::default());
+        }
+        let data = info.try_borrow_data()?;
+        Self::try_deserialize(&mut &data[..])
+    }
+}
+
+/// Point-in-time record of a plan written by `snapshot_plan`; never modified
+#[account]
+pub struct PlanSnapshot {
+    /// Plan account the snapshot was taken of
+    pub plan: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// Creator-chosen index distinguishing this plan's snapshots
+    pub snapshot_index: u64,
+    /// Plan's `current_subscribers` when the snapshot was taken
+    pub current_subscribers: u32,
+    /// Plan's `accrued_revenue` when the snapshot was taken
+    pub total_revenue: u64,
+    pub timestamp: i64,
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl PlanSnapshot {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // plan
+        32 + // creator
+        8 + // plan_id
+        8 + // snapshot_index
+        4 + // current_subscribers
+        8 + // total_revenue
+        8 + // timestamp
+        1; // bump
+}
+
+#[account]
+#[derive(Default)]
+pub struct CreatorRegistry {
+    /// Creator this registry belongs to
+    pub creator: Pubkey,
+    /// Plans ever created by the creator; never decreases
+    pub plans_created: u64,
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl CreatorRegistry {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // creator
+        8 + // plans_created
+        1; // bump
+}
+
+#[account]
+#[derive(Default)]
+pub struct Bundle {
+    /// Creator of the bundle and all its member plans
+    pub creator: Pubkey,
+    /// Unique bundle identifier per creator
+    pub bundle_id: u64,
+    /// Member plan IDs, in the order their accounts are passed
+    pub plan_ids: Vec<u64>,
+    /// Price charged once per cycle for all member plans
+    pub price: u64,
+    /// Mint shared by every member plan
+    pub payment_mint: Pubkey,
+    /// Creator token account receiving bundle payments
+    pub payout_token_account: Pubkey,
+    /// Whether new subscribers can join
+    pub is_active: bool,
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+impl Bundle {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // creator
+        8 + // bundle_id
+        4 + 8 * MAX_BUNDLE_PLANS + // plan_ids
+        8 + // price
+        32 + // payment_mint
+        32 + // payout_token_account
+        1 + // is_active
+        1; // bump
+}
+
+#[account]
+#[derive(Default)]
+pub struct SubscriptionPlan {
+    /// Creator's public key
+    pub creator: Pubkey,
+    /// Unique plan identifier
+    pub plan_id: u64,
+    /// Price per billing cycle (in smallest token unit)
+    pub price: u64,
+    /// Billing interval in seconds
+    pub interval_seconds: i64,
+    /// Maximum allowed subscribers
+    pub max_subscribers: u32,
+    /// Current number of active subscribers
+    pub current_subscribers: u32,
+    /// Whether plan accepts new subscriptions
+    pub is_active: bool,
+    /// Whether plan is temporarily paused
+    pub is_paused: bool,
+    /// URI to plan metadata (max 200 chars)
+    pub metadata_uri: String,
+    /// Creation timestamp
+    pub created_at: i64,
+    /// PDA bump seed
+    pub bump: u8,
+    /// Seconds before `next_payment` a payment is already accepted
+    pub early_tolerance_seconds: i64,
+    /// Start of the scheduled maintenance window (0 if none)
+    pub maintenance_start: i64,
+    /// End of the scheduled maintenance window (0 if none)
+    pub maintenance_end: i64,
+    /// Mint all plan payments are denominated in
+    pub payment_mint: Pubkey,
+    /// Creator token account that receives payments
+    pub payout_token_account: Pubkey,
+    /// SHA-256 of the metadata JSON at `metadata_uri` (all zeros if unset)
+    pub metadata_hash: [u8; 32],
+    /// Window after subscribing in which a subscriber may self-refund
+    pub refund_window_seconds: i64,
+    /// Push due dates that fall on a weekend (UTC) to Monday
+    pub bill_on_weekday_only: bool,
+    /// Billing cycles charged upfront at `subscribe`
+    pub prepay_cycles_on_join: u32,
+    /// How long a reserved seat is held before confirmation (0 = disabled)
+    pub reservation_seconds: i64,
+    /// Whether the interval may be shortened while subscribers exist
+    pub allow_interval_tightening: bool,
+    /// Plan subscribers are pointed to after deactivation
+    pub successor_plan_id: Option<u64>,
+    /// Cap on cycles a subscriber may hold in the prepaid vault
+    pub max_prepaid_cycles: u32,
+    /// Display label for the payment mint (max 10 bytes, may be empty)
+    pub currency_symbol: String,
+    /// Calendar unit for the billing interval
+    pub interval_unit: IntervalUnit,
+    /// Number of `interval_unit`s per cycle (unused for Seconds)
+    pub interval_count: u32,
+    /// Refund the unused part of the current cycle on cancel
+    pub prorated_cancel_refund: bool,
+    /// Team member allowed to pause, unpause and edit non-financial terms
+    pub manager: Option<Pubkey>,
+    /// Creator's plan-creation counter at creation (unique per creator)
+    pub creation_nonce: u64,
+    /// How long before `next_payment` notification services should remind subscribers
+    pub reminder_lead_seconds: i64,
+    /// Seats added automatically when the plan fills (0 = never expand)
+    pub auto_expand_step: u32,
+    /// Ceiling auto-expansion may raise `max_subscribers` to
+    pub hard_cap: u32,
+    /// Live subscriptions granted free by the creator (included in `current_subscribers`)
+    pub comp_subscribers: u32,
+    /// Upper bound (exclusive) on the per-subscriber offset of the first due date (0 = none)
+    pub jitter_seconds: i64,
+    /// One-time fee added to the first charge of a new subscription
+    pub setup_fee: u64,
+    /// Subscriptions recorded as Active or Grace
+    pub active_count: u32,
+    /// Subscriptions recorded as Paused
+    pub paused_count: u32,
+    /// Subscriptions recorded as Expired
+    pub expired_count: u32,
+    /// Day of the month (1-28, UTC) due dates snap forward to; 0 disables
+    pub billing_anchor_day: u8,
+    /// What happens once a subscription is past grace
+    pub past_grace_policy: PastGracePolicy,
+    /// Most missed cycles an `Accumulate` plan lets a subscriber owe
+    pub max_debt_cycles: u32,
+    /// Pledges needed before the plan goes live (0 = no funding round)
+    pub activation_threshold: u32,
+    /// When an unmet funding round fails and pledges become refundable
+    pub funding_deadline: i64,
+    /// Whether pledges reached `activation_threshold`
+    pub threshold_reached: bool,
+    /// Whether payments must be accompanied by a memo instruction
+    pub require_memo: bool,
+    /// Account layout version, see `CURRENT_SCHEMA_VERSION`
+    pub schema_version: u8,
+    /// Notice `deactivate_plan` gives before the plan stops (0 = immediate)
+    pub deactivation_notice_seconds: i64,
+    /// When a scheduled deactivation takes effect (0 = none scheduled)
+    pub deactivation_effective_at: i64,
+    /// Fallback token `process_payment` charges when the primary balance is short
+    pub secondary_payment_mint: Option<Pubkey>,
+    /// Creator's token account receiving secondary-token payments
+    pub secondary_payout_token_account: Pubkey,
+    /// Price per cycle in the secondary token
+    pub secondary_price: u64,
+    /// Tokens collected across all subscriptions, in primary-token terms
+    pub accrued_revenue: u64,
+    /// Time of the last payment collected on the plan (0 = none yet)
+    pub last_payment_at: i64,
+    /// Renewal discount earned per full year of tenure, in basis points
+    pub loyalty_discount_bps_per_year: u16,
+    /// Cap on the loyalty discount, in basis points
+    pub max_loyalty_discount_bps: u16,
+    /// Decimals of `payment_mint`, recorded at creation (`None` for plans created before it was)
+    pub payment_decimals: Option<u8>,
+    /// Longest any subscription may run, from its `created_at` (0 = unlimited)
+    pub max_lifetime_seconds: i64,
+    /// Closes the plan to new subscribers while existing ones keep billing
+    pub new_subscriptions_disabled: bool,
+    /// Minimum time after subscribing before the subscriber may cancel (0 = none)
+    pub min_subscription_seconds: i64,
+    /// Extra tokens subscribers may choose to pay in, each at its own price
+    pub accepted_tokens: Vec<AcceptedToken>,
+    /// Subscribers choose their own price, with `price` as the floor
+    pub pay_what_you_want: bool,
+    /// Reject cancelling in the same transaction that started the subscription
+    pub block_same_tx_cancel: bool,
+    /// How long payments are accepted after `next_payment`
+    pub grace_window: GraceWindow,
+    /// Sequence number of the plan's latest plan-level event
+    pub event_seq: u64,
+    /// Minimum time between changes to `max_subscribers` (0 = no cooldown)
+    pub capacity_change_cooldown_seconds: i64,
+    /// When `max_subscribers` was last changed by the creator or manager (0 = never)
+    pub last_capacity_change: i64,
+    /// Vouched for by the program admin; creators can't set this
+    pub verified: bool,
+    /// Tokens removed with `remove_accepted_token`, kept so subscriptions that chose them still renew
+    pub delisted_tokens: Vec<AcceptedToken>,
+}
+
+impl SubscriptionPlan {
+    pub const LEN: usize = 8 + // discriminator
+        32 + // creator
+        8 + // plan_id
+        8 + // price
+        8 + // interval_seconds
+        4 + // max_subscribers
+        4 + // current_subscribers
+        1 + // is_active
+        1 + // is_paused
+        4 + 200 + // metadata_uri (String with max 200 chars)
+        8 + // created_at
+        1 + // bump
+        8 + // early_tolerance_seconds
+        8 + // maintenance_start
+        8 + // maintenance_end
+        32 + // payment_mint
+        32 + // payout_token_account
+        32 + // metadata_hash
+        8 + // refund_window_seconds
+        1 + // bill_on_weekday_only
+        4 + // prepay_cycles_on_join
+        8 + // reservation_seconds
+        1 + // allow_interval_tightening
+        1 + 8 + // successor_plan_id
+        4 + // max_prepaid_cycles
+        4 + 10 + // currency_symbol
+        1 + // interval_unit
+        4 + // interval_count
+        1 + // prorated_cancel_refund
+        1 + 32 + // manager
+        8 + // creation_nonce
+        8 + // reminder_lead_seconds
+        4 + // auto_expand_step
+        4 + // hard_cap
+        4 + // comp_subscribers
+        8 + // jitter_seconds
+        8 + // setup_fee
+        4 + // active_count
+        4 + // paused_count
+        4 + // expired_count
+        1 + // billing_anchor_day
+        1 + // past_grace_policy
+        4 + // max_debt_cycles
+        4 + // activation_threshold
+        8 + // funding_deadline
+        1 + // threshold_reached
+        1 + // require_memo
+        1 + // schema_version
+        8 + // deactivation_notice_seconds
+        8 + // deactivation_effective_at
+        1 + 32 + // secondary_payment_mint
+        32 + // secondary_payout_token_account
+        8 + // secondary_price
+        8 + // accrued_revenue
+        8 + // last_payment_at
+        2 + // loyalty_discount_bps_per_year
+        2 + // max_loyalty_discount_bps
+        1 + 1 + // payment_decimals
+        8 + // max_lifetime_seconds
+        1 + // new_subscriptions_disabled
+        8 + // min_subscription_seconds
+        4 + MAX_ACCEPTED_TOKENS * AcceptedToken::LEN + // accepted_tokens
+        1 + // pay_what_you_want
+        1 + // block_same_tx_cancel
+        1 + 8 + // grace_window
+        8 + // event_seq
+        8 + // capacity_change_cooldown_seconds
+        8 + // last_capacity_change
+        1 + // verified
+        4 + MAX_ACCEPTED_TOKENS * AcceptedToken::LEN; // delisted_tokens
+
+    /// Cycles charged at `subscribe`; plans created before upfront cycles
+    /// existed store 0, which means a single cycle
+    pub fn cycles_on_join(&self) -> u32 {
+        self.prepay_cycles_on_join.max(1)
+    }
+
+    /// Apply the plan's billing-day rules to a computed due date
+    pub fn billing_date(&self, due: i64) -> Result<i64> {
+        if self.billing_anchor_day > 0 {
+            return next_anchor_day(due, self.billing_anchor_day);
+        }
+        if self.bill_on_weekday_only {
+            next_business_day(due)
+        } else {
+            Ok(due)
+        }
+    }
+
+    /// Advance `event_seq` for a new plan-level event and return it
+    pub fn next_event_seq(&mut self) -> Result<u64> {
+        self.event_seq = self.event_seq.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        Ok(self.event_seq)
+    }
+
+    /// Whether the plan is still collecting pledges toward its threshold
+    pub fn is_funding(&self) -> bool {
+        self.activation_threshold > 0 && !self.threshold_reached
+    }
+
+    /// Add a collected payment to the plan's revenue; free renewals aren't
+    /// payments and leave `last_payment_at` alone
+    pub fn record_revenue(&mut self, amount: u64, now: i64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        self.accrued_revenue = self.accrued_revenue
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        self.last_payment_at = now;
+        Ok(())
+    }
+
+    /// Whether `decimals` (read from the mint passed in) agrees with the
+    /// decimals recorded at creation. Plans that predate the record accept
+    /// any, since `payment_mint` is already pinned by address.
+    pub fn matches_payment_decimals(&self, decimals: u8) -> bool {
+        match self.payment_decimals {
+            Some(recorded) => recorded == decimals,
+            None => true,
+        }
+    }
+
+    /// Per-cycle price when paying in `mint`: `price` for the plan's own
+    /// `payment_mint`, the listed price for an accepted token, else `None`
+    pub fn price_in(&self, mint: &Pubkey) -> Option<u64> {
+        if *mint == self.payment_mint {
+            return Some(self.price);
+        }
+        self.accepted_tokens.iter().find(|token| token.mint == *mint).map(|token| token.price)
+    }
+
+    /// `price_in`, also pricing tokens in `delisted_tokens` for the
+    /// subscriptions that chose them before they were removed
+    pub fn renewal_price_in(&self, mint: &Pubkey) -> Option<u64> {
+        self.price_in(mint).or_else(|| self.delisted_token(mint).map(|token| token.price))
+    }
+
+    /// Entry for `mint` in `delisted_tokens`, unless it has been relisted
+    fn delisted_token(&self, mint: &Pubkey) -> Option<&AcceptedToken> {
+        self.delisted_tokens.iter().find(|token| token.mint == *mint)
+    }
+
+    /// Creator account that receives payments made in `mint`, including
+    /// renewals in a delisted token
+    pub fn payout_account_for(&self, mint: &Pubkey) -> Option<Pubkey> {
+        if *mint == self.payment_mint {
+            return Some(self.payout_token_account);
+        }
+        self.accepted_tokens
+            .iter()
+            .chain(&self.delisted_tokens)
+            .find(|token| token.mint == *mint)
+            .map(|token| token.payout_token_account)
+    }
+
+    /// `matches_payment_decimals` for any token the plan accepts
+    pub fn matches_decimals(&self, mint: &Pubkey, decimals: u8) -> bool {
+        if *mint == self.payment_mint {
+            return self.matches_payment_decimals(decimals);
+        }
+        self.accepted_tokens
+            .iter()
+            .chain(&self.delisted_tokens)
+            .any(|token| token.mint == *mint && token.decimals == decimals)
+    }
+
+    /// Whether the plan takes new subscribers (see `set_new_subscriptions_enabled`)
+    pub fn new_subscriptions_enabled(&self) -> bool {
+        !self.new_subscriptions_disabled
+    }
+
+    /// `is_active`, also counting a scheduled deactivation as done once its
+    /// effective time has passed
+    pub fn is_active_at(&self, now: i64) -> bool {
+        self.is_active
+            && (self.deactivation_effective_at == 0 || now < self.deactivation_effective_at)
+    }
+
+    /// Per-status counter for a status returned by `counted_as`
+    fn status_count_mut(&mut self, status: SubscriptionStatus) -> &mut u32 {
+        match status {
+            SubscriptionStatus::Paused => &mut self.paused_count,
+            SubscriptionStatus::Expired => &mut self.expired_count,
+            _ => &mut self.active_count,
+        }
+    }
+
+    /// Move one subscription between status counters; `from` is `None` for
+    /// a subscription not yet counted. Moves within a counter are no-ops.
+    pub fn count_status_change(
+        &mut self,
+        from: Option<SubscriptionStatus>,
+        to: SubscriptionStatus,
+    ) -> Result<()> {
+        let from = from.and_then(SubscriptionStatus::counted_as);
+        let to = to.counted_as();
+        if from == to {
+            return Ok(());
+        }
+        if let Some(status) = from {
+            let count = self.status_count_mut(status);
+            *count = count.checked_sub(1).ok_or(ErrorCode::Underflow)?;
+        }
+        if let Some(status) = to {
+            let count = self.status_count_mut(status);
+            *count = count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `key` may run manager-level actions (creator or manager)
+    pub fn is_creator_or_manager(&self, key: &Pubkey) -> bool {
+        *key == self.creator || self.manager == Some(*key)
+    }
+
+    /// Check the settings bounded by `interval_seconds` against it, after
+    /// the interval changes: early tolerance, minimum duration, reminder
+    /// lead and jitter
+    pub fn check_interval_bounds(&self) -> Result<()> {
+        require!(
+            self.early_tolerance_seconds >= 0
+                && self.early_tolerance_seconds <= self.interval_seconds / MAX_EARLY_TOLERANCE_DIVISOR,
+            ErrorCode::EarlyToleranceTooLarge
+        );
+        require!(
+            self.min_subscription_seconds <= self.interval_seconds,
+            ErrorCode::InvalidMinSubscriptionDuration
+        );
+        require!(
+            self.reminder_lead_seconds <= self.interval_seconds,
+            ErrorCode::InvalidReminderLead
+        );
+        require!(self.jitter_seconds < self.interval_seconds, ErrorCode::InvalidJitter);
+        Ok(())
+    }
+
+    /// Reject a `max_subscribers` change still inside the cooldown after
+    /// the previous one
+    pub fn check_capacity_change(&self, now: i64) -> Result<()> {
+        if self.capacity_change_cooldown_seconds > 0 && self.last_capacity_change > 0 {
+            let allowed_at = self.last_capacity_change
+                .checked_add(self.capacity_change_cooldown_seconds)
+                .ok_or(ErrorCode::Overflow)?;
+            require!(now >= allowed_at, ErrorCode::CapacityChangeTooSoon);
+        }
+        Ok(())
+    }
+
+    /// Whether `now` falls inside the scheduled maintenance window
+    pub fn in_maintenance(&self, now: i64) -> bool {
+        self.maintenance_start < self.maintenance_end
+            && now >= self.maintenance_start
+            && now < self.maintenance_end
+    }
+
+    /// Schedule shift owed to a subscription for the maintenance window.
+    ///
+    /// A subscription is affected when the window has started by `now`,
+    /// its payable window (`next_payment` through the end of its grace
+    /// period) overlapped the maintenance window, and it hasn't already been
+    /// credited for that window.
+    pub fn maintenance_shift(&self, subscription: &Subscription, now: i64) -> Result<i64> {
+        if self.maintenance_start >= self.maintenance_end
+            || now < self.maintenance_start
+            || subscription.maintenance_credited_until >= self.maintenance_end
+        {
+            return Ok(0);
+        }
+        let grace_end = subscription.next_payment
+            .saturating_add(subscription.grace_period(self)?);
+        let overlaps = subscription.next_payment < self.maintenance_end
+            && grace_end >= self.maintenance_start;
+        if overlaps {
+            Ok(self.maintenance_end - self.maintenance_start)
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Subscription {
+    /// Subscriber's public key
+    pub subscriber: Pubkey,
+    /// Associated plan ID
+    pub plan_id: u64,
+    /// Plan creator's public key
+    pub creator: Pubkey,
+    /// Whether subscription is active
+    pub is_active: bool,
+    /// Timestamp of last payment
+    pub last_payment: i64,
+    /// Timestamp when next payment is due
+    pub next_payment: i64,
+    /// Total number of payments made
+    pub total_payments: u64,
+    /// PDA bump seed
+    pub bump: u8,
+    /// End of the last maintenance window already credited to the schedule
+    pub maintenance_credited_until: i64,
+    /// Subscriber-supplied invoice/PO reference (all zeros if unset)
+    pub reference: [u8; 32],
+    /// Hard end of a fixed-term subscription (0 = open-ended)
+    pub ends_at: i64,
+    /// Reservation expiry while awaiting confirmation (0 = not reserved)
+    pub reserved_until: i64,
+    /// Lifetime tokens paid on this subscription
+    pub total_paid_amount: u64,
+    /// Billing interval locked in at subscribe time
+    pub interval_seconds: i64,
+    /// Tokens held for this subscription in the plan's prepaid vault
+    pub prepaid_balance: u64,
+    /// Lifecycle status, kept in sync with `is_active`
+    pub status: SubscriptionStatus,
+    /// Calendar unit locked in at subscribe time
+    pub interval_unit: IntervalUnit,
+    /// Units per cycle locked in at subscribe time
+    pub interval_count: u32,
+    /// Bundle this subscription was created through (None = standalone)
+    pub bundle_id: Option<u64>,
+    /// Held by the creator or admin; blocks billing without expiring or freeing the seat
+    pub is_frozen: bool,
+    /// Negotiated grace period replacing the plan default (None = default)
+    pub grace_override_seconds: Option<i64>,
+    /// Granted free by the creator; renews without payment until revoked
+    pub is_comp: bool,
+    /// Whether the plan's setup fee has been charged on this subscription
+    pub setup_fee_paid: bool,
+    /// Delegated renewals the subscriber still allows via `process_payment_delegated`
+    pub auto_renew_cycles_remaining: u32,
+    /// Time after which delegated renewals are refused (0 = never authorized)
+    pub auto_renew_expires_at: i64,
+    /// Tokens escrowed in the pledge vault while the plan is funding
+    pub pledged_amount: u64,
+    /// Account layout version, see `CURRENT_SCHEMA_VERSION`
+    pub schema_version: u8,
+    /// When the subscription was created (0 = before this was recorded)
+    pub created_at: i64,
+    /// Whether the last renewal found `blocked_token_account` frozen
+    pub payment_blocked: bool,
+    /// Token account that was frozen when `payment_blocked` was set
+    pub blocked_token_account: Pubkey,
+    /// Nonce the next `process_payment_with_permit` permit must carry
+    pub permit_nonce: u64,
+    /// Mint chosen at subscribe; all zeros bills in the plan's `payment_mint`
+    pub payment_mint: Pubkey,
+    /// Per-cycle price chosen on a pay-what-you-want plan (0 = the plan's price)
+    pub chosen_price: u64,
+    /// Amount paid per cycle at the latest charge, after discounts
+    pub cycle_amount_paid: u64,
+    /// Whether `created_at` was backfilled on migration rather than recorded
+    pub created_at_estimated: bool,
+    /// Cycles charged upfront when the subscription started; 0 if it
+    /// started before this was recorded
+    pub join_cycles: u32,
+}
+
+impl Subscription {
+    /// Add a charge to the lifetime spend, failing rather than wrapping
+    pub fn record_payment(&mut self, amount: u64) -> Result<()> {
+        self.total_paid_amount = self.total_paid_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::TotalPaidOverflow)?;
+        Ok(())
+    }
+
+    /// Grace allowed after `next_payment`: the override if set, else the
+    /// plan's `grace_window`
+    pub fn grace_period(&self, subscription_plan: &SubscriptionPlan) -> Result<i64> {
+        if let Some(seconds) = self.grace_override_seconds {
+            return Ok(seconds);
+        }
+        match subscription_plan.grace_window {
+            GraceWindow::Default => Ok(GRACE_PERIOD_SECONDS),
+            GraceWindow::Seconds { seconds } => Ok(seconds),
+            GraceWindow::Cycles { cycles } => Ok(
+                self.add_intervals(self.next_payment, cycles as u32)? - self.next_payment
+            ),
+        }
+    }
+
+    /// Set the lifecycle status, keeping `is_active` in sync
+    pub fn set_status(&mut self, status: SubscriptionStatus) {
+        self.status = status;
+        self.is_active = status.is_live();
+    }
+
+    /// Timestamp `cycles` billing intervals after `from`
+    pub fn add_intervals(&self, from: i64, cycles: u32) -> Result<i64> {
+        match self.interval_unit {
+            IntervalUnit::Months => add_months(
+                from,
+                self.interval_count.checked_mul(cycles).ok_or(ErrorCode::Overflow)?,
+            ),
+            IntervalUnit::Seconds | IntervalUnit::Days => self.interval_seconds
+                .checked_mul(cycles as i64)
+                .and_then(|period| from.checked_add(period))
+                .ok_or_else(|| error!(ErrorCode::Overflow)),
+        }
+    }
+
+    /// Calendar-aware `next_payment_after`: same anchoring and skipping rules
+    pub fn next_due_after(&self, scheduled: i64, now: i64) -> Result<i64> {
+        if self.interval_unit != IntervalUnit::Months {
+            return next_payment_after(scheduled, now, self.interval_seconds);
+        }
+        // Months differ in length, so step one cycle at a time. Payments are
+        // only accepted within grace, so this runs at most a couple of times.
+        let mut next = self.add_intervals(scheduled, 1)?;
+        loop {
+            let following = self.add_intervals(next, 1)?;
+            if following >= now {
+                return Ok(next);
+            }
+            next = following;
+        }
+    }
+
+    /// Calendar-aware `due_cycles` from this subscription's `next_payment`
+    pub fn cycles_due(&self, now: i64, max_cycles: u32) -> Result<u32> {
+        if self.interval_unit != IntervalUnit::Months {
+            return due_cycles(self.next_payment, now, self.interval_seconds, max_cycles);
+        }
+        let mut cycles = 0;
+        let mut due = self.next_payment;
+        while due <= now && cycles < max_cycles {
+            cycles += 1;
+            due = self.add_intervals(due, 1)?;
+        }
+        Ok(cycles)
+    }
+
+    /// Whether a fixed-term subscription has reached its end date
+    pub fn has_ended(&self, now: i64) -> bool {
+        self.ends_at != 0 && now >= self.ends_at
+    }
+
+    /// Whether the subscription has run for the plan's `max_lifetime_seconds`
+    /// 
+    /// Counted from `created_at`, so a migrated subscription's lifetime
+    /// starts at its latest payment before migration.
+    pub fn lifetime_reached(&self, max_lifetime_seconds: i64, now: i64) -> bool {
+        max_lifetime_seconds != 0 && now.saturating_sub(self.created_at) >= max_lifetime_seconds
+    }
+
+    /// Per-cycle price before discounts given the plan's current `price`:
+    /// the subscriber's `chosen_price`, unless the floor has risen above it
+    pub fn cycle_price(&self, plan_price: u64) -> u64 {
+        plan_price.max(self.chosen_price)
+    }
+
+    /// Record the per-cycle amount of a charge covering `cycles` cycles
+    pub fn record_cycle_amount(&mut self, amount: u64, cycles: u32) {
+        if cycles > 0 {
+            self.cycle_amount_paid = amount / cycles as u64;
+        }
+    }
+
+    /// Mint the subscription is billed in: the one chosen at subscribe, or
+    /// the plan's `payment_mint` for subscriptions that didn't choose
+    pub fn billing_mint(&self, subscription_plan: &SubscriptionPlan) -> Pubkey {
+        if self.payment_mint == Pubkey::default() {
+            subscription_plan.payment_mint
+        } else {
+            self.payment_mint
+        }
+    }
+
+    /// Cycles charged when the subscription started, as recorded then, so a
+    /// later change to the plan's upfront cycles doesn't move it; falls back
+    /// to the plan's current `cycles_on_join` for subscriptions that predate
+    /// the record
+    pub fn cycles_paid_on_join(&self, subscription_plan: &SubscriptionPlan) -> u32 {
+        if self.join_cycles > 0 {
+            self.join_cycles
+        } else {
+            subscription_plan.cycles_on_join()
+        }
+    }
+
+    /// Whether the subscription has run for the plan's `min_subscription_seconds`.
+    /// Subscriptions from before `created_at` was recorded always have,
+    /// including migrated ones whose `created_at` is only an estimate.
+    pub fn min_duration_met(&self, min_subscription_seconds: i64, now: i64) -> bool {
+        self.created_at == 0
+            || self.created_at_estimated
+            || now.saturating_sub(self.created_at) >= min_subscription_seconds
+    }
+
+    /// When the subscription stops for good: the earlier of `ends_at` and the
+    /// end of the plan's `max_lifetime_seconds`, or `None` if neither applies
+    pub fn hard_end(&self, max_lifetime_seconds: i64) -> Option<i64> {
+        let term_end = (self.ends_at != 0).then_some(self.ends_at);
+        let lifetime_end = (max_lifetime_seconds != 0)
+            .then(|| self.created_at.saturating_add(max_lifetime_seconds));
+        match (term_end, lifetime_end) {
+            (Some(term_end), Some(lifetime_end)) => Some(term_end.min(lifetime_end)),
+            (term_end, lifetime_end) => term_end.or(lifetime_end),
+        }
+    }
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // subscriber
+        8 + // plan_id
+        32 + // creator
+        1 + // is_active
+        8 + // last_payment
+        8 + // next_payment
+        8 + // total_payments
+        1 + // bump
+        8 + // maintenance_credited_until
+        32 + // reference
+        8 + // ends_at
+        8 + // reserved_until
+        8 + // total_paid_amount
+        8 + // interval_seconds
+        8 + // prepaid_balance
+        1 + // status
+        1 + // interval_unit
+        4 + // interval_count
+        1 + 8 + // bundle_id
+        1 + // is_frozen
+        1 + 8 + // grace_override_seconds
+        1 + // is_comp
+        1 + // setup_fee_paid
+        4 + // auto_renew_cycles_remaining
+        8 + // auto_renew_expires_at
+        8 + // pledged_amount
+        1 + // schema_version
+        8 + // created_at
+        1 + // payment_blocked
+        32 + // blocked_token_account
+        8 + // permit_nonce
+        32 + // payment_mint
+        8 + // chosen_price
+        8 + // cycle_amount_paid
+        1 + // created_at_estimated
+        4; // join_cycles
+}
+
+/// A subscriber's off-chain authorization for one
+/// `process_payment_with_permit` charge
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PaymentPermit {
+    /// Subscription account the permit may charge
+    pub subscription: Pubkey,
+    /// Most the charge may take
+    pub max_amount: u64,
+    /// Must equal the subscription's `permit_nonce`, so each permit pays once
+    pub nonce: u64,
+    /// Last moment the permit may be used
+    pub expires_at: i64,
+}
+
+impl PaymentPermit {
+    /// Bytes the subscriber signs: `PERMIT_DOMAIN`, this program's id, then
+    /// the permit's fields in order (its Borsh encoding)
+    pub fn message(&self) -> Vec<u8> {
+        [
+            PERMIT_DOMAIN,
+            crate::ID.as_ref(),
+            self.subscription.as_ref(),
+            &self.max_amount.to_le_bytes(),
+            &self.nonce.to_le_bytes(),
+            &self.expires_at.to_le_bytes(),
+        ]
+        .concat()
+    }
+}
+
+/// A token a plan accepts besides its `payment_mint` (see `add_accepted_token`)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AcceptedToken {
+    pub mint: Pubkey,
+    /// Price per cycle in this token's base units
+    pub price: u64,
+    /// Decimals read from the mint when it was added
+    pub decimals: u8,
+    /// Creator's token account receiving payments in this token
+    pub payout_token_account: Pubkey,
+}
+
+impl AcceptedToken {
+    pub const LEN: usize = 32 + // mint
+        8 + // price
+        1 + // decimals
+        32; // payout_token_account
+}
+
+/// Return data of `subscribe` and `process_payment`
+/// 
+/// One structured record per payment for bookkeeping, carrying the same
+/// values as the instruction's `PaymentProcessed` (`total_payments` is its
+/// `payment_number`). New fields are appended so older decoders still read
+/// the leading ones.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PaymentReceipt {
+    /// Subscription's `next_payment` after the instruction
+    pub next_payment: i64,
+    /// Subscription's `total_payments` after the instruction
+    pub total_payments: u64,
+    /// Tokens transferred by the instruction
+    pub charged_amount: u64,
+    pub plan_id: u64,
+    /// Platform fee taken out of `charged_amount`. Renewals carry no
+    /// platform fee, so this is 0 until one is introduced.
+    pub fee: u64,
+    /// `charged_amount` less `fee`, as received by the creator's payout account
+    pub net_to_creator: u64,
+    pub timestamp: i64,
+}
+
+impl PaymentReceipt {
+    pub fn for_subscription(subscription: &Subscription, charged_amount: u64, now: i64) -> Self {
+        Self {
+            next_payment: subscription.next_payment,
+            total_payments: subscription.total_payments,
+            charged_amount,
+            plan_id: subscription.plan_id,
+            fee: 0,
+            net_to_creator: charged_amount,
+            timestamp: now,
+        }
+    }
+}
+
+/// Return data of `plan_health`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlanHealth {
+    /// `current_subscribers / max_subscribers` in basis points
+    pub utilization_bps: u32,
+    pub current_subscribers: u32,
+    pub max_subscribers: u32,
+    /// Whether the plan accepts subscribers and payments, counting a
+    /// scheduled deactivation that has taken effect
+    pub is_active: bool,
+    pub is_paused: bool,
+    /// Whole days since the plan last collected a payment (None = never)
+    pub days_since_last_payment: Option<u32>,
+    pub accrued_revenue: u64,
+}
+
+impl PlanHealth {
+    pub fn for_plan(subscription_plan: &SubscriptionPlan, now: i64) -> Self {
+        let utilization_bps = (subscription_plan.current_subscribers as u64 * 10_000)
+            .checked_div(subscription_plan.max_subscribers as u64)
+            .unwrap_or(0);
+        let days_since_last_payment = (subscription_plan.last_payment_at != 0).then(|| {
+            let days = now.saturating_sub(subscription_plan.last_payment_at).max(0) / SECONDS_PER_DAY;
+            u32::try_from(days).unwrap_or(u32::MAX)
+        });
+        Self {
+            utilization_bps: u32::try_from(utilization_bps).unwrap_or(u32::MAX),
+            current_subscribers: subscription_plan.current_subscribers,
+            max_subscribers: subscription_plan.max_subscribers,
+            is_active: subscription_plan.is_active_at(now),
+            is_paused: subscription_plan.is_paused,
+            days_since_last_payment,
+            accrued_revenue: subscription_plan.accrued_revenue,
+        }
+    }
+}
+
+/// Reason a plan refused a new subscriber, in precedence order
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SubscribeRejectReason {
+    PlanInactive,
+    PlanPaused,
+    FundingInProgress,
+    PlanFull,
+    NewSubscriptionsDisabled,
+}
+
+impl SubscribeRejectReason {
+    /// Error the rejected instruction fails with
+    pub fn error(self) -> ErrorCode {
+        match self {
+            Self::PlanInactive => ErrorCode::PlanInactive,
+            Self::PlanPaused => ErrorCode::PlanPaused,
+            Self::FundingInProgress => ErrorCode::FundingInProgress,
+            Self::PlanFull => ErrorCode::PlanFull,
+            Self::NewSubscriptionsDisabled => ErrorCode::NewSubscriptionsDisabled,
+        }
+    }
+}
+
+/// How long a plan accepts payment after `next_payment`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GraceWindow {
+    /// `GRACE_PERIOD_SECONDS`
+    #[default]
+    Default,
+    /// A fixed number of seconds
+    Seconds { seconds: i64 },
+    /// This many billing intervals of the subscription, measured from its
+    /// `next_payment` when checked, so it follows interval changes. 0 means
+    /// payment is due exactly at `next_payment` with no slack.
+    Cycles { cycles: u8 },
+}
+
+/// How a plan treats subscriptions that are past their grace period
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PastGracePolicy {
+    /// Lapse: payments are refused and `refresh_status` expires the
+    /// subscription, freeing its seat
+    #[default]
+    Expire,
+    /// Keep owing: `process_payment` charges every missed cycle, until more
+    /// than `max_debt_cycles` are owed and the subscription expires
+    Accumulate,
+}
+
+/// Unit of a plan's billing interval
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum IntervalUnit {
+    /// Fixed `interval_seconds`
+    #[default]
+    Seconds,
+    /// `interval_count` days (`interval_seconds` = count * 86400)
+    Days,
+    /// `interval_count` calendar months in UTC
+    Months,
+}
+
+/// Lifecycle status of a subscription, stored as a single byte so clients
+/// can find subscriptions in a given state with a memcmp filter.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SubscriptionStatus {
+    /// Paid up
+    #[default]
+    Active,
+    /// Payment overdue but within the grace period
+    Grace,
+    /// Lapsed past grace or reached its fixed-term end
+    Expired,
+    /// Cancelled or refunded by the subscriber
+    Cancelled,
+    /// Plan is paused; billing resumes when unpaused
+    Paused,
+    /// Seat reserved, awaiting `confirm_subscription`
+    Pending,
+}
+
+impl SubscriptionStatus {
+    /// Whether the subscription still holds a seat and can be billed
+    pub fn is_live(self) -> bool {
+        matches!(self, Self::Active | Self::Grace | Self::Paused)
+    }
+
+    /// Whether the subscription counts toward `current_subscribers`,
+    /// including unconfirmed reservations
+    pub fn holds_seat(self) -> bool {
+        self.is_live() || self == Self::Pending
+    }
+
+    /// Which of the plan's status counters this status is tallied under:
+    /// Grace counts as Active; Cancelled and Pending aren't counted
+    pub fn counted_as(self) -> Option<Self> {
+        match self {
+            Self::Active | Self::Grace => Some(Self::Active),
+            Self::Paused | Self::Expired => Some(self),
+            Self::Cancelled | Self::Pending => None,
+        }
+    }
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[event]
+pub struct SubscriptionPlanCreated {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub price: u64,
+    pub interval_seconds: i64,
+    pub metadata_hash: [u8; 32],
+    pub currency_symbol: String,
+    pub timestamp: i64,
+    pub creation_nonce: u64,
+    /// Decimals `price` is denominated in; `None` only when cloned from a
+    /// plan that predates the record
+    pub payment_decimals: Option<u8>,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+#[event]
+pub struct SubscriptionCreated {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub prepaid_cycles: u32,
+    pub timestamp: i64,
+    pub setup_fee: u64,
+    /// Persisted `Subscription::created_at`; earlier than `timestamp` when
+    /// the subscription was reserved or pledged before it went live
+    pub created_at: i64,
+    /// Mint the subscription is billed in
+    pub payment_mint: Pubkey,
+    /// Price per cycle the subscriber chose (0 = the plan's price)
+    pub chosen_price: u64,
+}
+
+#[event]
+pub struct PlanCapacityReached {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub current_subscribers: u32,
+    pub max_subscribers: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentProcessed {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub payment_number: u64,
+    pub total_paid_amount: u64,
+    pub reference: [u8; 32],
+    pub timestamp: i64,
+    /// Mint `amount` was paid in: the plan's payment mint or its secondary
+    pub payment_mint: Pubkey,
+}
+
+#[event]
+pub struct CatchUpPaymentProcessed {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub cycles_charged: u32,
+    pub payment_number: u64,
+    pub total_paid_amount: u64,
+    pub reference: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionCancelled {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionPlanUpdated {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub metadata_hash: [u8; 32],
+    pub old_price: u64,
+    pub new_price: u64,
+    pub old_interval_seconds: i64,
+    pub new_interval_seconds: i64,
+    pub old_max_subscribers: u32,
+    pub new_max_subscribers: u32,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+impl SubscriptionPlanUpdated {
+    /// Update event for changes that leave price, interval and capacity
+    /// as-is; takes the plan's next `event_seq`
+    pub fn settings_changed(plan: &mut SubscriptionPlan, timestamp: i64) -> Result<Self> {
+        Ok(Self {
+            creator: plan.creator,
+            plan_id: plan.plan_id,
+            metadata_hash: plan.metadata_hash,
+            old_price: plan.price,
+            new_price: plan.price,
+            old_interval_seconds: plan.interval_seconds,
+            new_interval_seconds: plan.interval_seconds,
+            old_max_subscribers: plan.max_subscribers,
+            new_max_subscribers: plan.max_subscribers,
+            timestamp,
+            event_seq: plan.next_event_seq()?,
+        })
+    }
+}
+
+#[event]
+pub struct SubscriptionPlanPaused {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+#[event]
+pub struct SubscriptionPlanUnpaused {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+#[event]
+pub struct SubscriptionPlanDeactivated {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+    /// Active subscriptions left to wind down
+    pub current_subscribers: u32,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+#[event]
+pub struct PayoutAccountChanged {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub old_account: Pubkey,
+    pub new_account: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MaintenanceScheduled {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub start: i64,
+    pub end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SelfRefundIssued {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionEnded {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionLifetimeReached {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub created_at: i64,
+    pub max_lifetime_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SeatReserved {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub reserved_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReservationReleased {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TreasurySwept {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionIntervalMigrated {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub old_interval_seconds: i64,
+    pub new_interval_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FollowedSuccessor {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub old_plan_id: u64,
+    pub new_plan_id: u64,
+    pub amount: u64,
+    pub prepaid_cycles: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PrepaidDeposited {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub prepaid_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PrepaidBalanceRefunded {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionStatusChanged {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub old_status: SubscriptionStatus,
+    pub new_status: SubscriptionStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProratedRefundIssued {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriberRemoved {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PauseExtended {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub additional_seconds: i64,
+    pub maintenance_start: i64,
+    pub maintenance_end: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriberCountReconciled {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub previous_count: u32,
+    pub new_count: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundleSubscribed {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub bundle_id: u64,
+    pub plan_ids: Vec<u64>,
+    pub amount: u64,
+    pub next_payment: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundlePaymentProcessed {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub bundle_id: u64,
+    pub amount: u64,
+    pub next_payment: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundleCancelled {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub bundle_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundleDeactivated {
+    pub creator: Pubkey,
+    pub bundle_id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentUpcoming {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub next_payment: i64,
+    pub remind_at: i64,
+    pub reference: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionFrozen {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionUnfrozen {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PlanSoldOut {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub max_subscribers: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CapacityAutoExpanded {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub old_max_subscribers: u32,
+    pub new_max_subscribers: u32,
+    pub hard_cap: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GraceOverrideSet {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub grace_override_seconds: Option<i64>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeExemptionChanged {
+    pub creator: Pubkey,
+    pub exempt: bool,
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CreationFeeCollected {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub fee: u64,
+    pub fee_destination: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionGranted {
+    pub beneficiary: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub next_payment: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AutoRenewAuthorized {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub cycles: u32,
+    pub expires_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NewSubscriptionsToggled {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscribeRejected {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub reason: SubscribeRejectReason,
+    pub is_active: bool,
+    pub is_paused: bool,
+    pub current_subscribers: u32,
+    pub max_subscribers: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionPlanReactivated {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// Program admin who reactivated the plan
+    pub admin: Pubkey,
+    pub current_subscribers: u32,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+impl SubscriptionPlanReactivated {
+    /// Takes the plan's next `event_seq`
+    pub fn for_plan(plan: &mut SubscriptionPlan, admin: Pubkey, timestamp: i64) -> Result<Self> {
+        Ok(Self {
+            creator: plan.creator,
+            plan_id: plan.plan_id,
+            admin,
+            current_subscribers: plan.current_subscribers,
+            timestamp,
+            event_seq: plan.next_event_seq()?,
+        })
+    }
+}
+
+#[event]
+pub struct PledgeMade {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// Tokens escrowed: one cycle at the plan's price
+    pub amount: u64,
+    /// Pledges so far, including this one
+    pub pledges: u32,
+    pub activation_threshold: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ThresholdReached {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub pledges: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundingFailed {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// Pledges held when the round failed
+    pub pledges: u32,
+    pub activation_threshold: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PledgeRefunded {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionAccountMigrated {
+    pub subscriber: Pubkey,
+    pub plan_id: u64,
+    pub old_len: u32,
+    pub new_len: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub old_version: u8,
+    pub new_version: u8,
+    pub old_len: u32,
+    pub new_len: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DeactivationScheduled {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// When the plan stops accepting subscribers and payments
+    pub effective_at: i64,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+impl DeactivationScheduled {
+    /// Takes the plan's next `event_seq`
+    pub fn for_plan(plan: &mut SubscriptionPlan, effective_at: i64, timestamp: i64) -> Result<Self> {
+        Ok(Self {
+            creator: plan.creator,
+            plan_id: plan.plan_id,
+            effective_at,
+            timestamp,
+            event_seq: plan.next_event_seq()?,
+        })
+    }
+}
+
+#[event]
+pub struct DeactivationCancelled {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub timestamp: i64,
+    /// Plan's `event_seq` after this event; consecutive per plan
+    pub event_seq: u64,
+}
+
+impl DeactivationCancelled {
+    /// Takes the plan's next `event_seq`
+    pub fn for_plan(plan: &mut SubscriptionPlan, timestamp: i64) -> Result<Self> {
+        Ok(Self {
+            creator: plan.creator,
+            plan_id: plan.plan_id,
+            timestamp,
+            event_seq: plan.next_event_seq()?,
+        })
+    }
+}
+
+#[event]
+pub struct LoyaltyDiscountApplied {
+    pub subscriber: Pubkey,
+    pub plan_id: u64,
+    pub discount_bps: u16,
+    /// Total charged after the discount
+    pub discounted_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentBlockedFrozenAccount {
+    pub subscriber: Pubkey,
+    pub plan_id: u64,
+    /// Frozen account the renewal would have been charged from
+    pub token_account: Pubkey,
+    /// Amount that would have been charged
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PaymentBlockCleared {
+    pub subscriber: Pubkey,
+    pub plan_id: u64,
+    pub token_account: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FinalPaymentProrated {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub full_amount: u64,
+    pub amount: u64,
+    pub ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PlanSnapshotCreated {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub snapshot_index: u64,
+    pub current_subscribers: u32,
+    pub total_revenue: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ChosenPriceUpdated {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Overpayment {
+    pub subscriber: Pubkey,
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    /// Paid beyond the current price
+    pub amount: u64,
+    /// Whether `amount` went to the prepaid vault rather than the creator
+    pub credited_to_prepaid: bool,
+    pub prepaid_balance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PlanVerificationChanged {
+    pub creator: Pubkey,
+    pub plan_id: u64,
+    pub admin: Pubkey,
+    pub verified: bool,
+    pub timestamp: i64,
+}
+
+// ============================================================================
+// Error Codes
+// ============================================================================
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Subscription plan is inactive")]
+    PlanInactive,
+    #[msg("Subscription plan is full")]
+    PlanFull,
+    #[msg("Payment is not due yet")]
+    PaymentNotDue,
+    #[msg("Subscription is inactive")]
+    SubscriptionInactive,
+    #[msg("Price must be greater than 0")]
+    InvalidPrice,
+    #[msg("Interval must be at least 60 seconds")]
+    IntervalTooShort,
+    #[msg("Max subscribers must be greater than 0")]
+    InvalidMaxSubscribers,
+    #[msg("Metadata URI exceeds 200 character limit")]
+    MetadataUriTooLong,
+    #[msg("Mathematical overflow occurred")]
+    Overflow,
+    #[msg("Mathematical underflow occurred")]
+    Underflow,
+    #[msg("Invalid token account owner")]
+    InvalidTokenAccountOwner,
+    #[msg("Token account mint mismatch")]
+    MintMismatch,
+    #[msg("Subscription plan is paused")]
+    PlanPaused,
+    #[msg("Payment is too late (beyond grace period)")]
+    PaymentTooLate,
+    #[msg("Subscription is still active, cannot close")]
+    SubscriptionStillActive,
+    #[msg("New max subscribers cannot be less than current subscribers")]
+    MaxSubscribersTooLow,
+    #[msg("Invalid creator")]
+    InvalidCreator,
+    #[msg("Invalid subscriber")]
+    InvalidSubscriber,
+    #[msg("Invalid plan ID")]
+    InvalidPlanId,
+    #[msg("Plan is already paused")]
+    PlanAlreadyPaused,
+    #[msg("Plan is not paused")]
+    PlanNotPaused,
+    #[msg("Plan is already inactive")]
+    PlanAlreadyInactive,
+    #[msg("Early tolerance must be between 0 and a quarter of the interval")]
+    EarlyToleranceTooLarge,
+    #[msg("Max cycles must be greater than 0")]
+    InvalidMaxCycles,
+    #[msg("Maintenance window start must be before its end")]
+    InvalidMaintenanceWindow,
+    #[msg("Maintenance window must start in the future")]
+    MaintenanceWindowInPast,
+    #[msg("Plan is under maintenance")]
+    PlanUnderMaintenance,
+    #[msg("Token account is not the plan's payout account")]
+    InvalidPayoutAccount,
+    #[msg("Refund window must be between 0 and the billing interval")]
+    InvalidRefundWindow,
+    #[msg("Refund window has expired")]
+    RefundWindowExpired,
+    #[msg("End date must be after the next payment")]
+    InvalidEndDate,
+    #[msg("Subscription is past due beyond the grace period")]
+    SubscriptionLapsed,
+    #[msg("Prepay cycles on join must be between 1 and 36")]
+    InvalidPrepayCycles,
+    #[msg("Insufficient funds in subscriber token account")]
+    InsufficientFunds,
+    #[msg("Token program does not own the payment mint")]
+    WrongTokenProgram,
+    #[msg("Reservation window must be 0 or between 60 seconds and 7 days")]
+    InvalidReservationWindow,
+    #[msg("Plan does not accept reservations")]
+    ReservationsDisabled,
+    #[msg("Subscription has no pending reservation")]
+    NoReservation,
+    #[msg("Reservation has expired")]
+    ReservationExpired,
+    #[msg("Reservation has not expired yet")]
+    ReservationNotExpired,
+    #[msg("Reservation is still pending")]
+    ReservationPending,
+    #[msg("Lifetime paid amount would overflow")]
+    TotalPaidOverflow,
+    #[msg("Signer is not the program admin")]
+    InvalidAdmin,
+    #[msg("Amount must be greater than 0")]
+    InvalidAmount,
+    #[msg("Amount exceeds treasury balance")]
+    InsufficientTreasuryBalance,
+    #[msg("Interval cannot be shortened for existing subscribers")]
+    IntervalChangeNotAllowed,
+    #[msg("Successor plan is invalid for this plan")]
+    InvalidSuccessorPlan,
+    #[msg("Prepaid balance would exceed the plan's prepaid cycle cap")]
+    PrepayLimitExceeded,
+    #[msg("Prepaid balance does not cover this payment")]
+    InsufficientPrepaidBalance,
+    #[msg("Refund the prepaid balance before closing")]
+    PrepaidBalanceRemaining,
+    #[msg("Currency symbol too long (max 10 bytes)")]
+    CurrencySymbolTooLong,
+    #[msg("Interval count must be between 1 and 366")]
+    InvalidIntervalCount,
+    #[msg("Prorated cancel refunds are not enabled for this plan")]
+    ProratedRefundDisabled,
+    #[msg("Too many accounts passed for one batch")]
+    TooManyAccounts,
+    #[msg("Account is not a subscription of this plan")]
+    InvalidSubscriptionAccount,
+    #[msg("Only the plan creator can perform this action")]
+    Unauthorized,
+    #[msg("Price is below the configured minimum")]
+    PriceBelowMinimum,
+    #[msg("Subscription account passed more than once")]
+    DuplicateSubscriptionAccount,
+    #[msg("Bundle must contain between 2 and 5 plans")]
+    InvalidBundleSize,
+    #[msg("Account is not a member plan of this bundle")]
+    InvalidBundlePlan,
+    #[msg("Bundle member plans must share the same billing interval")]
+    BundleIntervalMismatch,
+    #[msg("Bundle is not active")]
+    BundleInactive,
+    #[msg("Bundle subscriptions must be billed and cancelled through the bundle")]
+    BundleMemberSubscription,
+    #[msg("Reminder lead must be between zero and the plan interval")]
+    InvalidReminderLead,
+    #[msg("Subscription is frozen")]
+    SubscriptionFrozen,
+    #[msg("Subscription is not frozen")]
+    SubscriptionNotFrozen,
+    #[msg("Hard cap must be at least the current max subscribers")]
+    InvalidAutoExpand,
+    #[msg("Grace override must be between zero and 90 days")]
+    InvalidGraceOverride,
+    #[msg("Fee destination does not match the program config")]
+    InvalidFeeDestination,
+    #[msg("Insufficient lamports to pay the plan creation fee")]
+    InsufficientFeeBalance,
+    #[msg("Comp subscriptions are not eligible for refunds")]
+    CompSubscription,
+    #[msg("Subscription was not granted by the creator")]
+    NotCompSubscription,
+    #[msg("Invariant broken: subscriber counts do not match subscription accounts")]
+    InvariantSubscriberCount,
+    #[msg("Invariant broken: live subscription is past its payment deadline")]
+    InvariantOverdueSubscription,
+    #[msg("Invariant broken: payout account mint differs from plan payment mint")]
+    InvariantPaymentMint,
+    #[msg("Jitter must be non-negative and shorter than the plan interval")]
+    InvalidJitter,
+    #[msg("Auto-renew needs at least one cycle and a future expiry")]
+    InvalidAutoRenewAuthorization,
+    #[msg("Auto-renew authorization has expired")]
+    AuthorizationExpired,
+    #[msg("Auto-renew authorization has no cycles left")]
+    AutoRenewExhausted,
+    #[msg("Charge exceeds the subscriber's max_token_amount")]
+    SlippageExceeded,
+    #[msg("Billing anchor day must be 0-28, needs a monthly interval and can't be combined with weekday-only billing")]
+    InvalidBillingAnchorDay,
+    #[msg("Subscriber owes more cycles than the plan's max_debt_cycles")]
+    MaxDebtExceeded,
+    #[msg("Plan is already active")]
+    PlanAlreadyActive,
+    #[msg("Plan is collecting pledges; join with pledge")]
+    FundingInProgress,
+    #[msg("Activation threshold must fit max_subscribers with a future deadline, before anyone joins")]
+    InvalidActivationThreshold,
+    #[msg("Funding round is no longer accepting pledges")]
+    FundingClosed,
+    #[msg("Funding round has not failed")]
+    FundingNotFailed,
+    #[msg("Subscription has no pledge")]
+    NoPledge,
+    #[msg("Pledge must be activated or refunded first")]
+    PledgePending,
+    #[msg("Payer and payee token accounts must differ")]
+    SelfPayment,
+    #[msg("Plan requires a memo instruction in the payment transaction")]
+    MemoRequired,
+    #[msg("Subscription account is already at the current size")]
+    AccountAlreadyCurrent,
+    #[msg("Account is not the subscription plan PDA")]
+    InvalidPlanAccount,
+    #[msg("Account type cannot be migrated")]
+    UnsupportedAccountType,
+    #[msg("Account layout is too old; migrate it first")]
+    SchemaTooOld,
+    #[msg("Funding authority is neither the owner nor an approved delegate of the token account")]
+    UnauthorizedFundingAuthority,
+    #[msg("Deactivation notice must be between 0 and MAX_DEACTIVATION_NOTICE_SECONDS")]
+    InvalidDeactivationNotice,
+    #[msg("A deactivation is already scheduled for this plan")]
+    DeactivationAlreadyScheduled,
+    #[msg("No pending deactivation to cancel")]
+    NoDeactivationScheduled,
+    #[msg("Plan has no secondary payment token")]
+    NoSecondaryPayment,
+    #[msg("Secondary payment mint must differ from the plan's payment mint")]
+    InvalidSecondaryMint,
+    #[msg("Secondary payment needs the subscriber's token account, the mint and the creator's payout account")]
+    InvalidSecondaryPaymentAccounts,
+    #[msg("Neither the primary nor the secondary token account can cover the payment")]
+    AllPaymentTokensInsufficient,
+    #[msg("Loyalty discount must not exceed its cap, and the cap must not exceed 10000 bps")]
+    InvalidLoyaltyDiscount,
+    #[msg("pay_all_due takes 1 to MAX_PAY_ALL_DUE groups of five accounts")]
+    InvalidPayAllDueAccounts,
+    #[msg("Payment mint decimals don't match the plan's recorded decimals")]
+    PaymentDecimalsMismatch,
+    #[msg("Subscription's payments are not blocked")]
+    PaymentNotBlocked,
+    #[msg("Token account is still frozen")]
+    TokenAccountFrozen,
+    #[msg("Token account is not the one that blocked the payment")]
+    InvalidBlockedTokenAccount,
+    #[msg("Max lifetime must be 0 or longer than one billing interval")]
+    InvalidMaxLifetime,
+    #[msg("Subscription has reached the plan's max lifetime")]
+    LifetimeCapReached,
+    #[msg("Payment permit has expired")]
+    PermitExpired,
+    #[msg("Payment permit nonce was already used")]
+    PermitReplay,
+    #[msg("Payment permit does not match this subscription")]
+    InvalidPermit,
+    #[msg("Transaction has no ed25519 signature of the permit by the subscriber")]
+    InvalidPermitSignature,
+    #[msg("Plan is not accepting new subscriptions")]
+    NewSubscriptionsDisabled,
+    #[msg("Minimum subscription duration must be between 0 and the billing interval")]
+    InvalidMinSubscriptionDuration,
+    #[msg("Subscription can't be cancelled before the plan's minimum duration")]
+    MinimumDurationNotMet,
+    #[msg("Token is not accepted by this plan")]
+    PaymentTokenNotAccepted,
+    #[msg("Plan already accepts the maximum number of extra tokens")]
+    TooManyAcceptedTokens,
+    #[msg("An accepted token must differ from the plan's payment mint")]
+    InvalidAcceptedToken,
+    #[msg("Setup fees can only be paid in the plan's payment mint")]
+    SetupFeeInAcceptedToken,
+    #[msg("Plan doesn't let subscribers choose their price")]
+    PayWhatYouWantDisabled,
+    #[msg("Chosen price is below the plan's price")]
+    ChosenPriceBelowFloor,
+    #[msg("Subscription can't be cancelled in the transaction that started it")]
+    SameTransactionCancel,
+    #[msg("Grace window must be between zero and 90 days")]
+    InvalidGraceWindow,
+    #[msg("Expected the plan's prepaid vault as the only remaining account")]
+    InvalidPrepaidVault,
+    #[msg("Capacity was changed too recently; wait for the cooldown")]
+    CapacityChangeTooSoon,
+    #[msg("Capacity change cooldown cannot be negative")]
+    InvalidCapacityChangeCooldown,
+    #[msg("Maintenance window is longer than allowed")]
+    MaintenanceWindowTooLong,
+    #[msg("Only the plan creator or its manager can perform this action")]
+    NotCreatorOrManager,
+    #[msg("Migrating this account needs extra accounts in remaining_accounts")]
+    MigrationAccountsMissing,
+    #[msg("Prepaid balances are held in the plan's payment mint, not this subscription's billing mint")]
+    PrepaidMintMismatch,
+    #[msg("Plan bills in days or months; change its interval with set_interval_unit")]
+    IntervalSetByUnit,
 }
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct PausePlan<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump,
-        has_one = creator @ ErrorCode::InvalidCreator,
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    pub creator: Signer<'info>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn on_time_payment_keeps_anchor() {
+        let scheduled = 1_000_000;
+        let next = next_payment_after(scheduled, scheduled, 30 * DAY).unwrap();
+        assert_eq!(next, scheduled + 30 * DAY);
+    }
+
+    #[test]
+    fn late_payment_within_grace_keeps_anchor() {
+        let scheduled = 1_000_000;
+        let next = next_payment_after(scheduled, scheduled + 6 * DAY, 30 * DAY).unwrap();
+        assert_eq!(next, scheduled + 30 * DAY);
+    }
+
+    #[test]
+    fn anchor_is_stable_across_mixed_payment_times() {
+        let start = 1_000_000;
+        let interval = 30 * DAY;
+        let offsets = [0, 5 * DAY, -60, 2 * DAY, 0, -3600];
+        let mut scheduled = start;
+        for (cycle, offset) in offsets.iter().enumerate() {
+            scheduled = next_payment_after(scheduled, scheduled + offset, interval).unwrap();
+            assert_eq!(scheduled, start + (cycle as i64 + 1) * interval);
+        }
+    }
+
+    #[test]
+    fn short_interval_late_payment_skips_whole_cycles() {
+        let scheduled = 1_000_000;
+        let interval = 60;
+        let now = scheduled + 10 * 60 + 30;
+        let next = next_payment_after(scheduled, now, interval).unwrap();
+        // Stays on the original minute cadence, no more than one cycle behind now
+        assert_eq!((next - scheduled) % interval, 0);
+        assert!(next >= now - interval);
+        assert!(next < now);
+    }
+
+    #[test]
+    fn due_cycles_counts_missed_cycles() {
+        let next = 1_000_000;
+        assert_eq!(due_cycles(next, next - 1, DAY, 10).unwrap(), 0);
+        assert_eq!(due_cycles(next, next, DAY, 10).unwrap(), 1);
+        assert_eq!(due_cycles(next, next + 3 * DAY + 5, DAY, 10).unwrap(), 4);
+    }
+
+    #[test]
+    fn due_cycles_respects_max_cycles() {
+        let next = 1_000_000;
+        assert_eq!(due_cycles(next, next + 6 * DAY, DAY, 2).unwrap(), 2);
+    }
+
+    fn plan_with_maintenance(start: i64, end: i64) -> SubscriptionPlan {
+        SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            maintenance_start: start,
+            maintenance_end: end,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn interval_bounds_cover_dependent_settings() {
+        let mut plan = plan_with_maintenance(0, 0);
+        plan.check_interval_bounds().unwrap();
+
+        plan.min_subscription_seconds = 20 * DAY;
+        plan.reminder_lead_seconds = 3 * DAY;
+        plan.jitter_seconds = DAY;
+        plan.check_interval_bounds().unwrap();
+
+        plan.interval_seconds = 7 * DAY;
+        assert_eq!(
+            plan.check_interval_bounds().unwrap_err(),
+            error!(ErrorCode::InvalidMinSubscriptionDuration)
+        );
+        plan.min_subscription_seconds = 0;
+        plan.interval_seconds = 2 * DAY;
+        assert_eq!(plan.check_interval_bounds().unwrap_err(), error!(ErrorCode::InvalidReminderLead));
+        plan.reminder_lead_seconds = 0;
+        plan.interval_seconds = DAY;
+        assert_eq!(plan.check_interval_bounds().unwrap_err(), error!(ErrorCode::InvalidJitter));
+    }
+
+    #[test]
+    fn maintenance_window_bounds() {
+        let plan = plan_with_maintenance(1_000, 2_000);
+        assert!(!plan.in_maintenance(999));
+        assert!(plan.in_maintenance(1_000));
+        assert!(plan.in_maintenance(1_999));
+        assert!(!plan.in_maintenance(2_000));
+    }
+
+    #[test]
+    fn maintenance_shift_applies_once_to_affected_subscriptions() {
+        let mut plan = plan_with_maintenance(10 * DAY, 12 * DAY);
+        let shift = |plan: &SubscriptionPlan, next_payment: i64, credited_until: i64| {
+            let subscription = Subscription {
+                next_payment,
+                maintenance_credited_until: credited_until,
+                ..Default::default()
+            };
+            plan.maintenance_shift(&subscription, 12 * DAY).unwrap()
+        };
+        // Due during the window
+        assert_eq!(shift(&plan, 11 * DAY, 0), 2 * DAY);
+        // Grace still running when the window started
+        assert_eq!(shift(&plan, 5 * DAY, 0), 2 * DAY);
+        // Already credited
+        assert_eq!(shift(&plan, 11 * DAY, 12 * DAY), 0);
+        // Due after the window, or grace over before it
+        assert_eq!(shift(&plan, 12 * DAY, 0), 0);
+        assert_eq!(shift(&plan, 2 * DAY, 0), 0);
+
+        // The subscription's own grace decides the overlap
+        plan.grace_window = GraceWindow::Seconds { seconds: DAY };
+        assert_eq!(shift(&plan, 5 * DAY, 0), 0);
+        assert_eq!(shift(&plan, 9 * DAY, 0), 2 * DAY);
+
+        // Nothing is owed for a window that hasn't started
+        let subscription = Subscription { next_payment: 11 * DAY, ..Default::default() };
+        assert_eq!(plan.maintenance_shift(&subscription, 10 * DAY - 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn multi_cycle_settlement_applies_the_maintenance_shift() {
+        let mut plan = plan_with_maintenance(10 * DAY, 12 * DAY);
+        let mut subscription = live_subscription(11 * DAY);
+        subscription.interval_seconds = 30 * DAY;
+        plan.count_status_change(None, SubscriptionStatus::Active).unwrap();
+
+        settle_cycles(&mut subscription, &mut plan, 2, 200, 12 * DAY).unwrap();
+        assert_eq!(subscription.next_payment, 11 * DAY + 60 * DAY + 2 * DAY);
+        assert_eq!(subscription.maintenance_credited_until, 12 * DAY);
+        assert_eq!(
+            payment_deadline(&subscription, &plan, 12 * DAY).unwrap(),
+            subscription.next_payment + GRACE_PERIOD_SECONDS
+        );
+
+        // Credited once: the next settlement keeps the shifted cadence
+        let due = subscription.next_payment;
+        settle_cycles(&mut subscription, &mut plan, 1, 100, due).unwrap();
+        assert_eq!(subscription.next_payment, due + 30 * DAY);
+    }
+
+    #[test]
+    fn early_payment_advances_from_scheduled_date() {
+        let scheduled = 1_000_000;
+        let next = next_payment_after(scheduled, scheduled - 30, 30 * DAY).unwrap();
+        assert_eq!(next, scheduled + 30 * DAY);
+    }
+
+    #[test]
+    fn repeated_early_payments_do_not_creep_forward() {
+        let start = 1_000_000;
+        let interval = 30 * DAY;
+        let mut scheduled = start;
+        for cycle in 1..=24 {
+            // Cranker fires 45 seconds ahead of every due date
+            scheduled = next_payment_after(scheduled, scheduled - 45, interval).unwrap();
+            assert_eq!(scheduled, start + cycle * interval);
+        }
+    }
+
+    // 2024-01-06 00:00:00 UTC, a Saturday
+    const SATURDAY: i64 = 1_704_499_200;
+
+    #[test]
+    fn weekday_from_unix_time() {
+        assert_eq!(weekday(0), 3); // 1970-01-01 was a Thursday
+        assert_eq!(weekday(SATURDAY), 5);
+        assert_eq!(weekday(SATURDAY + DAY), 6);
+        assert_eq!(weekday(SATURDAY + 2 * DAY), 0);
+        assert_eq!(weekday(-1), 2); // 1969-12-31 23:59:59, a Wednesday
+    }
+
+    #[test]
+    fn weekend_due_dates_move_to_monday() {
+        let monday = SATURDAY + 2 * DAY;
+        assert_eq!(next_business_day(SATURDAY).unwrap(), monday);
+        assert_eq!(next_business_day(SATURDAY + 3600).unwrap(), monday + 3600);
+        assert_eq!(next_business_day(SATURDAY + DAY + 3600).unwrap(), monday + 3600);
+    }
+
+    #[test]
+    fn week_boundaries_are_exact() {
+        let friday_last_second = SATURDAY - 1;
+        let sunday_last_second = SATURDAY + 2 * DAY - 1;
+        let monday = SATURDAY + 2 * DAY;
+        assert_eq!(next_business_day(friday_last_second).unwrap(), friday_last_second);
+        assert_eq!(next_business_day(sunday_last_second).unwrap(), sunday_last_second + DAY);
+        assert_eq!(next_business_day(monday).unwrap(), monday);
+    }
+
+    #[test]
+    fn billing_date_only_adjusts_when_enabled() {
+        let mut plan = SubscriptionPlan::default();
+        assert_eq!(plan.billing_date(SATURDAY).unwrap(), SATURDAY);
+        plan.bill_on_weekday_only = true;
+        assert_eq!(plan.billing_date(SATURDAY).unwrap(), SATURDAY + 2 * DAY);
+    }
+
+    #[test]
+    fn fixed_term_subscriptions_end_at_ends_at() {
+        let mut subscription = Subscription {
+            ends_at: 1_000,
+            ..Default::default()
+        };
+        assert!(!subscription.has_ended(999));
+        assert!(subscription.has_ended(1_000));
+
+        subscription.ends_at = 0;
+        assert!(!subscription.has_ended(i64::MAX));
+    }
+
+    #[test]
+    fn payment_deadline_includes_grace_and_maintenance() {
+        let mut plan = plan_with_maintenance(0, 0);
+        let subscription = Subscription {
+            next_payment: 10 * DAY,
+            ..Default::default()
+        };
+        assert_eq!(
+            payment_deadline(&subscription, &plan, 10 * DAY).unwrap(),
+            10 * DAY + GRACE_PERIOD_SECONDS
+        );
+
+        plan.maintenance_start = 11 * DAY;
+        plan.maintenance_end = 12 * DAY;
+        // Not extended before the window starts
+        assert_eq!(
+            payment_deadline(&subscription, &plan, 11 * DAY - 1).unwrap(),
+            10 * DAY + GRACE_PERIOD_SECONDS
+        );
+        assert_eq!(
+            payment_deadline(&subscription, &plan, 11 * DAY).unwrap(),
+            10 * DAY + GRACE_PERIOD_SECONDS + DAY
+        );
+    }
+
+    #[test]
+    fn total_paid_amount_accumulates_up_to_u64_max() {
+        let mut subscription = Subscription {
+            total_paid_amount: u64::MAX - 10,
+            ..Default::default()
+        };
+        subscription.record_payment(10).unwrap();
+        assert_eq!(subscription.total_paid_amount, u64::MAX);
+    }
+
+    #[test]
+    fn total_paid_amount_overflow_is_an_error() {
+        let mut subscription = Subscription {
+            total_paid_amount: u64::MAX,
+            ..Default::default()
+        };
+        assert!(subscription.record_payment(1).is_err());
+        assert_eq!(subscription.total_paid_amount, u64::MAX);
+    }
+
+    #[test]
+    fn activation_locks_in_plan_interval() {
+        let mut plan = SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            prepay_cycles_on_join: 1,
+            ..Default::default()
+        };
+        let mut subscription = Subscription::default();
+        activate_subscription(&mut subscription, &mut plan, 1, 0, 1_000_000).unwrap();
+        assert_eq!(subscription.interval_seconds, 30 * DAY);
+
+        // Later plan edits don't reach the stored interval
+        plan.interval_seconds = 7 * DAY;
+        let next = next_payment_after(
+            subscription.next_payment,
+            subscription.next_payment,
+            subscription.interval_seconds,
+        ).unwrap();
+        assert_eq!(next, 1_000_000 + 60 * DAY);
+    }
+
+    #[test]
+    fn prepaid_deposit_allowed_exactly_at_cap() {
+        let plan = SubscriptionPlan {
+            price: 1_000,
+            max_prepaid_cycles: 3,
+            ..Default::default()
+        };
+        assert_eq!(prepaid_balance_after_deposit(&plan, 1_000, 2_000).unwrap(), 3_000);
+        assert!(prepaid_balance_after_deposit(&plan, 1_000, 2_001).is_err());
+        assert!(prepaid_balance_after_deposit(&plan, 3_000, 1).is_err());
+    }
+
+    fn live_subscription(next_payment: i64) -> Subscription {
+        let mut subscription = Subscription {
+            next_payment,
+            ..Default::default()
+        };
+        subscription.set_status(SubscriptionStatus::Active);
+        subscription
+    }
+
+    #[test]
+    fn status_moves_through_active_grace_expired() {
+        let plan = SubscriptionPlan::default();
+        let due = 1_000_000;
+        let subscription = live_subscription(due);
+        assert_eq!(derive_status(&subscription, &plan, due).unwrap(), SubscriptionStatus::Active);
+        assert_eq!(derive_status(&subscription, &plan, due + 1).unwrap(), SubscriptionStatus::Grace);
+        assert_eq!(
+            derive_status(&subscription, &plan, due + GRACE_PERIOD_SECONDS).unwrap(),
+            SubscriptionStatus::Grace
+        );
+        assert_eq!(
+            derive_status(&subscription, &plan, due + GRACE_PERIOD_SECONDS + 1).unwrap(),
+            SubscriptionStatus::Expired
+        );
+    }
+
+    #[test]
+    fn status_follows_plan_pause_and_term_end() {
+        let mut plan = SubscriptionPlan { is_paused: true, ..Default::default() };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        assert_eq!(derive_status(&subscription, &plan, due - 1).unwrap(), SubscriptionStatus::Paused);
+
+        plan.is_paused = false;
+        subscription.set_status(SubscriptionStatus::Paused);
+        assert_eq!(derive_status(&subscription, &plan, due - 1).unwrap(), SubscriptionStatus::Active);
+
+        subscription.ends_at = due - 10;
+        assert_eq!(derive_status(&subscription, &plan, due - 1).unwrap(), SubscriptionStatus::Expired);
+    }
+
+    #[test]
+    fn terminal_and_pending_statuses_are_not_recomputed() {
+        let plan = SubscriptionPlan::default();
+        for status in [
+            SubscriptionStatus::Cancelled,
+            SubscriptionStatus::Expired,
+            SubscriptionStatus::Pending,
+        ] {
+            let mut subscription = live_subscription(1_000_000);
+            subscription.set_status(status);
+            assert!(!subscription.is_active);
+            assert_eq!(derive_status(&subscription, &plan, 0).unwrap(), status);
+        }
+    }
+
+    #[test]
+    fn set_status_keeps_is_active_in_sync() {
+        let mut subscription = Subscription::default();
+        for (status, live) in [
+            (SubscriptionStatus::Active, true),
+            (SubscriptionStatus::Grace, true),
+            (SubscriptionStatus::Paused, true),
+            (SubscriptionStatus::Expired, false),
+            (SubscriptionStatus::Cancelled, false),
+            (SubscriptionStatus::Pending, false),
+        ] {
+            subscription.set_status(status);
+            assert_eq!(subscription.is_active, live);
+        }
+    }
+
+    // 2024-01-31T12:00:00Z
+    const JAN_31_2024_NOON: i64 = 1_706_702_400;
+
+    #[test]
+    fn civil_date_round_trips() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(civil_from_days(days_from_civil(1969, 12, 31)), (1969, 12, 31));
+        assert_eq!(JAN_31_2024_NOON.div_euclid(DAY), days_from_civil(2024, 1, 31));
+    }
+
+    #[test]
+    fn add_months_clamps_to_month_end() {
+        let feb = add_months(JAN_31_2024_NOON, 1).unwrap();
+        assert_eq!(civil_from_days(feb.div_euclid(DAY)), (2024, 2, 29)); // leap year
+        assert_eq!(feb.rem_euclid(DAY), 12 * 60 * 60);
+
+        let feb_2025 = add_months(JAN_31_2024_NOON, 13).unwrap();
+        assert_eq!(civil_from_days(feb_2025.div_euclid(DAY)), (2025, 2, 28));
+
+        let mar_31 = days_from_civil(2024, 3, 31) * DAY;
+        let apr = add_months(mar_31, 1).unwrap();
+        assert_eq!(civil_from_days(apr.div_euclid(DAY)), (2024, 4, 30));
+
+        let dec_15 = days_from_civil(2024, 12, 15) * DAY;
+        let jan = add_months(dec_15, 1).unwrap();
+        assert_eq!(civil_from_days(jan.div_euclid(DAY)), (2025, 1, 15));
+    }
+
+    #[test]
+    fn monthly_subscription_schedules_by_calendar() {
+        let subscription = Subscription {
+            interval_unit: IntervalUnit::Months,
+            interval_count: 1,
+            interval_seconds: 30 * DAY,
+            next_payment: days_from_civil(2024, 2, 15) * DAY,
+            ..Default::default()
+        };
+        let due = subscription.next_payment;
+        let next = subscription.next_due_after(due, due).unwrap();
+        assert_eq!(civil_from_days(next.div_euclid(DAY)), (2024, 3, 15));
+
+        // Two calendar months overdue (Feb 15 and Mar 15) at Mar 20
+        let mar_20 = days_from_civil(2024, 3, 20) * DAY;
+        assert_eq!(subscription.cycles_due(mar_20, 12).unwrap(), 2);
+        assert_eq!(subscription.cycles_due(mar_20, 1).unwrap(), 1);
+        assert_eq!(subscription.cycles_due(due - 1, 12).unwrap(), 0);
+    }
+
+    #[test]
+    fn prorated_refund_scales_with_unused_time() {
+        let next = 1_000_000;
+        assert_eq!(prorated_refund(3_000, next, next - 10 * DAY, 30 * DAY).unwrap(), 1_000);
+        assert_eq!(prorated_refund(3_000, next, next - 30 * DAY, 30 * DAY).unwrap(), 3_000);
+        // Rounds down in the creator's favour
+        assert_eq!(prorated_refund(1_000, next, next - DAY, 30 * DAY).unwrap(), 33);
+    }
+
+    #[test]
+    fn prorated_refund_is_zero_once_due() {
+        let next = 1_000_000;
+        assert_eq!(prorated_refund(3_000, next, next, 30 * DAY).unwrap(), 0);
+        assert_eq!(prorated_refund(3_000, next, next + DAY, 30 * DAY).unwrap(), 0);
+    }
+
+    /// Deterministic xorshift64 stream for randomized property tests
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn prorated_refund_never_exceeds_the_price() {
+        let mut rng = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..10_000 {
+            let price = xorshift(&mut rng);
+            let interval = (xorshift(&mut rng) % (400 * DAY as u64)) as i64 + 1;
+            let next = 1_000_000_000;
+            let elapsed = (xorshift(&mut rng) % (interval as u64 + 1)) as i64;
+            let now = next - interval + elapsed;
+
+            let refund = prorated_refund(price, next, now, interval).unwrap();
+            // The unused share of the cycle, rounded down
+            let remaining = (interval - elapsed) as u128;
+            assert_eq!(refund as u128, price as u128 * remaining / interval as u128);
+            assert!(refund <= price);
+            // Waiting longer never raises the refund
+            let later = prorated_refund(price, next, now + 1, interval).unwrap();
+            assert!(later <= refund);
+        }
+    }
+
+    #[test]
+    fn prorated_refund_survives_large_products() {
+        let refund = prorated_refund(u64::MAX, i64::MAX, 0, i64::MAX).unwrap();
+        assert_eq!(refund, u64::MAX);
+        assert!(prorated_refund(u64::MAX, i64::MAX, 0, 1).is_err());
+    }
+
+    #[test]
+    fn min_price_boundary() {
+        assert!(check_min_price(1, 0).is_ok());
+        assert!(check_min_price(1_000, 1_000).is_ok());
+        assert!(check_min_price(999, 1_000).is_err());
+    }
+
+    #[test]
+    fn pending_reservations_hold_a_seat() {
+        for (status, holds) in [
+            (SubscriptionStatus::Active, true),
+            (SubscriptionStatus::Grace, true),
+            (SubscriptionStatus::Paused, true),
+            (SubscriptionStatus::Pending, true),
+            (SubscriptionStatus::Expired, false),
+            (SubscriptionStatus::Cancelled, false),
+        ] {
+            assert_eq!(status.holds_seat(), holds);
+        }
+    }
+
+    #[test]
+    fn bundle_shares_sum_to_price() {
+        assert_eq!(bundle_shares(10, 3).unwrap(), vec![4, 3, 3]);
+        assert_eq!(bundle_shares(9, 3).unwrap(), vec![3, 3, 3]);
+        assert!(bundle_shares(10, 0).is_err());
+    }
+
+    #[test]
+    fn frozen_subscription_neither_bills_nor_expires() {
+        let plan = SubscriptionPlan {
+            is_active: true,
+            ..Default::default()
+        };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        subscription.is_frozen = true;
+
+        assert!(check_payment_due(&subscription, &plan, due).is_err());
+        assert_eq!(
+            derive_status(&subscription, &plan, due + GRACE_PERIOD_SECONDS + 1).unwrap(),
+            SubscriptionStatus::Active
+        );
+
+        subscription.is_frozen = false;
+        assert!(check_payment_due(&subscription, &plan, due).is_ok());
+    }
+
+    #[test]
+    fn injected_clock_drives_due_grace_and_expiry() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            interval_seconds: 30 * DAY,
+            ..Default::default()
+        };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        subscription.interval_seconds = 30 * DAY;
+
+        set_test_time(due - 1);
+        let now = current_time().unwrap();
+        assert!(check_payment_due(&subscription, &plan, now).is_err());
+        assert_eq!(derive_status(&subscription, &plan, now).unwrap(), SubscriptionStatus::Active);
+
+        set_test_time(due + DAY);
+        let now = current_time().unwrap();
+        assert_eq!(derive_status(&subscription, &plan, now).unwrap(), SubscriptionStatus::Grace);
+        check_payment_due(&subscription, &plan, now).unwrap();
+        settle_cycle(&mut subscription, &mut plan, 100, now).unwrap();
+        assert_eq!(subscription.next_payment, due + 30 * DAY);
+
+        set_test_time(subscription.next_payment + GRACE_PERIOD_SECONDS + 1);
+        let now = current_time().unwrap();
+        assert!(check_payment_due(&subscription, &plan, now).is_err());
+        assert_eq!(derive_status(&subscription, &plan, now).unwrap(), SubscriptionStatus::Expired);
+    }
+
+    #[test]
+    fn take_seat_counts_up_to_capacity() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            max_subscribers: 2,
+            ..Default::default()
+        };
+        take_seat(&mut plan, 0).unwrap();
+        take_seat(&mut plan, 0).unwrap();
+        assert_eq!(plan.current_subscribers, 2);
+        assert!(check_can_join(&mut plan, 0).is_err());
+    }
+
+    #[test]
+    fn auto_expansion_stops_at_hard_cap() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            max_subscribers: 10,
+            current_subscribers: 10,
+            auto_expand_step: 4,
+            hard_cap: 12,
+            ..Default::default()
+        };
+        assert_eq!(expanded_capacity(&plan), Some(12));
+        check_can_join(&mut plan, 0).unwrap();
+        assert_eq!(plan.max_subscribers, 12);
+
+        plan.current_subscribers = 12;
+        assert_eq!(expanded_capacity(&plan), None);
+        assert!(check_can_join(&mut plan, 0).is_err());
+
+        plan.auto_expand_step = 0;
+        plan.hard_cap = u32::MAX;
+        assert_eq!(expanded_capacity(&plan), None);
+    }
+
+    #[test]
+    fn grace_override_only_moves_its_own_deadline() {
+        let plan = SubscriptionPlan::default();
+        let due = 1_000_000;
+        let standard = live_subscription(due);
+        let mut enterprise = live_subscription(due);
+        enterprise.grace_override_seconds = Some(30 * DAY);
+
+        let after_default_grace = due + GRACE_PERIOD_SECONDS + 1;
+        assert_eq!(
+            derive_status(&standard, &plan, after_default_grace).unwrap(),
+            SubscriptionStatus::Expired
+        );
+        assert_eq!(
+            derive_status(&enterprise, &plan, after_default_grace).unwrap(),
+            SubscriptionStatus::Grace
+        );
+        assert_eq!(payment_deadline(&enterprise, &plan, due).unwrap(), due + 30 * DAY);
+
+        enterprise.grace_override_seconds = Some(0);
+        assert_eq!(
+            derive_status(&enterprise, &plan, due + 1).unwrap(),
+            SubscriptionStatus::Expired
+        );
+    }
+
+    #[test]
+    fn comp_subscription_renews_free_and_frees_comp_seat() {
+        let mut plan = SubscriptionPlan {
+            price: 1_000,
+            current_subscribers: 2,
+            comp_subscribers: 1,
+            ..Default::default()
+        };
+        let mut comp = live_subscription(0);
+        comp.is_comp = true;
+        let paid = live_subscription(0);
+
+        assert_eq!(effective_price(&comp, &plan, 0).unwrap(), 0);
+        assert_eq!(effective_price(&paid, &plan, 0).unwrap(), 1_000);
+
+        free_seat(&mut plan, &paid).unwrap();
+        assert_eq!((plan.current_subscribers, plan.comp_subscribers), (1, 1));
+        free_seat(&mut plan, &comp).unwrap();
+        assert_eq!((plan.current_subscribers, plan.comp_subscribers), (0, 0));
+    }
+
+    #[test]
+    fn overdue_live_subscription_breaks_invariant() {
+        let plan = SubscriptionPlan::default();
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        let past_grace = due + GRACE_PERIOD_SECONDS + 1;
+
+        assert!(check_not_overdue(&subscription, &plan, due + 1).is_ok());
+        assert!(check_not_overdue(&subscription, &plan, past_grace).is_err());
+
+        subscription.set_status(SubscriptionStatus::Expired);
+        assert!(check_not_overdue(&subscription, &plan, past_grace).is_ok());
+    }
+
+    #[test]
+    fn subscriber_jitter_stays_within_bounds() {
+        let jitter = 6 * 60 * 60;
+        let offsets: Vec<i64> = (0..500)
+            .map(|_| subscriber_jitter(&Pubkey::new_unique(), jitter))
+            .collect();
+        assert!(offsets.iter().all(|offset| (0..jitter).contains(offset)));
+        // Spread across the window rather than clustered in one part of it
+        assert!(offsets.iter().any(|offset| *offset < jitter / 4));
+        assert!(offsets.iter().any(|offset| *offset >= jitter * 3 / 4));
+
+        let subscriber = Pubkey::new_unique();
+        assert_eq!(subscriber_jitter(&subscriber, jitter), subscriber_jitter(&subscriber, jitter));
+        assert_eq!(subscriber_jitter(&subscriber, 0), 0);
+    }
+
+    #[test]
+    fn due_check_separates_due_not_due_and_expired() {
+        let plan = SubscriptionPlan {
+            is_active: true,
+            ..Default::default()
+        };
+        let now = 1_000_000;
+        let due = live_subscription(now - DAY);
+        let not_due = live_subscription(now + DAY);
+        let mut expired = live_subscription(now - GRACE_PERIOD_SECONDS - DAY);
+        expired.set_status(SubscriptionStatus::Expired);
+
+        let count = [&due, &not_due, &expired]
+            .iter()
+            .filter(|subscription| is_payment_due(subscription, &plan, now))
+            .count();
+        assert_eq!(count, 1);
+        assert!(is_payment_due(&due, &plan, now));
+    }
+
+    #[test]
+    fn setup_fee_is_owed_once() {
+        let plan = SubscriptionPlan {
+            setup_fee: 500,
+            ..Default::default()
+        };
+        let mut subscription = Subscription::default();
+        assert_eq!(setup_fee_due(&subscription, &plan), 500);
+        subscription.setup_fee_paid = true;
+        assert_eq!(setup_fee_due(&subscription, &plan), 0);
+    }
+
+    #[test]
+    fn auto_renew_authorization_exhausts_and_expires() {
+        let mut subscription = live_subscription(0);
+        assert!(check_auto_renew(&subscription, 0).is_err());
+
+        subscription.auto_renew_cycles_remaining = 1;
+        subscription.auto_renew_expires_at = 1_000;
+        assert!(check_auto_renew(&subscription, 1_000).is_ok());
+        assert!(check_auto_renew(&subscription, 1_001).is_err());
+
+        subscription.auto_renew_cycles_remaining = 0;
+        assert!(check_auto_renew(&subscription, 500).is_err());
+    }
+
+    #[test]
+    fn max_token_amount_caps_charge() {
+        assert!(check_max_token_amount(1_000_000, None).is_ok());
+        assert!(check_max_token_amount(1_000_000, Some(1_000_000)).is_ok());
+        assert!(check_max_token_amount(1_000_001, Some(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn status_counts_follow_transitions() {
+        let counts = |plan: &SubscriptionPlan| (plan.active_count, plan.paused_count, plan.expired_count);
+        let mut plan = SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            ..Default::default()
+        };
+        let mut subscription = Subscription::default();
+
+        activate_subscription(&mut subscription, &mut plan, 1, 0, 1_000_000).unwrap();
+        assert_eq!(counts(&plan), (1, 0, 0));
+        transition_status(&mut subscription, &mut plan, SubscriptionStatus::Paused).unwrap();
+        assert_eq!(counts(&plan), (0, 1, 0));
+        transition_status(&mut subscription, &mut plan, SubscriptionStatus::Active).unwrap();
+        assert_eq!(counts(&plan), (1, 0, 0));
+        // Grace shares the active counter
+        transition_status(&mut subscription, &mut plan, SubscriptionStatus::Grace).unwrap();
+        assert_eq!(counts(&plan), (1, 0, 0));
+        transition_status(&mut subscription, &mut plan, SubscriptionStatus::Cancelled).unwrap();
+        assert_eq!(counts(&plan), (0, 0, 0));
+
+        let mut lapsed = Subscription::default();
+        activate_subscription(&mut lapsed, &mut plan, 1, 0, 1_000_000).unwrap();
+        transition_status(&mut lapsed, &mut plan, SubscriptionStatus::Expired).unwrap();
+        assert_eq!(counts(&plan), (0, 0, 1));
+
+        // A counter already at zero can't be decremented
+        let mut stray = Subscription::default();
+        stray.set_status(SubscriptionStatus::Paused);
+        assert!(transition_status(&mut stray, &mut plan, SubscriptionStatus::Active).is_err());
+    }
+
+    #[test]
+    fn migrated_plan_counts_existing_subscribers_as_active() {
+        let mut plan = SubscriptionPlan {
+            current_subscribers: 2,
+            ..Default::default()
+        };
+        plan.upgrade(&UpgradeInputs::default());
+        assert_eq!(plan.active_count, 2);
+
+        // Cancelling one of the subscribers that predate the counters
+        let mut subscription = live_subscription(1_000_000);
+        transition_status(&mut subscription, &mut plan, SubscriptionStatus::Cancelled).unwrap();
+        free_seat(&mut plan, &subscription).unwrap();
+        assert_eq!((plan.active_count, plan.current_subscribers), (1, 1));
+    }
+
+    #[test]
+    fn anchor_day_snaps_across_month_lengths() {
+        let at = |year, month, day| days_from_civil(year, month, day) * SECONDS_PER_DAY + 3_600;
+
+        // Already on the anchor
+        assert_eq!(next_anchor_day(at(2024, 3, 1), 1).unwrap(), at(2024, 3, 1));
+        // 31-, 30- and 28/29-day months all roll into the next month's 1st
+        assert_eq!(next_anchor_day(at(2024, 1, 31), 1).unwrap(), at(2024, 2, 1));
+        assert_eq!(next_anchor_day(at(2024, 4, 30), 1).unwrap(), at(2024, 5, 1));
+        assert_eq!(next_anchor_day(at(2023, 2, 28), 1).unwrap(), at(2023, 3, 1));
+        assert_eq!(next_anchor_day(at(2024, 2, 29), 1).unwrap(), at(2024, 3, 1));
+        // Later in the same month, and across the year end
+        assert_eq!(next_anchor_day(at(2024, 2, 3), 28).unwrap(), at(2024, 2, 28));
+        assert_eq!(next_anchor_day(at(2024, 12, 15), 10).unwrap(), at(2025, 1, 10));
+    }
+
+    #[test]
+    fn anchored_monthly_cycles_stay_on_anchor() {
+        let at = |year, month, day| days_from_civil(year, month, day) * SECONDS_PER_DAY;
+        let mut plan = SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            interval_unit: IntervalUnit::Months,
+            interval_count: 1,
+            billing_anchor_day: 15,
+            ..Default::default()
+        };
+        let mut subscription = Subscription::default();
+
+        // Joining on Jan 20 bills a month later, then snaps to Mar 15
+        activate_subscription(&mut subscription, &mut plan, 1, 0, at(2023, 1, 20)).unwrap();
+        assert_eq!(subscription.next_payment, at(2023, 3, 15));
+        for expected in [at(2023, 4, 15), at(2023, 5, 15), at(2023, 6, 15)] {
+            let due = subscription.next_payment;
+            settle_cycle(&mut subscription, &mut plan, 0, due).unwrap();
+            assert_eq!(subscription.next_payment, expected);
+        }
+
+        plan.billing_anchor_day = 0;
+        assert_eq!(plan.billing_date(at(2023, 1, 20)).unwrap(), at(2023, 1, 20));
+    }
+
+    #[test]
+    fn expire_policy_lapses_past_grace() {
+        let plan = SubscriptionPlan {
+            is_active: true,
+            interval_seconds: 30 * DAY,
+            ..Default::default()
+        };
+        let due = 100 * DAY;
+        let subscription = live_subscription(due);
+        let past_grace = due + GRACE_PERIOD_SECONDS + 1;
+
+        assert_eq!(payable_cycles(&subscription, &plan, due).unwrap(), 1);
+        assert_eq!(
+            payable_cycles(&subscription, &plan, past_grace).unwrap_err(),
+            error!(ErrorCode::PaymentTooLate)
+        );
+        assert_eq!(derive_status(&subscription, &plan, past_grace).unwrap(), SubscriptionStatus::Expired);
+    }
+
+    #[test]
+    fn accumulate_policy_collects_missed_cycles_up_to_max_debt() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            interval_seconds: 30 * DAY,
+            past_grace_policy: PastGracePolicy::Accumulate,
+            max_debt_cycles: 3,
+            active_count: 1,
+            ..Default::default()
+        };
+        let due = 100 * DAY;
+        let mut subscription = live_subscription(due);
+        subscription.interval_seconds = 30 * DAY;
+
+        // Within grace it still charges a single cycle
+        assert_eq!(payable_cycles(&subscription, &plan, due + DAY).unwrap(), 1);
+
+        // Two missed cycles past grace stay billable and in Grace
+        let now = due + 30 * DAY + GRACE_PERIOD_SECONDS + 1;
+        assert_eq!(derive_status(&subscription, &plan, now).unwrap(), SubscriptionStatus::Grace);
+        assert_eq!(payable_cycles(&subscription, &plan, now).unwrap(), 2);
+
+        // A fourth owed cycle is more debt than the plan allows
+        let too_late = due + 90 * DAY;
+        assert_eq!(
+            payable_cycles(&subscription, &plan, too_late).unwrap_err(),
+            error!(ErrorCode::MaxDebtExceeded)
+        );
+        assert_eq!(derive_status(&subscription, &plan, too_late).unwrap(), SubscriptionStatus::Expired);
+
+        // Paying the debt restores the original cadence
+        settle_cycles(&mut subscription, &mut plan, 2, 200, now).unwrap();
+        assert_eq!(subscription.next_payment, due + 60 * DAY);
+        assert_eq!(subscription.total_payments, 2);
+        assert_eq!(subscription.status, SubscriptionStatus::Active);
+    }
+
+    #[test]
+    fn join_rejection_precedence() {
+        let plan = |is_active, is_paused, full| SubscriptionPlan {
+            is_active,
+            is_paused,
+            max_subscribers: 1,
+            current_subscribers: full as u32,
+            ..Default::default()
+        };
+        use SubscribeRejectReason::*;
+        assert_eq!(join_rejection(&plan(false, true, true), 0), Some(PlanInactive));
+        assert_eq!(join_rejection(&plan(false, false, false), 0), Some(PlanInactive));
+        assert_eq!(join_rejection(&plan(true, true, true), 0), Some(PlanPaused));
+        assert_eq!(join_rejection(&plan(true, false, true), 0), Some(PlanFull));
+        assert_eq!(join_rejection(&plan(true, false, false), 0), None);
+
+        // Closing signups outranks capacity but not pause
+        let mut closed = plan(true, false, true);
+        closed.new_subscriptions_disabled = true;
+        assert_eq!(join_rejection(&closed, 0), Some(NewSubscriptionsDisabled));
+        closed.is_paused = true;
+        assert_eq!(join_rejection(&closed, 0), Some(PlanPaused));
+
+        // A full plan that can still grow isn't rejected
+        let mut expandable = plan(true, false, true);
+        expandable.auto_expand_step = 1;
+        expandable.hard_cap = 2;
+        assert_eq!(join_rejection(&expandable, 0), None);
+    }
+
+    #[test]
+    fn funding_round_gates_joins_until_threshold() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            max_subscribers: 10,
+            activation_threshold: 3,
+            funding_deadline: 1_000_000,
+            ..Default::default()
+        };
+        assert!(plan.is_funding());
+        assert_eq!(join_rejection(&plan, 0), Some(SubscribeRejectReason::FundingInProgress));
+        // Pausing still takes precedence over the funding round
+        plan.is_paused = true;
+        assert_eq!(join_rejection(&plan, 0), Some(SubscribeRejectReason::PlanPaused));
+        plan.is_paused = false;
+
+        plan.threshold_reached = true;
+        assert!(!plan.is_funding());
+        assert_eq!(join_rejection(&plan, 0), None);
+
+        plan.activation_threshold = 0;
+        plan.threshold_reached = false;
+        assert!(!plan.is_funding());
+    }
+
+    /// `account` serialized without its trailing zero bytes, like an account
+    /// written before the zero-valued fields at the end of its layout existed
+    fn legacy_bytes<T: AccountSerialize>(account: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        while data.last() == Some(&0) {
+            data.pop();
+        }
+        data
+    }
+
+    #[test]
+    fn legacy_subscription_keeps_fields_when_grown() {
+        let mut subscription = live_subscription(1_000_000);
+        subscription.subscriber = Pubkey::new_unique();
+        subscription.plan_id = 7;
+        subscription.total_payments = 12;
+        subscription.reference = [9; 32];
+        subscription.bundle_id = Some(3);
+        subscription.is_comp = true;
+
+        let data = legacy_bytes(&subscription);
+        assert!(data.len() < Subscription::LEN);
+
+        let migrated = decode_legacy::<Subscription>(&data).unwrap();
+        assert_eq!(migrated.subscriber, subscription.subscriber);
+        assert_eq!(migrated.plan_id, 7);
+        assert_eq!(migrated.total_payments, 12);
+        assert_eq!(migrated.reference, [9; 32]);
+        assert_eq!(migrated.bundle_id, Some(3));
+        assert!(migrated.is_comp);
+        assert_eq!(migrated.status, SubscriptionStatus::Active);
+        assert_eq!(migrated.auto_renew_expires_at, 0);
+        assert_eq!(migrated.pledged_amount, 0);
+    }
+
+    /// A subscription as the original program wrote it
+    fn baseline_subscription(creator: Pubkey, plan_id: u64, is_active: bool) -> Vec<u8> {
+        let mut data = Subscription::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(Pubkey::new_unique().as_ref()); // subscriber
+        data.extend_from_slice(&plan_id.to_le_bytes());
+        data.extend_from_slice(creator.as_ref());
+        data.push(is_active as u8);
+        data.extend_from_slice(&1_000_000i64.to_le_bytes()); // last_payment
+        data.extend_from_slice(&(1_000_000i64 + 30 * DAY).to_le_bytes()); // next_payment
+        data.extend_from_slice(&4u64.to_le_bytes()); // total_payments
+        data.push(254); // bump
+        data
+    }
+
+    /// A plan as the original program wrote it, zero-padded to its old size
+    fn baseline_plan(creator: Pubkey, plan_id: u64, bump: u8) -> Vec<u8> {
+        let mut data = SubscriptionPlan::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(creator.as_ref());
+        data.extend_from_slice(&plan_id.to_le_bytes());
+        data.extend_from_slice(&5_000u64.to_le_bytes()); // price
+        data.extend_from_slice(&(30 * DAY).to_le_bytes()); // interval_seconds
+        data.extend_from_slice(&10u32.to_le_bytes()); // max_subscribers
+        data.extend_from_slice(&2u32.to_le_bytes()); // current_subscribers
+        data.extend_from_slice(&[1, 0]); // is_active, is_paused
+        let uri = b"ipfs://plan";
+        data.extend_from_slice(&(uri.len() as u32).to_le_bytes());
+        data.extend_from_slice(uri);
+        data.extend_from_slice(&1_000i64.to_le_bytes()); // created_at
+        data.push(bump);
+        data.resize(8 + 32 + 8 + 8 + 8 + 4 + 4 + 1 + 1 + 4 + 200 + 8 + 1, 0);
+        data
+    }
+
+    #[test]
+    fn v1_accounts_migrate_to_v2() {
+        let creator = Pubkey::new_unique();
+        let plan_id = 7u64;
+        let (plan_key, bump) = Pubkey::find_program_address(
+            &[b"subscription_plan", creator.as_ref(), &plan_id.to_le_bytes()],
+            &crate::ID,
+        );
+
+        let mut plan = decode_legacy::<SubscriptionPlan>(&baseline_plan(creator, plan_id, bump)).unwrap();
+        assert_eq!(
+            plan.check_schema().unwrap_err(),
+            error!(ErrorCode::SchemaTooOld)
+        );
+        // The original plan had no payment mint, so the creator supplies one
+        assert_eq!(
+            plan.upgrade_inputs(&[]).err().unwrap(),
+            error!(ErrorCode::MigrationAccountsMissing)
+        );
+        let (payment_mint, payout_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        plan.upgrade(&UpgradeInputs {
+            payout: Some((payment_mint, 6, payout_token_account)),
+            ..Default::default()
+        });
+        assert_eq!(plan.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(plan.metadata_uri, "ipfs://plan");
+        assert_eq!((plan.price, plan.interval_seconds, plan.created_at), (5_000, 30 * DAY, 1_000));
+        assert_eq!(plan.payment_mint, payment_mint);
+        assert_eq!(plan.payout_token_account, payout_token_account);
+        assert_eq!(plan.payment_decimals, Some(6));
+        assert_eq!(plan.max_prepaid_cycles, DEFAULT_MAX_PREPAID_CYCLES);
+        assert_eq!(plan.active_count, 2);
+
+        // Subscriptions take their interval from the plan, migrated or not
+        let mut plan_data = baseline_plan(creator, plan_id, bump);
+        let (mut lamports, owner) = (0, crate::ID);
+        let plan_info = AccountInfo::new(&plan_key, false, false, &mut lamports, &mut plan_data, &owner, false, 0);
+        let plan_accounts = [plan_info];
+
+        let mut active = decode_legacy::<Subscription>(&baseline_subscription(creator, plan_id, true)).unwrap();
+        assert_eq!(active.schema_version(), 1);
+        assert!(active.check_schema().is_err());
+        let inputs = active.upgrade_inputs(&plan_accounts).unwrap();
+        active.upgrade(&inputs);
+        assert!(active.check_schema().is_ok());
+        assert_eq!(active.status, SubscriptionStatus::Active);
+        assert_eq!(active.interval_seconds, 30 * DAY);
+        assert_eq!(active.total_payments, 4);
+
+        let mut cancelled = decode_legacy::<Subscription>(&baseline_subscription(creator, plan_id, false)).unwrap();
+        let inputs = cancelled.upgrade_inputs(&plan_accounts).unwrap();
+        cancelled.upgrade(&inputs);
+        assert_eq!(cancelled.status, SubscriptionStatus::Cancelled);
+        assert!(!cancelled.is_active);
+        assert_eq!(cancelled.interval_seconds, 30 * DAY);
+
+        // Another creator's plan with the same id isn't this subscription's
+        let other = decode_legacy::<Subscription>(&baseline_subscription(Pubkey::new_unique(), plan_id, true)).unwrap();
+        assert_eq!(
+            other.upgrade_inputs(&plan_accounts).err().unwrap(),
+            error!(ErrorCode::InvalidPlanAccount)
+        );
+    }
+
+    #[test]
+    fn funding_authority_must_own_or_be_delegate() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token_2022::spl_token_2022::state::{Account, AccountState};
+
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let token_account = |delegated_amount: u64| {
+            let mut data = vec![0; Account::LEN];
+            Account::pack(
+                Account {
+                    owner,
+                    amount: 1_000,
+                    delegate: COption::Some(delegate),
+                    delegated_amount,
+                    state: AccountState::Initialized,
+                    ..Default::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            TokenAccount::try_deserialize(&mut &data[..]).unwrap()
+        };
+
+        assert!(check_funding_authority(&token_account(0), owner, 1_000).is_ok());
+        assert!(check_funding_authority(&token_account(0), delegate, 1).is_err());
+        assert!(check_funding_authority(&token_account(500), delegate, 500).is_ok());
+        assert_eq!(
+            check_funding_authority(&token_account(500), delegate, 501).unwrap_err(),
+            error!(ErrorCode::UnauthorizedFundingAuthority)
+        );
+        assert!(check_funding_authority(&token_account(500), Pubkey::new_unique(), 1).is_err());
+    }
+
+    #[test]
+    fn scheduled_deactivation_takes_effect_at_its_time() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            max_subscribers: 10,
+            deactivation_effective_at: 5_000,
+            ..Default::default()
+        };
+        assert!(plan.is_active_at(4_999));
+        assert_eq!(join_rejection(&plan, 4_999), None);
+        assert!(!plan.is_active_at(5_000));
+        assert_eq!(join_rejection(&plan, 5_000), Some(SubscribeRejectReason::PlanInactive));
+
+        plan.deactivation_effective_at = 0;
+        assert!(plan.is_active_at(5_000));
+    }
+
+    #[test]
+    fn secondary_token_covers_a_short_primary_balance() {
+        assert!(check_payment_funds(1_000, 1_000, None).is_ok());
+        assert!(check_payment_funds(1_000, 1_000, Some((0, 50))).is_ok());
+        assert_eq!(
+            check_payment_funds(999, 1_000, None).unwrap_err(),
+            error!(ErrorCode::InsufficientFunds)
+        );
+        assert!(check_payment_funds(999, 1_000, Some((50, 50))).is_ok());
+        assert_eq!(
+            check_payment_funds(999, 1_000, Some((49, 50))).unwrap_err(),
+            error!(ErrorCode::AllPaymentTokensInsufficient)
+        );
+    }
+
+    #[test]
+    fn plan_health_reflects_plan_counters() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            max_subscribers: 400,
+            current_subscribers: 100,
+            ..Default::default()
+        };
+        let health = PlanHealth::for_plan(&plan, 10 * DAY);
+        assert_eq!(health.utilization_bps, 2_500);
+        assert_eq!(health.current_subscribers, 100);
+        assert!(health.is_active);
+        assert_eq!(health.days_since_last_payment, None);
+        assert_eq!(health.accrued_revenue, 0);
+
+        plan.record_revenue(3_000, 2 * DAY).unwrap();
+        plan.record_revenue(0, 9 * DAY).unwrap();
+        plan.current_subscribers = 400;
+        plan.is_paused = true;
+        let health = PlanHealth::for_plan(&plan, 10 * DAY);
+        assert_eq!(health.utilization_bps, 10_000);
+        assert!(health.is_paused);
+        assert_eq!(health.days_since_last_payment, Some(8));
+        assert_eq!(health.accrued_revenue, 3_000);
+    }
+
+    #[test]
+    fn loyalty_discount_steps_up_each_full_year() {
+        let plan = SubscriptionPlan {
+            price: 10_000,
+            loyalty_discount_bps_per_year: 500,
+            max_loyalty_discount_bps: 1_200,
+            ..Default::default()
+        };
+        let created = 1_000_000;
+        let subscription = Subscription {
+            created_at: created,
+            ..Default::default()
+        };
+        let at = |years: i64, extra: i64| created + years * SECONDS_PER_YEAR + extra;
+
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(1, -1)), 0);
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(1, 0)), 500);
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(2, -1)), 500);
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(2, 0)), 1_000);
+        // Capped from the third year on
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(3, 0)), 1_200);
+        assert_eq!(loyalty_discount_bps(&subscription, &plan, at(40, 0)), 1_200);
+
+        assert_eq!(effective_price(&subscription, &plan, at(1, 0)).unwrap(), 9_500);
+        assert_eq!(effective_price(&subscription, &plan, at(3, 0)).unwrap(), 8_800);
+
+        // Untracked tenure earns nothing
+        let legacy = Subscription::default();
+        assert_eq!(loyalty_discount_bps(&legacy, &plan, at(5, 0)), 0);
+    }
+
+    #[test]
+    fn discount_math_rounds_in_the_creators_favour() {
+        assert_eq!(apply_discount_bps(999, 500).unwrap(), 950);
+        assert_eq!(apply_discount_bps(u64::MAX, 10_000).unwrap(), 0);
+        assert_eq!(apply_discount_bps(u64::MAX, 0).unwrap(), u64::MAX);
+        assert!(apply_discount_bps(1_000, 10_001).is_err());
+    }
+
+    #[test]
+    fn v2_subscriptions_take_created_at_from_last_payment() {
+        let subscription = Subscription {
+            last_payment: 1_700_000_000,
+            schema_version: 2,
+            ..Default::default()
+        };
+        let mut migrated = decode_legacy::<Subscription>(&legacy_bytes(&subscription)).unwrap();
+        assert_eq!(migrated.created_at, 0);
+        migrated.upgrade(&UpgradeInputs::default());
+        assert_eq!(migrated.created_at, 1_700_000_000);
+        assert_eq!(migrated.schema_version, 3);
+        // The estimate doesn't hold the subscriber to a minimum duration
+        assert!(migrated.created_at_estimated);
+        assert!(migrated.min_duration_met(30 * DAY, 1_700_000_001));
+
+        // Already-recorded creation times are kept
+        let mut current = Subscription {
+            last_payment: 1_700_000_000,
+            created_at: 1_600_000_000,
+            schema_version: 2,
+            ..Default::default()
+        };
+        current.upgrade(&UpgradeInputs::default());
+        assert_eq!(current.created_at, 1_600_000_000);
+        assert!(!current.created_at_estimated);
+    }
+
+    #[test]
+    fn payment_decimals_must_match_when_recorded() {
+        let plan = SubscriptionPlan {
+            payment_decimals: Some(6),
+            ..Default::default()
+        };
+        assert!(plan.matches_payment_decimals(6));
+        assert!(!plan.matches_payment_decimals(9));
+
+        // Plans that predate the record accept the pinned mint as-is
+        let legacy = SubscriptionPlan::default();
+        assert!(legacy.matches_payment_decimals(0));
+        assert!(legacy.matches_payment_decimals(9));
+    }
+
+    #[test]
+    fn frozen_token_account_blocks_payment() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token_2022::spl_token_2022::state::{Account, AccountState};
+
+        let token_account = |state: AccountState| {
+            let mut data = vec![0; Account::LEN];
+            Account::pack(
+                Account {
+                    owner: Pubkey::new_unique(),
+                    amount: 1_000,
+                    state,
+                    ..Default::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            TokenAccount::try_deserialize(&mut &data[..]).unwrap()
+        };
+        let key = Pubkey::new_unique();
+        let mut subscription = Subscription::default();
+
+        assert!(!mark_payment_blocked(&mut subscription, &token_account(AccountState::Initialized), key));
+        assert!(!subscription.payment_blocked);
+
+        assert!(mark_payment_blocked(&mut subscription, &token_account(AccountState::Frozen), key));
+        assert!(subscription.payment_blocked);
+        assert_eq!(subscription.blocked_token_account, key);
+
+        // A payment that goes through clears the block
+        let mut plan = SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            ..Default::default()
+        };
+        subscription.interval_seconds = 30 * DAY;
+        settle_cycle(&mut subscription, &mut plan, 100, DAY).unwrap();
+        assert!(!subscription.payment_blocked);
+    }
+
+    #[test]
+    fn lifetime_cap_reached_mid_cycle_refuses_next_renewal() {
+        let mut plan = SubscriptionPlan {
+            is_active: true,
+            interval_seconds: 30 * DAY,
+            max_lifetime_seconds: 75 * DAY,
+            current_subscribers: 1,
+            active_count: 1,
+            ..Default::default()
+        };
+        let mut subscription = live_subscription(60 * DAY);
+        subscription.interval_seconds = 30 * DAY;
+
+        // Still inside the lifetime at the second renewal
+        assert_eq!(payable_cycles(&subscription, &plan, 60 * DAY).unwrap(), 1);
+        settle_cycle(&mut subscription, &mut plan, 100, 60 * DAY).unwrap();
+
+        // The cap passes mid-cycle; the paid cycle runs out normally
+        assert!(subscription.lifetime_reached(plan.max_lifetime_seconds, 75 * DAY));
+        assert_eq!(derive_status(&subscription, &plan, 80 * DAY).unwrap(), SubscriptionStatus::Active);
+
+        // ...but the next renewal is refused and the subscription ends
+        assert_eq!(
+            payable_cycles(&subscription, &plan, 90 * DAY).unwrap_err(),
+            error!(ErrorCode::LifetimeCapReached)
+        );
+        end_at_lifetime(&mut subscription, &mut plan, 90 * DAY).unwrap();
+        assert!(!subscription.is_active);
+        assert_eq!(subscription.status, SubscriptionStatus::Expired);
+        assert_eq!(plan.current_subscribers, 0);
+    }
+
+    /// ed25519 program data for one signature over `message` by `key`, with
+    /// every offset pointing at `instruction_index`
+    fn ed25519_data(key: Pubkey, message: &[u8], instruction_index: u16) -> Vec<u8> {
+        let signature_offset = (ED25519_OFFSETS_START + ED25519_OFFSETS_LEN) as u16;
+        let public_key_offset = signature_offset + 64;
+        let message_offset = public_key_offset + 32;
+        let mut data = vec![1, 0];
+        for field in [
+            signature_offset,
+            instruction_index,
+            public_key_offset,
+            instruction_index,
+            message_offset,
+            message.len() as u16,
+            instruction_index,
+        ] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(&[0; 64]);
+        data.extend_from_slice(key.as_ref());
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn ed25519_signed_message_reads_only_self_contained_signatures() {
+        let key = Pubkey::new_unique();
+        let message = b"permit".as_slice();
+        assert_eq!(
+            ed25519_signed_message(&ed25519_data(key, message, u16::MAX)),
+            Some((key, message))
+        );
+        // Key or message taken from another instruction could be swapped out
+        assert_eq!(ed25519_signed_message(&ed25519_data(key, message, 0)), None);
+
+        let mut two_signatures = ed25519_data(key, message, u16::MAX);
+        two_signatures[0] = 2;
+        assert_eq!(ed25519_signed_message(&two_signatures), None);
+
+        let truncated = ed25519_data(key, message, u16::MAX);
+        assert_eq!(ed25519_signed_message(&truncated[..truncated.len() - 1]), None);
+    }
+
+    #[test]
+    fn permit_signature_is_found_through_the_instructions_sysvar() {
+        use anchor_lang::solana_program::sysvar::instructions::{
+            construct_instructions_data, BorrowedInstruction,
+        };
+
+        let subscriber = Pubkey::new_unique();
+        let permit = PaymentPermit {
+            subscription: Pubkey::new_unique(),
+            max_amount: 1_000,
+            nonce: 3,
+            expires_at: 10 * DAY,
+        };
+        let message = permit.message();
+        let sysvar_data = |data: &[u8]| {
+            construct_instructions_data(&[
+                BorrowedInstruction {
+                    program_id: &ed25519_program::ID,
+                    accounts: vec![],
+                    data,
+                },
+                BorrowedInstruction {
+                    program_id: &crate::ID,
+                    accounts: vec![],
+                    data: &[],
+                },
+            ])
+        };
+        let check = |mut data: Vec<u8>, signer: Pubkey| {
+            let key = anchor_lang::solana_program::sysvar::instructions::ID;
+            let mut lamports = 0;
+            let owner = Pubkey::default();
+            let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+            check_permit_signature(&info, signer, &message)
+        };
+
+        let signed = ed25519_data(subscriber, &message, u16::MAX);
+        assert!(check(sysvar_data(&signed), subscriber).is_ok());
+        assert_eq!(
+            check(sysvar_data(&signed), Pubkey::new_unique()).unwrap_err(),
+            error!(ErrorCode::InvalidPermitSignature)
+        );
+
+        // A signature over a different permit doesn't authorize this one
+        let other = PaymentPermit { nonce: 4, ..permit }.message();
+        let signed_other = ed25519_data(subscriber, &other, u16::MAX);
+        assert!(check(sysvar_data(&signed_other), subscriber).is_err());
+    }
+
+    #[test]
+    fn raw_subscription_layout_offsets_are_stable() {
+        let mut subscription = live_subscription(1_000_000);
+        subscription.subscriber = Pubkey::new_unique();
+        subscription.creator = Pubkey::new_unique();
+        subscription.plan_id = 42;
+        subscription.ends_at = 5_000_000;
+        subscription.set_status(SubscriptionStatus::Grace);
+        let mut data = Vec::new();
+        subscription.try_serialize(&mut data).unwrap();
+
+        let at = |offset: usize, len: usize| &data[offset..offset + len];
+        assert_eq!(at(SUBSCRIPTION_SUBSCRIBER_OFFSET, 32), subscription.subscriber.as_ref());
+        assert_eq!(at(SUBSCRIPTION_PLAN_ID_OFFSET, 8), 42u64.to_le_bytes());
+        assert_eq!(at(SUBSCRIPTION_CREATOR_OFFSET, 32), subscription.creator.as_ref());
+        assert_eq!(data[SUBSCRIPTION_IS_ACTIVE_OFFSET], 1);
+        assert_eq!(at(SUBSCRIPTION_NEXT_PAYMENT_OFFSET, 8), 1_000_000i64.to_le_bytes());
+        assert_eq!(at(SUBSCRIPTION_ENDS_AT_OFFSET, 8), 5_000_000i64.to_le_bytes());
+        assert_eq!(data[SUBSCRIPTION_STATUS_OFFSET], SubscriptionStatus::Grace as u8);
+        assert_eq!(data[SUBSCRIPTION_BUNDLE_ID_OFFSET], 0);
+    }
+
+    #[test]
+    fn is_active_subscriber_decodes_serialized_accounts() {
+        let serialize = |subscription: &Subscription| {
+            let mut data = Vec::new();
+            subscription.try_serialize(&mut data).unwrap();
+            data
+        };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        let data = serialize(&subscription);
+        assert!(is_active_subscriber(&data, due));
+        assert!(is_active_subscriber(&data, due + GRACE_PERIOD_SECONDS));
+        assert!(!is_active_subscriber(&data, due + GRACE_PERIOD_SECONDS + 1));
+
+        // Variable-size fields before the grace override are stepped over
+        subscription.bundle_id = Some(7);
+        subscription.grace_override_seconds = Some(DAY);
+        let data = serialize(&subscription);
+        assert!(is_active_subscriber(&data, due + DAY));
+        assert!(!is_active_subscriber(&data, due + DAY + 1));
+
+        subscription.ends_at = due - 1;
+        assert!(!is_active_subscriber(&serialize(&subscription), due - 1));
+
+        subscription.ends_at = 0;
+        subscription.set_status(SubscriptionStatus::Cancelled);
+        assert!(!is_active_subscriber(&serialize(&subscription), due));
+
+        // Legacy (shorter) accounts decode too; other accounts never match
+        let legacy = live_subscription(due);
+        assert!(is_active_subscriber(&legacy_bytes(&legacy), due));
+        let mut plan_data = Vec::new();
+        SubscriptionPlan::default().try_serialize(&mut plan_data).unwrap();
+        assert!(!is_active_subscriber(&plan_data, due));
+        assert!(!is_active_subscriber(&[], due));
+    }
+
+    #[test]
+    fn fee_exempt_creators_owe_no_creation_fee() {
+        let program_config = ProgramConfig {
+            plan_creation_fee: 5_000,
+            ..Default::default()
+        };
+        let partner = CreatorConfig {
+            fee_exempt: true,
+            ..Default::default()
+        };
+
+        assert_eq!(creation_fee_due(&program_config, &CreatorConfig::default()), 5_000);
+        assert_eq!(creation_fee_due(&program_config, &partner), 0);
+    }
+
+    #[test]
+    fn closed_signups_still_bill_existing_subscribers() {
+        let plan = SubscriptionPlan {
+            is_active: true,
+            interval_seconds: 30 * DAY,
+            max_subscribers: 10,
+            new_subscriptions_disabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            join_rejection(&plan, 0),
+            Some(SubscribeRejectReason::NewSubscriptionsDisabled)
+        );
+
+        let due = 100 * DAY;
+        assert_eq!(payable_cycles(&live_subscription(due), &plan, due).unwrap(), 1);
+    }
+
+    #[test]
+    fn final_charge_is_prorated_to_the_hard_end() {
+        let plan = SubscriptionPlan {
+            price: 3_000,
+            ..Default::default()
+        };
+        let mut subscription = live_subscription(10 * DAY);
+        subscription.interval_seconds = 30 * DAY;
+
+        subscription.ends_at = 40 * DAY;
+        assert_eq!(renewal_charge(&subscription, &plan, 1, 10 * DAY).unwrap(), 3_000);
+        assert_eq!(renewal_charge(&subscription, &plan, 2, 40 * DAY).unwrap(), 3_000);
+
+        subscription.ends_at = 25 * DAY;
+        assert_eq!(renewal_charge(&subscription, &plan, 1, 10 * DAY).unwrap(), 1_500);
+
+        subscription.ends_at = 0;
+        let plan = SubscriptionPlan {
+            max_lifetime_seconds: 50 * DAY,
+            ..plan
+        };
+        assert_eq!(subscription.hard_end(plan.max_lifetime_seconds), Some(50 * DAY));
+        assert_eq!(renewal_charge(&subscription, &plan, 2, 40 * DAY).unwrap(), 3_000 + 1_000);
+    }
+
+    #[test]
+    fn payment_receipt_reflects_settled_subscription() {
+        let mut plan = SubscriptionPlan {
+            plan_id: 7,
+            ..Default::default()
+        };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        subscription.plan_id = 7;
+        subscription.interval_seconds = 30 * DAY;
+
+        settle_cycle(&mut subscription, &mut plan, 500, due).unwrap();
+        let receipt = PaymentReceipt::for_subscription(&subscription, 500, due);
+        assert_eq!(
+            receipt,
+            PaymentReceipt {
+                next_payment: due + 30 * DAY,
+                total_payments: 1,
+                charged_amount: 500,
+                plan_id: 7,
+                fee: 0,
+                net_to_creator: 500,
+                timestamp: due,
+            }
+        );
+        assert_eq!(receipt.total_payments, subscription.total_payments);
+    }
+
+    #[test]
+    fn refund_eligibility_uses_cycles_charged_at_join() {
+        let mut plan = SubscriptionPlan {
+            interval_seconds: 30 * DAY,
+            prepay_cycles_on_join: 3,
+            ..Default::default()
+        };
+        let mut subscription = Subscription::default();
+        let (cycles, amount) = initial_charge(&plan, 100).unwrap();
+        activate_subscription(&mut subscription, &mut plan, cycles, amount, 1_000_000).unwrap();
+        assert_eq!(subscription.join_cycles, 3);
+
+        // The creator changing upfront cycles later doesn't move it
+        plan.prepay_cycles_on_join = 1;
+        assert_eq!(subscription.cycles_paid_on_join(&plan), 3);
+        plan.prepay_cycles_on_join = 6;
+        assert_eq!(subscription.cycles_paid_on_join(&plan), 3);
+
+        // Unrecorded on older subscriptions: the plan's value stands in
+        subscription.join_cycles = 0;
+        assert_eq!(subscription.cycles_paid_on_join(&plan), 6);
+    }
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct UnpausePlan<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump,
-        has_one = creator @ ErrorCode::InvalidCreator,
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    pub creator: Signer<'info>,
-}
+    #[test]
+    fn cancelling_waits_for_minimum_duration() {
+        let subscription = Subscription {
+            created_at: 1_000,
+            ..Default::default()
+        };
+        assert!(subscription.min_duration_met(0, 1_000));
+        assert!(!subscription.min_duration_met(DAY, 1_000 + DAY - 1));
+        assert!(subscription.min_duration_met(DAY, 1_000 + DAY));
 
-#[derive(Accounts)]
-#[instruction(plan_id: u64)]
-pub struct DeactivatePlan<'info> {
-    #[account(
-        mut,
-        seeds = [b"subscription_plan", creator.key().as_ref(), &plan_id.to_le_bytes()],
-        bump = subscription_plan.bump,
-        has_one = creator @ ErrorCode::InvalidCreator,
-    )]
-    pub subscription_plan: Account<'info, SubscriptionPlan>,
-    pub creator: Signer<'info>,
-}
+        let legacy = Subscription::default();
+        assert!(legacy.min_duration_met(DAY, 1));
+    }
 
-// ============================================================================
-// Data Structures
-// ============================================================================
+    #[test]
+    fn subscriptions_bill_in_their_chosen_token() {
+        let primary = Pubkey::new_unique();
+        let alternate = Pubkey::new_unique();
+        let plan = SubscriptionPlan {
+            payment_mint: primary,
+            price: 1_000,
+            payment_decimals: Some(6),
+            accepted_tokens: vec![AcceptedToken {
+                mint: alternate,
+                price: 5_000,
+                decimals: 9,
+                payout_token_account: Pubkey::new_unique(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(plan.price_in(&primary), Some(1_000));
+        assert_eq!(plan.price_in(&alternate), Some(5_000));
+        assert_eq!(plan.price_in(&Pubkey::new_unique()), None);
+        assert!(plan.matches_decimals(&alternate, 9));
+        assert!(!plan.matches_decimals(&alternate, 6));
 
-#[account]
-pub struct SubscriptionPlan {
-    /// Creator's public key
-    pub creator: Pubkey,
-    /// Unique plan identifier
-    pub plan_id: u64,
-    /// Price per billing cycle (in smallest token unit)
-    pub price: u64,
-    /// Billing interval in seconds
-    pub interval_seconds: i64,
-    /// Maximum allowed subscribers
-    pub max_subscribers: u32,
-    /// Current number of active subscribers
-    pub current_subscribers: u32,
-    /// Whether plan accepts new subscriptions
-    pub is_active: bool,
-    /// Whether plan is temporarily paused
-    pub is_paused: bool,
-    /// URI to plan metadata (max 200 chars)
-    pub metadata_uri: String,
-    /// Creation timestamp
-    pub created_at: i64,
-    /// PDA bump seed
-    pub bump: u8,
-}
+        let mut subscription = Subscription::default();
+        assert_eq!(subscription.billing_mint(&plan), primary);
+        assert_eq!(effective_price(&subscription, &plan, 0).unwrap(), 1_000);
 
-impl SubscriptionPlan {
-    pub const LEN: usize = 8 + // discriminator
-        32 + // creator
-        8 + // plan_id
-        8 + // price
-        8 + // interval_seconds
-        4 + // max_subscribers
-        4 + // current_subscribers
-        1 + // is_active
-        1 + // is_paused
-        4 + 200 + // metadata_uri (String with max 200 chars)
-        8 + // created_at
-        1; // bump
-}
+        subscription.payment_mint = alternate;
+        assert_eq!(subscription.billing_mint(&plan), alternate);
+        assert_eq!(effective_price(&subscription, &plan, 0).unwrap(), 5_000);
 
-#[account]
-pub struct Subscription {
-    /// Subscriber's public key
-    pub subscriber: Pubkey,
-    /// Associated plan ID
-    pub plan_id: u64,
-    /// Plan creator's public key
-    pub creator: Pubkey,
-    /// Whether subscription is active
-    pub is_active: bool,
-    /// Timestamp of last payment
-    pub last_payment: i64,
-    /// Timestamp when next payment is due
-    pub next_payment: i64,
-    /// Total number of payments made
-    pub total_payments: u64,
-    /// PDA bump seed
-    pub bump: u8,
-}
+        let mut delisted = SubscriptionPlan {
+            accepted_tokens: Vec::new(),
+            delisted_tokens: plan.accepted_tokens.clone(),
+            ..plan.clone()
+        };
+        assert_eq!(delisted.price_in(&alternate), None);
+        assert_eq!(effective_price(&subscription, &delisted, 0).unwrap(), 5_000);
+        assert!(delisted.matches_decimals(&alternate, 9));
+        assert_eq!(
+            delisted.payout_account_for(&alternate),
+            plan.payout_account_for(&alternate)
+        );
 
-impl Subscription {
-    pub const LEN: usize = 8 + // discriminator
-        32 + // subscriber
-        8 + // plan_id
-        32 + // creator
-        1 + // is_active
-        8 + // last_payment
-        8 + // next_payment
-        8 + // total_payments
-        1; // bump
-}
+        delisted.delisted_tokens.clear();
+        assert!(effective_price(&subscription, &delisted, 0).is_err());
+    }
 
-// ============================================================================
-// Events
-// ============================================================================
+    #[test]
+    fn chosen_price_is_floored_at_plan_price() {
+        let mint = Pubkey::new_unique();
+        let mut plan = SubscriptionPlan {
+            payment_mint: mint,
+            price: 1_000,
+            ..Default::default()
+        };
+        assert!(check_chosen_price(&plan, &mint, 1_500).is_err());
 
-#[event]
-pub struct SubscriptionPlanCreated {
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub price: u64,
-    pub interval_seconds: i64,
-    pub timestamp: i64,
-}
+        plan.pay_what_you_want = true;
+        check_chosen_price(&plan, &mint, 1_000).unwrap();
+        assert!(check_chosen_price(&plan, &mint, 999).is_err());
+        assert!(check_chosen_price(&plan, &Pubkey::new_unique(), 1_500).is_err());
 
-#[event]
-pub struct SubscriptionCreated {
-    pub subscriber: Pubkey,
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+        let subscription = Subscription {
+            chosen_price: 1_500,
+            ..Default::default()
+        };
+        assert_eq!(effective_price(&subscription, &plan, 0).unwrap(), 1_500);
+        plan.price = 2_000;
+        assert_eq!(effective_price(&subscription, &plan, 0).unwrap(), 2_000);
+    }
 
-#[event]
-pub struct PaymentProcessed {
-    pub subscriber: Pubkey,
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub amount: u64,
-    pub payment_number: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn cancel_guard_finds_a_subscribe_in_the_same_transaction() {
+        use anchor_lang::solana_program::sysvar::instructions::{
+            construct_instructions_data, BorrowedAccountMeta, BorrowedInstruction,
+        };
 
-#[event]
-pub struct SubscriptionCancelled {
-    pub subscriber: Pubkey,
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+        let subscription = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let plan_key = Pubkey::new_unique();
+        fn meta(pubkey: &Pubkey) -> BorrowedAccountMeta<'_> {
+            BorrowedAccountMeta { pubkey, is_signer: false, is_writable: true }
+        }
+        let check_join = |plan: &SubscriptionPlan, join: &[u8], accounts: &[Pubkey], program_id: &Pubkey| {
+            let mut data = construct_instructions_data(&[
+                BorrowedInstruction {
+                    program_id,
+                    accounts: accounts.iter().map(meta).collect(),
+                    data: join,
+                },
+                BorrowedInstruction {
+                    program_id: &crate::ID,
+                    accounts: vec![meta(&plan_key), meta(&subscription)],
+                    data: &instruction::CancelSubscription::DISCRIMINATOR,
+                },
+            ]);
+            let key = anchor_lang::solana_program::sysvar::instructions::ID;
+            let mut lamports = 0;
+            let owner = Pubkey::default();
+            let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+            check_not_joined_in_tx(plan, &subscription, &info)
+        };
+        let check = |plan: &SubscriptionPlan, joined: &Pubkey, program_id: &Pubkey| {
+            check_join(plan, &instruction::Subscribe::DISCRIMINATOR, &[plan_key, *joined], program_id)
+        };
 
-#[event]
-pub struct SubscriptionPlanUpdated {
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+        let mut plan = SubscriptionPlan::default();
+        assert!(check(&plan, &subscription, &crate::ID).is_ok());
 
-#[event]
-pub struct SubscriptionPlanPaused {
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+        plan.block_same_tx_cancel = true;
+        assert_eq!(
+            check(&plan, &subscription, &crate::ID).unwrap_err(),
+            error!(ErrorCode::SameTransactionCancel)
+        );
+        assert!(check(&plan, &other, &crate::ID).is_ok());
+        assert!(check(&plan, &subscription, &Pubkey::new_unique()).is_ok());
 
-#[event]
-pub struct SubscriptionPlanUnpaused {
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+        // Bundle members can sit anywhere among the join's accounts
+        let bundle_join = [plan_key, other, plan_key, subscription];
+        assert!(check_join(&plan, &instruction::SubscribeBundle::DISCRIMINATOR, &bundle_join, &crate::ID).is_err());
+        let successor_join = [plan_key, other, plan_key, subscription];
+        assert!(check_join(&plan, &instruction::FollowSuccessor::DISCRIMINATOR, &successor_join, &crate::ID).is_err());
+        let successor_old = [plan_key, subscription, plan_key, other];
+        assert!(check_join(&plan, &instruction::FollowSuccessor::DISCRIMINATOR, &successor_old, &crate::ID).is_ok());
+    }
 
-#[event]
-pub struct SubscriptionPlanDeactivated {
-    pub creator: Pubkey,
-    pub plan_id: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn grace_window_in_seconds_or_cycles() {
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        subscription.interval_seconds = 30 * DAY;
+        let mut plan = SubscriptionPlan::default();
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due + GRACE_PERIOD_SECONDS);
 
-// ============================================================================
-// Error Codes
-// ============================================================================
+        plan.grace_window = GraceWindow::Seconds { seconds: DAY };
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due + DAY);
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Subscription plan is inactive")]
-    PlanInactive,
-    #[msg("Subscription plan is full")]
-    PlanFull,
-    #[msg("Payment is not due yet")]
-    PaymentNotDue,
-    #[msg("Subscription is inactive")]
-    SubscriptionInactive,
-    #[msg("Price must be greater than 0")]
-    InvalidPrice,
-    #[msg("Interval must be at least 60 seconds")]
-    IntervalTooShort,
-    #[msg("Max subscribers must be greater than 0")]
-    InvalidMaxSubscribers,
-    #[msg("Metadata URI exceeds 200 character limit")]
-    MetadataUriTooLong,
-    #[msg("Mathematical overflow occurred")]
-    Overflow,
-    #[msg("Mathematical underflow occurred")]
-    Underflow,
-    #[msg("Invalid token account owner")]
-    InvalidTokenAccountOwner,
-    #[msg("Token account mint mismatch")]
-    MintMismatch,
-    #[msg("Subscription plan is paused")]
-    PlanPaused,
-    #[msg("Payment is too late (beyond grace period)")]
-    PaymentTooLate,
-    #[msg("Subscription is still active, cannot close")]
-    SubscriptionStillActive,
-    #[msg("New max subscribers cannot be less than current subscribers")]
-    MaxSubscribersTooLow,
-    #[msg("Invalid creator")]
-    InvalidCreator,
-    #[msg("Invalid subscriber")]
-    InvalidSubscriber,
-    #[msg("Invalid plan ID")]
-    InvalidPlanId,
-    #[msg("Plan is already paused")]
-    PlanAlreadyPaused,
-    #[msg("Plan is not paused")]
-    PlanNotPaused,
-    #[msg("Plan is already inactive")]
-    PlanAlreadyInactive,
+        plan.grace_window = GraceWindow::Cycles { cycles: 1 };
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due + 30 * DAY);
+        subscription.interval_seconds = 7 * DAY;
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due + 7 * DAY);
+
+        plan.grace_window = GraceWindow::Cycles { cycles: 0 };
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due);
+        assert_eq!(derive_status(&subscription, &plan, due).unwrap(), SubscriptionStatus::Active);
+        assert_eq!(derive_status(&subscription, &plan, due + 1).unwrap(), SubscriptionStatus::Expired);
+
+        subscription.grace_override_seconds = Some(DAY);
+        assert_eq!(payment_deadline(&subscription, &plan, due).unwrap(), due + DAY);
+    }
+
+    #[test]
+    fn plan_events_are_sequenced() {
+        let mut plan = plan_with_maintenance(0, 0);
+        assert_eq!(plan.next_event_seq().unwrap(), 1);
+        let update = SubscriptionPlanUpdated::settings_changed(&mut plan, 0).unwrap();
+        assert_eq!(update.event_seq, 2);
+        assert_eq!(plan.event_seq, 2);
+
+        // Deactivation lifecycle events share the sequence
+        assert_eq!(DeactivationScheduled::for_plan(&mut plan, DAY, 0).unwrap().event_seq, 3);
+        assert_eq!(DeactivationCancelled::for_plan(&mut plan, 0).unwrap().event_seq, 4);
+        let admin = Pubkey::new_unique();
+        assert_eq!(SubscriptionPlanReactivated::for_plan(&mut plan, admin, 0).unwrap().event_seq, 5);
+
+        plan.event_seq = u64::MAX;
+        assert!(plan.next_event_seq().is_err());
+        assert_eq!(plan.event_seq, u64::MAX);
+    }
+
+    #[test]
+    fn capacity_changes_respect_cooldown() {
+        let mut plan = plan_with_maintenance(0, 0);
+        plan.last_capacity_change = 1_000;
+        assert!(plan.check_capacity_change(1_001).is_ok());
+
+        plan.capacity_change_cooldown_seconds = DAY;
+        assert!(plan.check_capacity_change(1_000 + DAY - 1).is_err());
+        assert!(plan.check_capacity_change(1_000 + DAY).is_ok());
+
+        // Never changed: the first change is always allowed
+        plan.last_capacity_change = 0;
+        assert!(plan.check_capacity_change(1).is_ok());
+    }
+
+    #[test]
+    fn zero_interval_is_an_error_not_a_panic() {
+        // Subscriptions created before `interval_seconds` existed read it as 0
+        let legacy = Subscription {
+            is_active: true,
+            next_payment: 1_000_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            legacy.cycles_due(1_000_000 + DAY, u32::MAX).unwrap_err(),
+            error!(ErrorCode::IntervalTooShort)
+        );
+        assert_eq!(
+            legacy.next_due_after(legacy.next_payment, 1_000_000 + DAY).unwrap_err(),
+            error!(ErrorCode::IntervalTooShort)
+        );
+    }
+
+    #[test]
+    fn prorated_refund_uses_the_amount_paid_per_cycle() {
+        let mut plan = SubscriptionPlan { price: 1_000, ..Default::default() };
+        let due = 1_000_000;
+        let mut subscription = live_subscription(due);
+        subscription.interval_seconds = 30 * DAY;
+
+        // Renewed at a 10% loyalty discount
+        settle_cycle(&mut subscription, &mut plan, 900, due).unwrap();
+        assert_eq!(subscription.cycle_amount_paid, 900);
+        let halfway = subscription.next_payment - 15 * DAY;
+        assert_eq!(
+            prorated_refund(subscription.cycle_amount_paid, subscription.next_payment, halfway, 30 * DAY)
+                .unwrap(),
+            450
+        );
+
+        settle_cycles(&mut subscription, &mut plan, 3, 2_700, due).unwrap();
+        assert_eq!(subscription.cycle_amount_paid, 900);
+    }
+
+    #[cfg(feature = "test-clock")]
+    #[test]
+    fn trailing_test_clock_account_is_split_off() {
+        let (clock_key, _) = Pubkey::find_program_address(&[b"test_clock"], &crate::ID);
+        let other_key = Pubkey::new_unique();
+        let program_id = crate::ID;
+        let system_program = anchor_lang::system_program::ID;
+        let (mut clock_lamports, mut other_lamports) = (0, 0);
+        let mut clock_data = TestClock { unix_timestamp: 1_234 }.try_to_vec().unwrap();
+        let mut other_data = vec![];
+        let other = AccountInfo::new(&other_key, false, false, &mut other_lamports, &mut other_data, &system_program, false, 0);
+        let clock = AccountInfo::new(&clock_key, false, false, &mut clock_lamports, &mut clock_data, &program_id, false, 0);
+
+        let accounts = [other.clone(), clock.clone()];
+        let (now, rest) = split_test_clock(&crate::ID, &accounts).unwrap();
+        assert_eq!(now, Some(1_234));
+        assert_eq!(rest.len(), 1);
+
+        // Only the last account counts, so the clock can't shadow an
+        // instruction's own accounts
+        let accounts = [clock, other];
+        let (now, rest) = split_test_clock(&crate::ID, &accounts).unwrap();
+        assert_eq!(now, None);
+        assert_eq!(rest.len(), 2);
+    }
 }
@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use circulum::cpi::accounts::Subscribe;
+use circulum::program::Circulum;
+use circulum::SubscriptionPlan;
+
+declare_id!("D65aCyhuveAAx5sKZESGEnRtiXNHw9Qak8HNGX5QxLGW");
+
+/// Test-only program exercising Circulum's `subscribe` via CPI with a PDA
+/// subscriber, the way a DAO or aggregator would subscribe a member.
+#[program]
+pub mod mock_caller {
+    use super::*;
+
+    /// Subscribe the `["member", authority]` PDA to a plan
+    /// 
+    /// The PDA pays rent for the subscription account and owns the token
+    /// account charged for the initial payment, so it must be funded with
+    /// lamports and tokens beforehand.
+    pub fn subscribe_member(ctx: Context<SubscribeMember>, plan_id: u64) -> Result<()> {
+        let authority_key = ctx.accounts.authority.key();
+        let seeds = &[
+            b"member".as_ref(),
+            authority_key.as_ref(),
+            &[ctx.bumps.member],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Subscribe {
+            subscription_plan: ctx.accounts.subscription_plan.to_account_info(),
+            subscription: ctx.accounts.subscription.to_account_info(),
+            subscriber: ctx.accounts.member.to_account_info(),
+            rent_payer: ctx.accounts.member.to_account_info(),
+            funding_authority: ctx.accounts.member.to_account_info(),
+            subscriber_token_account: ctx.accounts.member_token_account.to_account_info(),
+            payment_mint: ctx.accounts.payment_mint.to_account_info(),
+            creator_token_account: ctx.accounts.creator_token_account.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            instructions_sysvar: ctx.accounts.instructions_sysvar.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.circulum_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+
+        circulum::cpi::subscribe(cpi_ctx, plan_id, [0u8; 32], 0, None, None)?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SubscribeMember<'info> {
+    #[account(mut)]
+    pub subscription_plan: Account<'info, SubscriptionPlan>,
+    /// CHECK: Created and validated by Circulum
+    #[account(mut)]
+    pub subscription: UncheckedAccount<'info>,
+    /// CHECK: System-owned PDA used only as a signer and payer
+    #[account(
+        mut,
+        seeds = [b"member", authority.key().as_ref()],
+        bump,
+    )]
+    pub member: UncheckedAccount<'info>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub member_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub circulum_program: Program<'info, Circulum>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Instructions sysvar, forwarded to Circulum
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
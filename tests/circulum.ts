@@ -3,9 +3,11 @@ import { Program } from "@coral-xyz/anchor";
 // Note: This import will work after running 'anchor build'
 // The types file will be generated based on the program name in Anchor.toml
 import { Circulum } from "../target/types/circulum";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID, createMint, createAccount, mintTo } from "@solana/spl-token";
+import { MockCaller } from "../target/types/mock_caller";
+import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL, TransactionInstruction, Ed25519Program } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, createMint, createAccount, mintTo, approve, getAccount } from "@solana/spl-token";
 import { assert } from "chai";
+import { createHash } from "crypto";
 
 describe("Circulum", () => {
   // Configure the client to use the local cluster.
@@ -16,6 +18,7 @@ describe("Circulum", () => {
 
   // Test accounts
   let creator: Keypair;
+  let creatorRegistryPda: PublicKey;
   let subscriber: Keypair;
   let tokenMint: PublicKey;
   let creatorTokenAccount: PublicKey;
@@ -26,10 +29,72 @@ describe("Circulum", () => {
   const intervalSeconds = new anchor.BN(2592000); // 30 days
   const maxSubscribers = 1000;
   const metadataUri = "https://example.com/metadata.json";
+  const emptyReference = new Array(32).fill(0);
+  const currencySymbol = "USDC";
+  const metadataHash = Array.from(
+    createHash("sha256").update('{"name":"Test Plan"}').digest()
+  );
+
+  const findPlanPda = (planCreator: PublicKey, id: anchor.BN) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("subscription_plan"),
+        planCreator.toBuffer(),
+        id.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const findCreatorRegistryPda = (planCreator: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("creator_registry"), planCreator.toBuffer()],
+      program.programId
+    )[0];
+
+  const [programConfigPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("program_config")],
+    program.programId
+  );
+
+  const findSubscriptionPda = (owner: PublicKey, id: anchor.BN) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("subscription"),
+        owner.toBuffer(),
+        id.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  // Creates a subscriber keypair with SOL and a token account holding `amount`
+  const createFundedSubscriber = async (amount = 1000000000) => {
+    const wallet = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(wallet.publicKey, 2 * LAMPORTS_PER_SOL)
+    );
+    const tokenAccount = await createAccount(
+      provider.connection,
+      wallet,
+      tokenMint,
+      wallet.publicKey
+    );
+    if (amount > 0) {
+      await mintTo(
+        provider.connection,
+        creator,
+        tokenMint,
+        tokenAccount,
+        creator,
+        amount
+      );
+    }
+    return { wallet, tokenAccount };
+  };
 
   before(async () => {
     // Initialize test accounts
     creator = Keypair.generate();
+    creatorRegistryPda = findCreatorRegistryPda(creator.publicKey);
     subscriber = Keypair.generate();
 
     // Airdrop SOL to test accounts
@@ -91,11 +156,18 @@ describe("Circulum", () => {
         price,
         intervalSeconds,
         maxSubscribers,
-        metadataUri
+        metadataUri,
+        metadataHash,
+        currencySymbol
       )
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
         creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
         systemProgram: SystemProgram.programId,
       })
       .signers([creator])
@@ -114,6 +186,13 @@ describe("Circulum", () => {
     assert.equal(subscriptionPlan.currentSubscribers, 0);
     assert.equal(subscriptionPlan.isActive, true);
     assert.equal(subscriptionPlan.metadataUri, metadataUri);
+    assert.deepEqual(subscriptionPlan.metadataHash, metadataHash);
+    assert.equal(subscriptionPlan.currencySymbol, currencySymbol);
+    assert.equal(subscriptionPlan.creationNonce.toNumber(), 0);
+    assert.equal(subscriptionPlan.paymentDecimals, 6);
+
+    const registry = await program.account.creatorRegistry.fetch(creatorRegistryPda);
+    assert.equal(registry.plansCreated.toNumber(), 1);
   });
 
   it("Subscribes to a plan", async () => {
@@ -136,11 +215,13 @@ describe("Circulum", () => {
     );
 
     await program.methods
-      .subscribe(planId)
+      .subscribe(planId, emptyReference, new anchor.BN(0), null, null)
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
         subscription: subscriptionPda,
         subscriber: subscriber.publicKey,
+        rentPayer: subscriber.publicKey,
+        fundingAuthority: subscriber.publicKey,
         systemProgram: SystemProgram.programId,
       })
       .signers([subscriber])
@@ -192,12 +273,14 @@ describe("Circulum", () => {
     const subscription = await program.account.subscription.fetch(subscriptionPda);
     
     await program.methods
-      .processPayment(planId)
+      .processPayment(planId, null)
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
         subscription: subscriptionPda,
         subscriber: subscriber.publicKey,
+        fundingAuthority: subscriber.publicKey,
         subscriberTokenAccount: subscriberTokenAccount,
+        paymentMint: tokenMint,
         creatorTokenAccount: creatorTokenAccount,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -248,13 +331,14 @@ describe("Circulum", () => {
     );
 
     assert.equal(subscription.isActive, false);
+    assert.deepEqual(subscription.status, { cancelled: {} });
     assert.equal(subscriptionPlan.currentSubscribers, 0);
   });
 
   it("Updates a subscription plan", async () => {
     const newPlanId = new anchor.BN(2);
     const newPrice = new anchor.BN(2000000); // 0.002 SOL
-    const newInterval = new anchor.BN(604800); // 7 days
+    const newInterval = new anchor.BN(5184000); // 60 days
     const newMaxSubscribers = 500;
     const newMetadataUri = "https://example.com/new-metadata.json";
 
@@ -274,31 +358,41 @@ describe("Circulum", () => {
         price,
         intervalSeconds,
         maxSubscribers,
-        metadataUri
+        metadataUri,
+        metadataHash,
+        currencySymbol
       )
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
         creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
         systemProgram: SystemProgram.programId,
       })
       .signers([creator])
       .rpc();
 
     // Now update it
-    await program.methods
+    const updateSignature = await program.methods
       .updateSubscriptionPlan(
         newPlanId,
         newPrice,
         newInterval,
         newMaxSubscribers,
-        newMetadataUri
+        newMetadataUri,
+        null,
+        null
       )
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
-        creator: creator.publicKey,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
       })
       .signers([creator])
-      .rpc();
+      .rpc({ commitment: "confirmed" });
 
     // Fetch updated plan
     const updatedPlan = await program.account.subscriptionPlan.fetch(
@@ -309,6 +403,22 @@ describe("Circulum", () => {
     assert.ok(updatedPlan.intervalSeconds.eq(newInterval));
     assert.equal(updatedPlan.maxSubscribers, newMaxSubscribers);
     assert.equal(updatedPlan.metadataUri, newMetadataUri);
+
+    // The update event carries old and new terms
+    const updateTx = await provider.connection.getTransaction(updateSignature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const updated = [...parser.parseLogs(updateTx.meta.logMessages)].find(
+      (e) => e.name === "subscriptionPlanUpdated"
+    );
+    assert.ok(updated.data.oldPrice.eq(price));
+    assert.ok(updated.data.newPrice.eq(newPrice));
+    assert.ok(updated.data.oldIntervalSeconds.eq(intervalSeconds));
+    assert.ok(updated.data.newIntervalSeconds.eq(newInterval));
+    assert.equal(updated.data.oldMaxSubscribers, maxSubscribers);
+    assert.equal(updated.data.newMaxSubscribers, newMaxSubscribers);
   });
 
   it("Deactivates a subscription plan", async () => {
@@ -322,14 +432,14 @@ describe("Circulum", () => {
       program.programId
     );
 
-    await program.methods
-      .deactivatePlan(planToDeactivate)
+    const deactivateSignature = await program.methods
+      .deactivatePlan(planToDeactivate, null)
       .accounts({
         subscriptionPlan: subscriptionPlanPda,
         creator: creator.publicKey,
       })
       .signers([creator])
-      .rpc();
+      .rpc({ commitment: "confirmed" });
 
     // Fetch updated plan
     const deactivatedPlan = await program.account.subscriptionPlan.fetch(
@@ -337,6 +447,16 @@ describe("Circulum", () => {
     );
 
     assert.equal(deactivatedPlan.isActive, false);
+
+    const deactivateTx = await provider.connection.getTransaction(deactivateSignature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const deactivated = [...parser.parseLogs(deactivateTx.meta.logMessages)].find(
+      (e) => e.name === "subscriptionPlanDeactivated"
+    );
+    assert.equal(deactivated.data.currentSubscribers, deactivatedPlan.currentSubscribers);
   });
 
   it("Fails to subscribe to inactive plan", async () => {
@@ -361,11 +481,13 @@ describe("Circulum", () => {
 
     try {
       await program.methods
-        .subscribe(inactivePlanId)
+        .subscribe(inactivePlanId, emptyReference, new anchor.BN(0), null, null)
         .accounts({
           subscriptionPlan: subscriptionPlanPda,
           subscription: subscriptionPda,
           subscriber: subscriber.publicKey,
+          rentPayer: subscriber.publicKey,
+          fundingAuthority: subscriber.publicKey,
           systemProgram: SystemProgram.programId,
         })
         .signers([subscriber])
@@ -376,4 +498,3866 @@ describe("Circulum", () => {
       assert.include(error.toString(), "PlanInactive");
     }
   });
+
+  it("Logs PlanCapacityReached when subscribing to a full plan", async () => {
+    const fullPlanId = new anchor.BN(3);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, fullPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(fullPlanId, price, intervalSeconds, 1, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const first = await createFundedSubscriber();
+    const soldOutSignature = await program.methods
+      .subscribe(fullPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: findSubscriptionPda(first.wallet.publicKey, fullPlanId),
+        subscriber: first.wallet.publicKey,
+        rentPayer: first.wallet.publicKey,
+        fundingAuthority: first.wallet.publicKey,
+        subscriberTokenAccount: first.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([first.wallet])
+      .rpc({ commitment: "confirmed" });
+
+    // Taking the last seat announces the sell-out
+    const soldOutTx = await provider.connection.getTransaction(soldOutSignature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const soldOut = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(
+        soldOutTx.meta.logMessages
+      ),
+    ].find((e) => e.name === "planSoldOut");
+    assert.ok(soldOut, "PlanSoldOut should be emitted");
+    assert.equal(soldOut.data.maxSubscribers, 1);
+
+    const second = await createFundedSubscriber();
+    try {
+      await program.methods
+        .subscribe(fullPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(second.wallet.publicKey, fullPlanId),
+          subscriber: second.wallet.publicKey,
+          rentPayer: second.wallet.publicKey,
+          fundingAuthority: second.wallet.publicKey,
+          subscriberTokenAccount: second.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([second.wallet])
+        .rpc();
+
+      assert.fail("Should have failed to subscribe to a full plan");
+    } catch (error) {
+      assert.include(error.toString(), "PlanFull");
+
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const events = [...parser.parseLogs(error.logs)];
+      const capacity = events.find((e) => e.name === "planCapacityReached");
+      assert.ok(capacity, "PlanCapacityReached should be logged");
+      assert.equal(capacity.data.currentSubscribers, 1);
+      assert.equal(capacity.data.maxSubscribers, 1);
+    }
+  });
+
+  it("Rotates the payout account", async () => {
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, planId);
+    const newPayoutAccount = await createAccount(
+      provider.connection,
+      creator,
+      tokenMint,
+      creator.publicKey,
+      Keypair.generate()
+    );
+
+    await program.methods
+      .setPayoutAccount(planId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        newPayoutTokenAccount: newPayoutAccount,
+      })
+      .signers([creator])
+      .rpc();
+
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.payoutTokenAccount.equals(newPayoutAccount));
+
+    // Payout accounts owned by someone else are rejected
+    try {
+      await program.methods
+        .setPayoutAccount(planId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+          newPayoutTokenAccount: subscriberTokenAccount,
+        })
+        .signers([creator])
+        .rpc();
+
+      assert.fail("Should have rejected a payout account not owned by the creator");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidTokenAccountOwner");
+    }
+
+    // Restore the original payout account for the remaining tests
+    await program.methods
+      .setPayoutAccount(planId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        newPayoutTokenAccount: creatorTokenAccount,
+      })
+      .signers([creator])
+      .rpc();
+  });
+
+  it("Refunds the initial payment within the refund window", async () => {
+    const refundPlanId = new anchor.BN(4);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, refundPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(refundPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .setRefundWindow(refundPlanId, new anchor.BN(3600))
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    // The plan PDA pays refunds out of the payout account as a delegate
+    await approve(
+      provider.connection,
+      creator,
+      creatorTokenAccount,
+      subscriptionPlanPda,
+      creator,
+      price.toNumber()
+    );
+
+    const buyer = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(buyer.wallet.publicKey, refundPlanId);
+    await program.methods
+      .subscribe(refundPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: buyer.wallet.publicKey,
+        rentPayer: buyer.wallet.publicKey,
+        fundingAuthority: buyer.wallet.publicKey,
+        subscriberTokenAccount: buyer.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer.wallet])
+      .rpc();
+
+    // A subscription to another creator's plan with the same id can't be
+    // refunded out of this plan's payout account
+    const impostor = await createFundedSubscriber();
+    const impostorPlanPda = findPlanPda(impostor.wallet.publicKey, refundPlanId);
+    await program.methods
+      .createSubscriptionPlan(refundPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: impostorPlanPda,
+        creator: impostor.wallet.publicKey,
+        creatorRegistry: findCreatorRegistryPda(impostor.wallet.publicKey),
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: impostor.tokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([impostor.wallet])
+      .rpc();
+    const accomplice = await createFundedSubscriber();
+    const accompliceSubscription = findSubscriptionPda(accomplice.wallet.publicKey, refundPlanId);
+    await program.methods
+      .subscribe(refundPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: impostorPlanPda,
+        subscription: accompliceSubscription,
+        subscriber: accomplice.wallet.publicKey,
+        rentPayer: accomplice.wallet.publicKey,
+        fundingAuthority: accomplice.wallet.publicKey,
+        subscriberTokenAccount: accomplice.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: impostor.tokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([accomplice.wallet])
+      .rpc();
+    try {
+      await program.methods
+        .selfRefund(refundPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: accompliceSubscription,
+          subscriber: accomplice.wallet.publicKey,
+          subscriberTokenAccount: accomplice.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([accomplice.wallet])
+        .rpc();
+      assert.fail("Should have rejected a subscription to another creator's plan");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidCreator");
+    }
+
+    const balanceBefore = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+
+    await program.methods
+      .selfRefund(refundPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: buyer.wallet.publicKey,
+        subscriberTokenAccount: buyer.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyer.wallet])
+      .rpc();
+
+    const balanceAfter = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+
+    assert.equal(balanceAfter - balanceBefore, BigInt(price.toString()));
+    assert.equal(subscription.isActive, false);
+    assert.equal(plan.currentSubscribers, 0);
+  });
+
+  it("Sets a subscription reference", async () => {
+    const subscriptionPda = findSubscriptionPda(subscriber.publicKey, planId);
+    const reference = Array.from(Buffer.from("PO-2024-0042".padEnd(32, "\0")));
+
+    await program.methods
+      .setReference(planId, reference)
+      .accounts({
+        subscription: subscriptionPda,
+        subscriber: subscriber.publicKey,
+      })
+      .signers([subscriber])
+      .rpc();
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.deepEqual(subscription.reference, reference);
+  });
+
+  it("Rejects inactive subscribers in assert_active_subscriber", async () => {
+    // The cancelled subscription on plan 1 must not pass the check
+    try {
+      await program.methods
+        .assertActiveSubscriber(planId)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, planId),
+          subscription: findSubscriptionPda(subscriber.publicKey, planId),
+          subscriber: subscriber.publicKey,
+        })
+        .rpc();
+
+      assert.fail("Cancelled subscriptions should not be active");
+    } catch (error) {
+      assert.include(error.toString(), "SubscriptionInactive");
+    }
+  });
+
+  it("Charges upfront cycles at subscribe", async () => {
+    const prepayPlanId = new anchor.BN(5);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, prepayPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(prepayPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .setPrepayCyclesOnJoin(prepayPlanId, 3)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    const buyer = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(buyer.wallet.publicKey, prepayPlanId);
+    const balanceBefore = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+
+    await program.methods
+      .subscribe(prepayPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: buyer.wallet.publicKey,
+        rentPayer: buyer.wallet.publicKey,
+        fundingAuthority: buyer.wallet.publicKey,
+        subscriberTokenAccount: buyer.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer.wallet])
+      .rpc();
+
+    const balanceAfter = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+
+    assert.equal(balanceBefore - balanceAfter, BigInt(price.muln(3).toString()));
+    assert.equal(subscription.totalPayments.toNumber(), 3);
+    assert.ok(subscription.totalPaidAmount.eq(price.muln(3)));
+    assert.ok(
+      subscription.nextPayment.sub(subscription.lastPayment).eq(intervalSeconds.muln(3))
+    );
+  });
+
+  it("Rejects a duplicate subscribe without charging twice", async () => {
+    const prepayPlanId = new anchor.BN(5);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, prepayPlanId);
+    const buyer = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(buyer.wallet.publicKey, prepayPlanId);
+
+    const subscribeAccounts = {
+      subscriptionPlan: subscriptionPlanPda,
+      subscription: subscriptionPda,
+      subscriber: buyer.wallet.publicKey,
+      rentPayer: buyer.wallet.publicKey,
+      fundingAuthority: buyer.wallet.publicKey,
+      subscriberTokenAccount: buyer.tokenAccount,
+      paymentMint: tokenMint,
+      creatorTokenAccount: creatorTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+
+    await program.methods
+      .subscribe(prepayPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts(subscribeAccounts)
+      .signers([buyer.wallet])
+      .rpc();
+
+    const balanceAfterFirst = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+
+    try {
+      await program.methods
+        .subscribe(prepayPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts(subscribeAccounts)
+        .signers([buyer.wallet])
+        .rpc();
+
+      assert.fail("Duplicate subscribe should fail");
+    } catch (error) {
+      assert.include(error.logs.join("\n"), "already in use");
+    }
+
+    const balanceAfterRetry = (await getAccount(provider.connection, buyer.tokenAccount)).amount;
+    assert.equal(balanceAfterRetry, balanceAfterFirst);
+  });
+
+  it("Rejects a token program that does not own the payment mint", async () => {
+    const prepayPlanId = new anchor.BN(5);
+    const buyer = await createFundedSubscriber();
+
+    try {
+      await program.methods
+        .subscribe(prepayPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, prepayPlanId),
+          subscription: findSubscriptionPda(buyer.wallet.publicKey, prepayPlanId),
+          subscriber: buyer.wallet.publicKey,
+          rentPayer: buyer.wallet.publicKey,
+          fundingAuthority: buyer.wallet.publicKey,
+          subscriberTokenAccount: buyer.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer.wallet])
+        .rpc();
+
+      assert.fail("Classic mint paired with Token-2022 should be rejected");
+    } catch (error) {
+      assert.include(error.toString(), "WrongTokenProgram");
+    }
+  });
+
+  it("Reserves a seat and confirms the subscription", async () => {
+    const reservePlanId = new anchor.BN(6);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, reservePlanId);
+
+    await program.methods
+      .createSubscriptionPlan(reservePlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .setReservationWindow(reservePlanId, new anchor.BN(600))
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    const buyer = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(buyer.wallet.publicKey, reservePlanId);
+
+    await program.methods
+      .subscribeReserve(reservePlanId, emptyReference, new anchor.BN(0), null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: buyer.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer.wallet])
+      .rpc();
+
+    let subscription = await program.account.subscription.fetch(subscriptionPda);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(subscription.isActive, false);
+    assert.deepEqual(subscription.status, { pending: {} });
+    assert.ok(subscription.reservedUntil.gtn(0));
+    assert.equal(plan.currentSubscribers, 1);
+
+    await program.methods
+      .confirmSubscription(reservePlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: buyer.wallet.publicKey,
+        subscriberTokenAccount: buyer.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyer.wallet])
+      .rpc();
+
+    subscription = await program.account.subscription.fetch(subscriptionPda);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(subscription.isActive, true);
+    assert.deepEqual(subscription.status, { active: {} });
+    assert.equal(subscription.reservedUntil.toNumber(), 0);
+    assert.equal(plan.currentSubscribers, 1);
+  });
+
+  it("Rejects a treasury sweep from a non-admin", async () => {
+    const BPF_LOADER_UPGRADEABLE = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+    const [programDataPda] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE
+    );
+    const [treasuryPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), tokenMint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .initializeProgramConfig(provider.publicKey)
+      .accounts({
+        programConfig: programConfigPda,
+        authority: provider.publicKey,
+        program: program.programId,
+        programData: programDataPda,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .initializeTreasury()
+      .accounts({
+        programConfig: programConfigPda,
+        treasury: treasuryPda,
+        mint: tokenMint,
+        admin: provider.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await mintTo(provider.connection, creator, tokenMint, treasuryPda, creator, 5000);
+
+    const intruder = await createFundedSubscriber(0);
+    try {
+      await program.methods
+        .sweepTreasury(new anchor.BN(5000))
+        .accounts({
+          programConfig: programConfigPda,
+          treasury: treasuryPda,
+          mint: tokenMint,
+          destination: intruder.tokenAccount,
+          admin: intruder.wallet.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([intruder.wallet])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "InvalidAdmin");
+    }
+
+    const adminDestination = await createAccount(
+      provider.connection,
+      creator,
+      tokenMint,
+      provider.publicKey,
+      Keypair.generate()
+    );
+    await program.methods
+      .sweepTreasury(new anchor.BN(5000))
+      .accounts({
+        programConfig: programConfigPda,
+        treasury: treasuryPda,
+        mint: tokenMint,
+        destination: adminDestination,
+        admin: provider.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const treasury = await getAccount(provider.connection, treasuryPda);
+    assert.equal(Number(treasury.amount), 0);
+  });
+
+  it("Rejects shortening the interval unless tightening is allowed", async () => {
+    const tightenPlanId = new anchor.BN(7);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, tightenPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(
+        tightenPlanId,
+        price,
+        intervalSeconds,
+        maxSubscribers,
+        metadataUri,
+        metadataHash,
+        currencySymbol
+      )
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, tightenPlanId);
+    await program.methods
+      .subscribe(tightenPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+    const before = await program.account.subscription.fetch(subscriptionPda);
+
+    const shorten = () =>
+      program.methods
+        .updateSubscriptionPlan(tightenPlanId, null, new anchor.BN(604800), null, null, null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          authority: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await shorten();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "IntervalChangeNotAllowed");
+    }
+
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const after = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(plan.intervalSeconds.toString(), intervalSeconds.toString());
+    assert.equal(after.nextPayment.toString(), before.nextPayment.toString());
+
+    await program.methods
+      .setAllowIntervalTightening(tightenPlanId, true)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    await shorten();
+
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.intervalSeconds.toNumber(), 604800);
+  });
+
+  it("Keeps the joined interval until the creator migrates the subscription", async () => {
+    const tightenPlanId = new anchor.BN(7);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, tightenPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, tightenPlanId);
+
+    // Plan 7 was tightened to 7 days; join, then lengthen it back to 30 days
+    await program.methods
+      .subscribe(tightenPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+    await program.methods
+      .updateSubscriptionPlan(tightenPlanId, null, intervalSeconds, null, null, null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    let subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.intervalSeconds.toNumber(), 604800);
+
+    const migrate = (signer: Keypair) =>
+      program.methods
+        .migrateSubscriptionInterval(tightenPlanId)
+        .accounts({
+          subscriptionPlan: findPlanPda(signer.publicKey, tightenPlanId),
+          subscription: subscriptionPda,
+          creator: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    try {
+      await migrate(member.wallet);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.notEqual(error.message, "Should have failed");
+    }
+
+    await migrate(creator);
+    subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.intervalSeconds.toString(), intervalSeconds.toString());
+  });
+
+  it("Follows a deactivated plan to its successor", async () => {
+    const oldPlanId = new anchor.BN(8);
+    const successorPlanId = new anchor.BN(9);
+    const oldPlanPda = findPlanPda(creator.publicKey, oldPlanId);
+    const successorPlanPda = findPlanPda(creator.publicKey, successorPlanId);
+
+    for (const [id, pda] of [[oldPlanId, oldPlanPda], [successorPlanId, successorPlanPda]] as const) {
+      await program.methods
+        .createSubscriptionPlan(id, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+        .accounts({
+          subscriptionPlan: pda,
+          creator: creator.publicKey,
+          creatorRegistry: creatorRegistryPda,
+          programConfig: programConfigPda,
+          feeDestination: provider.publicKey,
+          paymentMint: tokenMint,
+          payoutTokenAccount: creatorTokenAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+    }
+
+    const member = await createFundedSubscriber();
+    const oldSubscriptionPda = findSubscriptionPda(member.wallet.publicKey, oldPlanId);
+    const newSubscriptionPda = findSubscriptionPda(member.wallet.publicKey, successorPlanId);
+    await program.methods
+      .subscribe(oldPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: oldPlanPda,
+        subscription: oldSubscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    await program.methods
+      .deactivatePlan(oldPlanId, successorPlanId)
+      .accounts({
+        subscriptionPlan: oldPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    // Following charges the successor's setup fee like a fresh subscribe
+    const setupFee = new anchor.BN(250000);
+    await program.methods
+      .setSetupFee(successorPlanId, setupFee)
+      .accounts({
+        subscriptionPlan: successorPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    const balanceBefore = (await getAccount(provider.connection, member.tokenAccount)).amount;
+
+    await program.methods
+      .followSuccessor(oldPlanId)
+      .accounts({
+        subscriptionPlan: oldPlanPda,
+        subscription: oldSubscriptionPda,
+        successorPlan: successorPlanPda,
+        newSubscription: newSubscriptionPda,
+        subscriber: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const oldSubscription = await program.account.subscription.fetch(oldSubscriptionPda);
+    const newSubscription = await program.account.subscription.fetch(newSubscriptionPda);
+    const oldPlan = await program.account.subscriptionPlan.fetch(oldPlanPda);
+    const successorPlan = await program.account.subscriptionPlan.fetch(successorPlanPda);
+    assert.equal(oldSubscription.isActive, false);
+    assert.equal(newSubscription.isActive, true);
+    assert.equal(newSubscription.planId.toNumber(), 9);
+    assert.isTrue(newSubscription.setupFeePaid);
+    const balanceAfter = (await getAccount(provider.connection, member.tokenAccount)).amount;
+    assert.equal((balanceBefore - balanceAfter).toString(), price.add(setupFee).toString());
+    assert.equal(oldPlan.currentSubscribers, 0);
+    assert.equal(successorPlan.currentSubscribers, 1);
+  });
+
+  it("Caps prepaid vault deposits at max_prepaid_cycles", async () => {
+    const vaultPlanId = new anchor.BN(10);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, vaultPlanId);
+    const [prepaidVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("prepaid_vault"), subscriptionPlanPda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createSubscriptionPlan(vaultPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .initializePrepaidVault(vaultPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        prepaidVault: prepaidVaultPda,
+        paymentMint: tokenMint,
+        creator: creator.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setMaxPrepaidCycles(vaultPlanId, 2)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, vaultPlanId);
+    await program.methods
+      .subscribe(vaultPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const deposit = (cycles: number) =>
+      program.methods
+        .depositToVault(vaultPlanId, cycles)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          prepaidVault: prepaidVaultPda,
+          subscriber: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([member.wallet])
+        .rpc();
+
+    await deposit(2);
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.prepaidBalance.toString(), price.muln(2).toString());
+
+    try {
+      await deposit(1);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "PrepayLimitExceeded");
+    }
+
+    const vault = await getAccount(provider.connection, prepaidVaultPda);
+    assert.equal(vault.amount.toString(), price.muln(2).toString());
+  });
+
+  it("Refunds the prepaid balance on cancel", async () => {
+    const vaultPlanId = new anchor.BN(10);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, vaultPlanId);
+    const [prepaidVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("prepaid_vault"), subscriptionPlanPda.toBuffer()],
+      program.programId
+    );
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, vaultPlanId);
+
+    await program.methods
+      .subscribe(vaultPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+    await program.methods
+      .depositToVault(vaultPlanId, 1)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        prepaidVault: prepaidVaultPda,
+        subscriber: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const before = await getAccount(provider.connection, member.tokenAccount);
+    await program.methods
+      .cancelAndRefundPrepaid(vaultPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        prepaidVault: prepaidVaultPda,
+        subscriber: member.wallet.publicKey,
+        destination: member.tokenAccount,
+        paymentMint: tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const after = await getAccount(provider.connection, member.tokenAccount);
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal((after.amount - before.amount).toString(), price.toString());
+    assert.equal(subscription.isActive, false);
+    assert.equal(subscription.prepaidBalance.toNumber(), 0);
+  });
+
+  it("Subscribes a PDA via CPI from another program", async () => {
+    const mockCaller = anchor.workspace.MockCaller as Program<MockCaller>;
+    const cpiPlanId = new anchor.BN(11);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, cpiPlanId);
+    const authority = Keypair.generate();
+    const [memberPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("member"), authority.publicKey.toBuffer()],
+      mockCaller.programId
+    );
+    const subscriptionPda = findSubscriptionPda(memberPda, cpiPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(cpiPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    // The member PDA pays subscription rent and owns the charged token account
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(memberPda, LAMPORTS_PER_SOL)
+    );
+    const memberTokenAccount = await createAccount(
+      provider.connection,
+      creator,
+      tokenMint,
+      memberPda,
+      Keypair.generate()
+    );
+    await mintTo(provider.connection, creator, tokenMint, memberTokenAccount, creator, 10000000);
+
+    await mockCaller.methods
+      .subscribeMember(cpiPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        member: memberPda,
+        authority: authority.publicKey,
+        memberTokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        circulumProgram: program.programId,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.subscriber.equals(memberPda));
+    assert.equal(subscription.isActive, true);
+  });
+
+  it("Rejects subscribing from an empty token account with InsufficientFunds", async () => {
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, new anchor.BN(11));
+    const broke = await createFundedSubscriber(0);
+
+    try {
+      await program.methods
+        .subscribe(new anchor.BN(11), emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(broke.wallet.publicKey, new anchor.BN(11)),
+          subscriber: broke.wallet.publicKey,
+          rentPayer: broke.wallet.publicKey,
+          fundingAuthority: broke.wallet.publicKey,
+          subscriberTokenAccount: broke.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([broke.wallet])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "InsufficientFunds");
+    }
+  });
+
+  it("Refunds the unused part of the cycle on a prorated cancel", async () => {
+    const proratedPlanId = new anchor.BN(12);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, proratedPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(proratedPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setProratedCancelRefund(proratedPlanId, true)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    await approve(
+      provider.connection,
+      creator,
+      creatorTokenAccount,
+      subscriptionPlanPda,
+      creator,
+      price.toNumber()
+    );
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, proratedPlanId);
+    await program.methods
+      .subscribe(proratedPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const before = await getAccount(provider.connection, member.tokenAccount);
+    await program.methods
+      .cancelWithProratedRefund(proratedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    // Cancelled seconds after joining: almost the whole cycle comes back
+    const after = await getAccount(provider.connection, member.tokenAccount);
+    const refunded = Number(after.amount - before.amount);
+    assert.isAbove(refunded, price.toNumber() * 0.99);
+    assert.isAtMost(refunded, price.toNumber());
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.isActive, false);
+  });
+
+  it("Bulk cancels a plan's subscribers and skips inactive ones", async () => {
+    const bulkPlanId = new anchor.BN(13);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, bulkPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(bulkPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const subscriptionPdas: PublicKey[] = [];
+    for (let i = 0; i < 2; i++) {
+      const member = await createFundedSubscriber();
+      const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, bulkPlanId);
+      await program.methods
+        .subscribe(bulkPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+      subscriptionPdas.push(subscriptionPda);
+    }
+
+    const cancelAll = () =>
+      program.methods
+        .cancelAllSubscribers(bulkPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+        })
+        .remainingAccounts(
+          subscriptionPdas.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+        )
+        .signers([creator])
+        .rpc();
+
+    await cancelAll();
+    // Retrying the same batch is a no-op
+    await cancelAll();
+
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.currentSubscribers, 0);
+    for (const pda of subscriptionPdas) {
+      const subscription = await program.account.subscription.fetch(pda);
+      assert.deepEqual(subscription.status, { cancelled: {} });
+    }
+  });
+
+  it("Extends the outage window of a paused plan", async () => {
+    const bulkPlanId = new anchor.BN(13);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, bulkPlanId);
+    const extend = (seconds: number) =>
+      program.methods
+        .extendPause(bulkPlanId, new anchor.BN(seconds))
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await extend(3600);
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "PlanNotPaused");
+    }
+
+    await program.methods
+      .pausePlan(bulkPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    await extend(3600);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const firstEnd = plan.maintenanceEnd.toNumber();
+    assert.equal(firstEnd - plan.maintenanceStart.toNumber(), 3600);
+
+    await extend(600);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.maintenanceEnd.toNumber(), firstEnd + 600);
+
+    await program.methods
+      .unpausePlan(bulkPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+  });
+
+  it("Reports the next charge via effective_next_price", async () => {
+    const pricedPlanId = new anchor.BN(12);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, pricedPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, pricedPlanId);
+
+    await program.methods
+      .subscribe(pricedPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const nextPrice = () =>
+      program.methods
+        .effectiveNextPrice(pricedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+        })
+        .view();
+
+    assert.equal((await nextPrice()).toString(), price.toString());
+
+    const raised = price.muln(2);
+    await program.methods
+      .updateSubscriptionPlan(pricedPlanId, raised, null, null, null, null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    assert.equal((await nextPrice()).toString(), raised.toString());
+  });
+
+  it("Lets a manager pause but not change financial terms or payout", async () => {
+    const managedPlanId = new anchor.BN(14);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, managedPlanId);
+    const manager = Keypair.generate();
+
+    await program.methods
+      .createSubscriptionPlan(managedPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setManager(managedPlanId, manager.publicKey)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .pausePlan(managedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        authority: manager.publicKey,
+      })
+      .signers([manager])
+      .rpc();
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.isPaused, true);
+
+    await program.methods
+      .updateSubscriptionPlan(managedPlanId, null, null, null, "https://example.com/managed.json", null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: manager.publicKey,
+      })
+      .signers([manager])
+      .rpc();
+
+    try {
+      await program.methods
+        .updateSubscriptionPlan(managedPlanId, price.muln(10), null, null, null, null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          authority: manager.publicKey,
+        })
+        .signers([manager])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.message, "Unauthorized");
+    }
+
+    try {
+      await program.methods
+        .setPayoutAccount(managedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: manager.publicKey,
+          newPayoutTokenAccount: creatorTokenAccount,
+        })
+        .signers([manager])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.notEqual(error.message, "Should have failed");
+    }
+
+    const outsider = Keypair.generate();
+    try {
+      await program.methods
+        .pausePlan(managedPlanId)
+        .accounts({ subscriptionPlan: subscriptionPlanPda, authority: outsider.publicKey })
+        .signers([outsider])
+        .rpc();
+      assert.fail("Should have failed");
+    } catch (error) {
+      assert.include(error.toString(), "NotCreatorOrManager");
+    }
+
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.price.toString(), price.toString());
+    assert.equal(plan.metadataUri, "https://example.com/managed.json");
+  });
+
+  it("Enforces the configured minimum price exactly at the boundary", async () => {
+    const floorPlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, floorPlanId);
+    const minPrice = new anchor.BN(1_000_000);
+
+    await program.methods
+      .setMinPrice(minPrice)
+      .accounts({
+        programConfig: programConfigPda,
+        admin: provider.publicKey,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .createSubscriptionPlan(floorPlanId, minPrice.subn(1), intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+          creatorRegistry: creatorRegistryPda,
+          programConfig: programConfigPda,
+          feeDestination: provider.publicKey,
+          paymentMint: tokenMint,
+          payoutTokenAccount: creatorTokenAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Expected PriceBelowMinimum");
+    } catch (error) {
+      assert.include(error.toString(), "PriceBelowMinimum");
+    }
+
+    await program.methods
+      .createSubscriptionPlan(floorPlanId, minPrice, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.price.toString(), minPrice.toString());
+
+    try {
+      await program.methods
+        .updateSubscriptionPlan(floorPlanId, minPrice.subn(1), null, null, null, null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          authority: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Expected PriceBelowMinimum");
+    } catch (error) {
+      assert.include(error.toString(), "PriceBelowMinimum");
+    }
+
+    await program.methods
+      .setMinPrice(new anchor.BN(0))
+      .accounts({
+        programConfig: programConfigPda,
+        admin: provider.publicKey,
+      })
+      .rpc();
+  });
+
+  it("Reconciles current_subscribers from the passed subscriptions", async () => {
+    const reconcilePlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, reconcilePlanId);
+    const members = [Keypair.generate(), Keypair.generate()];
+
+    for (const member of members) {
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(member.publicKey, LAMPORTS_PER_SOL)
+      );
+      await program.methods
+        .subscribe(reconcilePlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(member.publicKey, reconcilePlanId),
+          subscriber: member.publicKey,
+          rentPayer: member.publicKey,
+          fundingAuthority: member.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member])
+        .rpc();
+    }
+    const subscriptionAccounts = members.map((member) => ({
+      pubkey: findSubscriptionPda(member.publicKey, reconcilePlanId),
+      isWritable: false,
+      isSigner: false,
+    }));
+
+    try {
+      await program.methods
+        .reconcileSubscriberCount(reconcilePlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+        })
+        .remainingAccounts([subscriptionAccounts[0], subscriptionAccounts[0]])
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have rejected a duplicated subscription account");
+    } catch (error) {
+      assert.include(error.toString(), "DuplicateSubscriptionAccount");
+    }
+
+    await program.methods
+      .reconcileSubscriberCount(reconcilePlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .remainingAccounts(subscriptionAccounts)
+      .signers([creator])
+      .rpc();
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.currentSubscribers, 2);
+  });
+
+  it("Subscribes to and cancels a bundle of plans together", async () => {
+    const memberPlanIds = [new anchor.BN(16), new anchor.BN(17)];
+    const bundleId = new anchor.BN(1);
+    const bundlePrice = new anchor.BN(1500000);
+    const [bundlePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bundle"), creator.publicKey.toBuffer(), bundleId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const memberPlanPdas = memberPlanIds.map((id) => findPlanPda(creator.publicKey, id));
+
+    for (const id of memberPlanIds) {
+      await program.methods
+        .createSubscriptionPlan(id, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, id),
+          creator: creator.publicKey,
+          creatorRegistry: creatorRegistryPda,
+          programConfig: programConfigPda,
+          feeDestination: provider.publicKey,
+          paymentMint: tokenMint,
+          payoutTokenAccount: creatorTokenAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+    }
+
+    await program.methods
+      .createBundle(bundleId, memberPlanIds, bundlePrice)
+      .accounts({
+        bundle: bundlePda,
+        creator: creator.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(
+        memberPlanPdas.map((pubkey) => ({ pubkey, isWritable: false, isSigner: false }))
+      )
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const memberAccounts = memberPlanIds.flatMap((id, i) => [
+      { pubkey: memberPlanPdas[i], isWritable: true, isSigner: false },
+      { pubkey: findSubscriptionPda(member.wallet.publicKey, id), isWritable: true, isSigner: false },
+    ]);
+    // Dust sent to a member subscription's address mustn't block joining
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(findSubscriptionPda(member.wallet.publicKey, memberPlanIds[0]), 1000)
+    );
+    const creatorBalanceBefore = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+
+    await program.methods
+      .subscribeBundle(bundleId)
+      .accounts({
+        bundle: bundlePda,
+        subscriber: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(memberAccounts)
+      .signers([member.wallet])
+      .rpc();
+
+    const creatorBalanceAfter = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+    assert.equal((creatorBalanceAfter - creatorBalanceBefore).toString(), bundlePrice.toString());
+
+    const subscriptions = await Promise.all(
+      memberPlanIds.map((id) =>
+        program.account.subscription.fetch(findSubscriptionPda(member.wallet.publicKey, id))
+      )
+    );
+    for (const subscription of subscriptions) {
+      assert.equal(subscription.isActive, true);
+      assert.equal(subscription.bundleId.toString(), bundleId.toString());
+    }
+    assert.equal(
+      subscriptions[0].nextPayment.toString(),
+      subscriptions[1].nextPayment.toString()
+    );
+
+    try {
+      await program.methods
+        .cancelSubscription(memberPlanIds[0])
+        .accounts({
+          subscriptionPlan: memberPlanPdas[0],
+          subscription: findSubscriptionPda(member.wallet.publicKey, memberPlanIds[0]),
+          subscriber: member.wallet.publicKey,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected cancelling a single bundle member");
+    } catch (error) {
+      assert.include(error.toString(), "BundleMemberSubscription");
+    }
+
+    await program.methods
+      .cancelBundle(bundleId)
+      .accounts({
+        bundle: bundlePda,
+        subscriber: member.wallet.publicKey,
+      })
+      .remainingAccounts(memberAccounts)
+      .signers([member.wallet])
+      .rpc();
+
+    for (const [i, id] of memberPlanIds.entries()) {
+      const subscription = await program.account.subscription.fetch(
+        findSubscriptionPda(member.wallet.publicKey, id)
+      );
+      assert.equal(subscription.isActive, false);
+      const plan = await program.account.subscriptionPlan.fetch(memberPlanPdas[i]);
+      assert.equal(plan.currentSubscribers, 0);
+    }
+
+    await program.methods
+      .deactivateBundle(bundleId)
+      .accounts({ bundle: bundlePda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    assert.equal((await program.account.bundle.fetch(bundlePda)).isActive, false);
+
+    const latecomer = await createFundedSubscriber();
+    try {
+      await program.methods
+        .subscribeBundle(bundleId)
+        .accounts({
+          bundle: bundlePda,
+          subscriber: latecomer.wallet.publicKey,
+          subscriberTokenAccount: latecomer.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          memberPlanIds.flatMap((id, i) => [
+            { pubkey: memberPlanPdas[i], isWritable: true, isSigner: false },
+            { pubkey: findSubscriptionPda(latecomer.wallet.publicKey, id), isWritable: true, isSigner: false },
+          ])
+        )
+        .signers([latecomer.wallet])
+        .rpc();
+      assert.fail("Should have rejected joining a deactivated bundle");
+    } catch (error) {
+      assert.include(error.toString(), "BundleInactive");
+    }
+  });
+
+  it("Emits PaymentUpcoming with the plan's reminder lead on subscribe", async () => {
+    const reminderPlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, reminderPlanId);
+    const reminderLead = new anchor.BN(86400);
+
+    await program.methods
+      .setReminderLeadSeconds(reminderPlanId, reminderLead)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, reminderPlanId);
+    const signature = await program.methods
+      .subscribe(reminderPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const upcoming = [...parser.parseLogs(tx.meta.logMessages)].find(
+      (e) => e.name === "paymentUpcoming"
+    );
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(upcoming, "PaymentUpcoming should be emitted");
+    assert.ok(upcoming.data.nextPayment.eq(subscription.nextPayment));
+    assert.ok(upcoming.data.remindAt.eq(subscription.nextPayment.sub(reminderLead)));
+  });
+
+  it("Freezes and unfreezes a single subscription", async () => {
+    const holdPlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, holdPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, holdPlanId);
+
+    await program.methods
+      .subscribe(holdPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    try {
+      await program.methods
+        .freezeSubscription(holdPlanId, member.wallet.publicKey)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          programConfig: programConfigPda,
+          authority: member.wallet.publicKey,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected a freeze by the subscriber");
+    } catch (error) {
+      assert.include(error.toString(), "Unauthorized");
+    }
+
+    await program.methods
+      .freezeSubscription(holdPlanId, member.wallet.publicKey)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    let subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.isFrozen, true);
+    assert.equal(subscription.isActive, true);
+
+    try {
+      await program.methods
+        .selfRefund(holdPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected a refund while frozen");
+    } catch (error) {
+      assert.include(error.toString(), "SubscriptionFrozen");
+    }
+
+    await program.methods
+      .unfreezeSubscription(holdPlanId, member.wallet.publicKey)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.isFrozen, false);
+  });
+
+  it("Auto-expands a full plan up to its hard cap", async () => {
+    const growingPlanId = new anchor.BN(18);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, growingPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(growingPlanId, price, intervalSeconds, 1, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setAutoExpand(growingPlanId, 5, 2)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const subscribeMember = async () => {
+      const member = await createFundedSubscriber();
+      await program.methods
+        .subscribe(growingPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(member.wallet.publicKey, growingPlanId),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+    };
+
+    await subscribeMember();
+    await subscribeMember();
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.maxSubscribers, 2);
+    assert.equal(plan.currentSubscribers, 2);
+
+    try {
+      await subscribeMember();
+      assert.fail("Should have stopped expanding at the hard cap");
+    } catch (error) {
+      assert.include(error.toString(), "PlanFull");
+    }
+  });
+
+  it("Returns a PaymentReceipt from subscribe matching the account", async () => {
+    const receiptPlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, receiptPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, receiptPlanId);
+
+    const signature = await program.methods
+      .subscribe(receiptPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const [returnData] = tx.meta.returnData.data;
+    const receipt = program.coder.types.decode("PaymentReceipt", Buffer.from(returnData, "base64"));
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+
+    assert.ok(receipt.nextPayment.eq(subscription.nextPayment));
+    assert.ok(receipt.totalPayments.eq(subscription.totalPayments));
+    assert.ok(receipt.chargedAmount.eq(plan.price));
+    assert.ok(receipt.planId.eq(receiptPlanId));
+    assert.ok(receipt.fee.eqn(0));
+    assert.ok(receipt.netToCreator.eq(receipt.chargedAmount));
+    assert.ok(receipt.timestamp.eq(subscription.createdAt));
+  });
+
+  it("Collects the plan creation fee only when configured and not exempt", async () => {
+    const feeDestination = Keypair.generate().publicKey;
+    const fee = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const createPlan = (id: anchor.BN) =>
+      program.methods
+        .createSubscriptionPlan(id, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, id),
+          creator: creator.publicKey,
+          creatorRegistry: creatorRegistryPda,
+          programConfig: programConfigPda,
+          feeDestination,
+          paymentMint: tokenMint,
+          payoutTokenAccount: creatorTokenAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+    // Fee off: nothing reaches the destination
+    await createPlan(new anchor.BN(19));
+    assert.equal(await provider.connection.getBalance(feeDestination), 0);
+
+    await program.methods
+      .setPlanCreationFee(fee, feeDestination)
+      .accounts({
+        programConfig: programConfigPda,
+        admin: provider.publicKey,
+      })
+      .rpc();
+
+    // Fee on: the destination must match the config
+    try {
+      await program.methods
+        .createSubscriptionPlan(new anchor.BN(20), price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, new anchor.BN(20)),
+          creator: creator.publicKey,
+          creatorRegistry: creatorRegistryPda,
+          programConfig: programConfigPda,
+          feeDestination: provider.publicKey,
+          paymentMint: tokenMint,
+          payoutTokenAccount: creatorTokenAccount,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      assert.fail("Should have rejected the wrong fee destination");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidFeeDestination");
+    }
+
+    await createPlan(new anchor.BN(20));
+    assert.equal(await provider.connection.getBalance(feeDestination), fee.toNumber());
+
+    // Exempt creators keep creating plans for free
+    const setFeeExemption = (exempt: boolean) =>
+      program.methods
+        .setFeeExemption(creator.publicKey, exempt)
+        .accounts({ programConfig: programConfigPda, admin: provider.publicKey })
+        .rpc({ commitment: "confirmed" });
+    const signature = await setFeeExemption(true);
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const changed = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages),
+    ].find((e) => e.name === "feeExemptionChanged");
+    assert.ok(changed.data.creator.equals(creator.publicKey));
+    assert.isTrue(changed.data.exempt);
+
+    await createPlan(new anchor.BN(27));
+    assert.equal(await provider.connection.getBalance(feeDestination), fee.toNumber());
+    await setFeeExemption(false);
+
+    await program.methods
+      .setPlanCreationFee(new anchor.BN(0), provider.publicKey)
+      .accounts({
+        programConfig: programConfigPda,
+        admin: provider.publicKey,
+      })
+      .rpc();
+  });
+
+  it("Grants a free comp subscription and revokes it", async () => {
+    const compPlanId = new anchor.BN(15);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, compPlanId);
+    const beneficiary = Keypair.generate().publicKey;
+    const subscriptionPda = findSubscriptionPda(beneficiary, compPlanId);
+    const planBefore = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+
+    await program.methods
+      .grantSubscription(compPlanId, beneficiary)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.isComp, true);
+    assert.equal(subscription.isActive, true);
+    assert.ok(subscription.totalPaidAmount.eqn(0));
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.currentSubscribers, planBefore.currentSubscribers + 1);
+    assert.equal(plan.compSubscribers, planBefore.compSubscribers + 1);
+
+    // Another creator's plan with the same id can't revoke this comp
+    const rival = await createFundedSubscriber();
+    const rivalPlanPda = findPlanPda(rival.wallet.publicKey, compPlanId);
+    await program.methods
+      .createSubscriptionPlan(compPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: rivalPlanPda,
+        creator: rival.wallet.publicKey,
+        creatorRegistry: findCreatorRegistryPda(rival.wallet.publicKey),
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: rival.tokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([rival.wallet])
+      .rpc();
+    try {
+      await program.methods
+        .revokeComp(compPlanId, beneficiary)
+        .accounts({
+          subscriptionPlan: rivalPlanPda,
+          subscription: subscriptionPda,
+          creator: rival.wallet.publicKey,
+        })
+        .signers([rival.wallet])
+        .rpc();
+      assert.fail("Should have rejected revoking another creator's comp");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidCreator");
+    }
+
+    await program.methods
+      .revokeComp(compPlanId, beneficiary)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.currentSubscribers, planBefore.currentSubscribers);
+    assert.equal(plan.compSubscribers, planBefore.compSubscribers);
+  });
+
+  it("Checks plan invariants against the passed subscriptions", async () => {
+    const auditedPlanId = new anchor.BN(21);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, auditedPlanId);
+
+    await program.methods
+      .createSubscriptionPlan(auditedPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, auditedPlanId);
+    await program.methods
+      .subscribe(auditedPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const checkInvariants = (subscriptions: PublicKey[]) =>
+      program.methods
+        .checkPlanInvariants(auditedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          payoutTokenAccount: creatorTokenAccount,
+        })
+        .remainingAccounts(
+          subscriptions.map((pubkey) => ({ pubkey, isWritable: false, isSigner: false }))
+        )
+        .rpc();
+
+    await checkInvariants([subscriptionPda]);
+
+    try {
+      await checkInvariants([]);
+      assert.fail("Should have reported the missing subscription");
+    } catch (error) {
+      assert.include(error.toString(), "InvariantSubscriberCount");
+    }
+  });
+
+  it("Counts a subscriber's due subscriptions via return data", async () => {
+    const member = await createFundedSubscriber();
+    const planIds = [new anchor.BN(19), new anchor.BN(20)];
+
+    for (const id of planIds) {
+      await program.methods
+        .subscribe(id, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, id),
+          subscription: findSubscriptionPda(member.wallet.publicKey, id),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+    }
+
+    // Both were just paid upfront, so neither is due yet
+    const due = await program.methods
+      .dueSubscriptionsCount()
+      .accounts({ subscriber: member.wallet.publicKey })
+      .remainingAccounts(
+        planIds.flatMap((id) => [
+          { pubkey: findPlanPda(creator.publicKey, id), isWritable: false, isSigner: false },
+          { pubkey: findSubscriptionPda(member.wallet.publicKey, id), isWritable: false, isSigner: false },
+        ])
+      )
+      .view();
+    assert.equal(due, 0);
+  });
+
+  it("Charges the setup fee on the first payment only", async () => {
+    const setupPlanId = new anchor.BN(22);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, setupPlanId);
+    const setupFee = new anchor.BN(250000);
+
+    await program.methods
+      .createSubscriptionPlan(setupPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setSetupFee(setupPlanId, setupFee)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, setupPlanId);
+    const balanceBefore = (await getAccount(provider.connection, member.tokenAccount)).amount;
+    const signature = await program.methods
+      .subscribe(setupPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc({ commitment: "confirmed" });
+    const balanceAfter = (await getAccount(provider.connection, member.tokenAccount)).amount;
+
+    assert.equal((balanceBefore - balanceAfter).toString(), price.add(setupFee).toString());
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.setupFeePaid, true);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const created = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages),
+    ].find((e) => e.name === "subscriptionCreated");
+    assert.ok(created.data.setupFee.eq(setupFee));
+    assert.ok(created.data.createdAt.eq(subscription.createdAt));
+  });
+
+  it("Authorizes bounded auto-renewal with a scoped delegate", async () => {
+    const renewPlanId = new anchor.BN(22);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, renewPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, renewPlanId);
+
+    await program.methods
+      .subscribe(renewPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const authorize = (cycles: number, expiresAt: anchor.BN) =>
+      program.methods
+        .authorizeAutoRenew(renewPlanId, cycles, expiresAt)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([member.wallet])
+        .rpc();
+
+    try {
+      await authorize(3, new anchor.BN(1));
+      assert.fail("Should have rejected an expiry in the past");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAutoRenewAuthorization");
+    }
+
+    const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 365 * 86400);
+    await authorize(3, expiresAt);
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.equal(subscription.autoRenewCyclesRemaining, 3);
+    assert.ok(subscription.autoRenewExpiresAt.eq(expiresAt));
+    const tokenAccount = await getAccount(provider.connection, member.tokenAccount);
+    assert.ok(tokenAccount.delegate.equals(subscriptionPlanPda));
+    assert.equal(tokenAccount.delegatedAmount.toString(), price.muln(3).toString());
+  });
+
+  it("Clones a plan's settings into a fresh plan", async () => {
+    const sourcePlanId = new anchor.BN(22);
+    const clonePlanId = new anchor.BN(23);
+    const sourcePlanPda = findPlanPda(creator.publicKey, sourcePlanId);
+    const clonePlanPda = findPlanPda(creator.publicKey, clonePlanId);
+
+    await program.methods
+      .clonePlan(sourcePlanId, clonePlanId)
+      .accounts({
+        sourcePlan: sourcePlanPda,
+        subscriptionPlan: clonePlanPda,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        creator: creator.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const source = await program.account.subscriptionPlan.fetch(sourcePlanPda);
+    const clone = await program.account.subscriptionPlan.fetch(clonePlanPda);
+    assert.ok(clone.planId.eq(clonePlanId));
+    assert.ok(clone.price.eq(source.price));
+    assert.ok(clone.intervalSeconds.eq(source.intervalSeconds));
+    assert.equal(clone.maxSubscribers, source.maxSubscribers);
+    assert.equal(clone.metadataUri, source.metadataUri);
+    assert.deepEqual(clone.metadataHash, source.metadataHash);
+    assert.ok(clone.setupFee.eq(source.setupFee));
+    assert.ok(clone.paymentMint.equals(source.paymentMint));
+    assert.equal(clone.paymentDecimals, source.paymentDecimals);
+    assert.equal(clone.currentSubscribers, 0);
+    assert.isTrue(clone.isActive);
+    assert.ok(clone.creationNonce.gt(source.creationNonce));
+  });
+
+  it("Rejects a charge above the subscriber's max_token_amount", async () => {
+    const cappedPlanId = new anchor.BN(23);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, cappedPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, cappedPlanId);
+
+    // The subscriber quotes the current charge, then the price moves
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const quoted = plan.price.add(plan.setupFee);
+    const newPrice = plan.price.muln(2);
+    await program.methods
+      .updateSubscriptionPlan(cappedPlanId, newPrice, null, null, null, null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    const subscribe = (maxTokenAmount: anchor.BN) =>
+      program.methods
+        .subscribe(cappedPlanId, emptyReference, new anchor.BN(0), maxTokenAmount, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+
+    try {
+      await subscribe(quoted);
+      assert.fail("Should have rejected a charge above the cap");
+    } catch (error) {
+      assert.include(error.toString(), "SlippageExceeded");
+    }
+
+    await subscribe(newPrice.add(plan.setupFee));
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.isTrue(subscription.isActive);
+    const updatedPlan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(updatedPlan.activeCount, 1);
+  });
+
+  it("Lets a sponsor pay the subscription's rent", async () => {
+    const sponsoredPlanId = new anchor.BN(23);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, sponsoredPlanId);
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, sponsoredPlanId);
+    const sponsor = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(sponsor.publicKey, LAMPORTS_PER_SOL)
+    );
+
+    const sponsorBefore = await provider.connection.getBalance(sponsor.publicKey);
+    const memberBefore = await provider.connection.getBalance(member.wallet.publicKey);
+
+    await program.methods
+      .subscribe(sponsoredPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: sponsor.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet, sponsor])
+      .rpc();
+
+    const accountInfo = await provider.connection.getAccountInfo(subscriptionPda);
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(accountInfo.data.length);
+    assert.equal(sponsorBefore - (await provider.connection.getBalance(sponsor.publicKey)), rent);
+    assert.equal(await provider.connection.getBalance(member.wallet.publicKey), memberBefore);
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.subscriber.equals(member.wallet.publicKey));
+    assert.isTrue(subscription.isActive);
+  });
+
+  it("Only the admin can reactivate a deactivated plan", async () => {
+    const deactivatedPlanId = new anchor.BN(2);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, deactivatedPlanId);
+
+    const reactivate = (admin: Keypair | null) =>
+      program.methods
+        .reactivatePlan(deactivatedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          admin: admin ? admin.publicKey : provider.publicKey,
+        })
+        .signers(admin ? [admin] : [])
+        .rpc({ commitment: "confirmed" });
+
+    try {
+      await reactivate(creator);
+      assert.fail("Should have rejected the creator");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAdmin");
+    }
+
+    const signature = await reactivate(null);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.isActive);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const reactivated = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages),
+    ].find((e) => e.name === "subscriptionPlanReactivated");
+    assert.ok(reactivated.data.admin.equals(provider.publicKey));
+
+    try {
+      await reactivate(null);
+      assert.fail("Should have rejected an active plan");
+    } catch (error) {
+      assert.include(error.toString(), "PlanAlreadyActive");
+    }
+  });
+
+  it("Collects pledges until the activation threshold, then activates them", async () => {
+    const fundedPlanId = new anchor.BN(24);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, fundedPlanId);
+    const [pledgeVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pledge_vault"), subscriptionPlanPda.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createSubscriptionPlan(fundedPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    const fundingDeadline = new anchor.BN(Math.floor(Date.now() / 1000) + 86400);
+    await program.methods
+      .setActivationThreshold(fundedPlanId, 2, fundingDeadline)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .initializePledgeVault(fundedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        pledgeVault: pledgeVaultPda,
+        paymentMint: tokenMint,
+        creator: creator.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    const backers = [await createFundedSubscriber(), await createFundedSubscriber()];
+    const pledge = (backer: { wallet: Keypair; tokenAccount: PublicKey }) =>
+      program.methods
+        .pledge(fundedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(backer.wallet.publicKey, fundedPlanId),
+          subscriber: backer.wallet.publicKey,
+          subscriberTokenAccount: backer.tokenAccount,
+          pledgeVault: pledgeVaultPda,
+          paymentMint: tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backer.wallet])
+        .rpc({ commitment: "confirmed" });
+
+    // Plain subscribe is closed while the round is open
+    try {
+      await program.methods
+        .subscribe(fundedPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(backers[0].wallet.publicKey, fundedPlanId),
+          subscriber: backers[0].wallet.publicKey,
+          rentPayer: backers[0].wallet.publicKey,
+          fundingAuthority: backers[0].wallet.publicKey,
+          subscriberTokenAccount: backers[0].tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([backers[0].wallet])
+        .rpc();
+      assert.fail("Should have required a pledge");
+    } catch (error) {
+      assert.include(error.toString(), "FundingInProgress");
+    }
+
+    await pledge(backers[0]);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isFalse(plan.thresholdReached);
+    assert.equal((await getAccount(provider.connection, pledgeVaultPda)).amount.toString(), price.toString());
+
+    const signature = await pledge(backers[1]);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.thresholdReached);
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const reached = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages),
+    ].find((e) => e.name === "thresholdReached");
+    assert.equal(reached.data.pledges, 2);
+
+    const backerSubscription = findSubscriptionPda(backers[0].wallet.publicKey, fundedPlanId);
+    try {
+      await program.methods
+        .refundPledge(fundedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: backerSubscription,
+          subscriber: backers[0].wallet.publicKey,
+          subscriberTokenAccount: backers[0].tokenAccount,
+          pledgeVault: pledgeVaultPda,
+          paymentMint: tokenMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([backers[0].wallet])
+        .rpc();
+      assert.fail("Should not refund a successful round");
+    } catch (error) {
+      assert.include(error.toString(), "FundingNotFailed");
+    }
+
+    const creatorBefore = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+    await program.methods
+      .activatePledge(fundedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: backerSubscription,
+        pledgeVault: pledgeVaultPda,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const subscription = await program.account.subscription.fetch(backerSubscription);
+    assert.isTrue(subscription.isActive);
+    assert.equal(subscription.pledgedAmount.toNumber(), 0);
+    const creatorAfter = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+    assert.equal((creatorAfter - creatorBefore).toString(), price.toString());
+
+    // The price drops before the second pledge is activated: only the new
+    // price reaches the creator and the rest is credited as prepaid
+    const loweredPrice = price.divn(4);
+    await program.methods
+      .updateSubscriptionPlan(fundedPlanId, loweredPrice, null, null, null, null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+
+    // The difference has nowhere to go without the prepaid vault
+    const secondSubscription = findSubscriptionPda(backers[1].wallet.publicKey, fundedPlanId);
+    try {
+      await program.methods
+        .activatePledge(fundedPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: secondSubscription,
+          pledgeVault: pledgeVaultPda,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Should have required the prepaid vault");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidPrepaidVault");
+    }
+
+    const [prepaidVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("prepaid_vault"), subscriptionPlanPda.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .initializePrepaidVault(fundedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        prepaidVault: prepaidVaultPda,
+        paymentMint: tokenMint,
+        creator: creator.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+
+    await program.methods
+      .activatePledge(fundedPlanId)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: secondSubscription,
+        pledgeVault: pledgeVaultPda,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([{ pubkey: prepaidVaultPda, isWritable: true, isSigner: false }])
+      .rpc();
+
+    const overpaid = price.sub(loweredPrice);
+    const second = await program.account.subscription.fetch(secondSubscription);
+    assert.isTrue(second.isActive);
+    assert.ok(second.prepaidBalance.eq(overpaid));
+    assert.equal((await getAccount(provider.connection, prepaidVaultPda)).amount.toString(), overpaid.toString());
+    const creatorFinal = (await getAccount(provider.connection, creatorTokenAccount)).amount;
+    assert.equal((creatorFinal - creatorAfter).toString(), loweredPrice.toString());
+  });
+
+  it("Rejects the subscriber's own account as the payee", async () => {
+    const selfPayPlanId = new anchor.BN(23);
+    const member = await createFundedSubscriber();
+
+    try {
+      await program.methods
+        .subscribe(selfPayPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, selfPayPlanId),
+          subscription: findSubscriptionPda(member.wallet.publicKey, selfPayPlanId),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: member.tokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected a self-payment");
+    } catch (error) {
+      assert.include(error.toString(), "SelfPayment");
+    }
+  });
+
+  it("Requires a memo instruction when the plan asks for one", async () => {
+    const memoPlanId = new anchor.BN(25);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, memoPlanId);
+    const memoProgramId = new PublicKey("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+    await program.methods
+      .createSubscriptionPlan(memoPlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    await program.methods
+      .setRequireMemo(memoPlanId, true)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscribe = (preInstructions: TransactionInstruction[]) =>
+      program.methods
+        .subscribe(memoPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(member.wallet.publicKey, memoPlanId),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions(preInstructions)
+        .signers([member.wallet])
+        .rpc();
+
+    try {
+      await subscribe([]);
+      assert.fail("Should have required a memo");
+    } catch (error) {
+      assert.include(error.toString(), "MemoRequired");
+    }
+
+    await subscribe([
+      new TransactionInstruction({
+        programId: memoProgramId,
+        keys: [],
+        data: Buffer.from("INV-2024-0042"),
+      }),
+    ]);
+    const subscription = await program.account.subscription.fetch(
+      findSubscriptionPda(member.wallet.publicKey, memoPlanId)
+    );
+    assert.isTrue(subscription.isActive);
+
+    // Sibling charging paths are held to the same rule
+    try {
+      await program.methods
+        .processPaymentCatchup(memoPlanId, 3)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(member.wallet.publicKey, memoPlanId),
+          subscriber: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have required a memo on catch-up");
+    } catch (error) {
+      assert.include(error.toString(), "MemoRequired");
+    }
+  });
+
+  it("Refuses to migrate a subscription that is already current", async () => {
+    try {
+      await program.methods
+        .migrateSubscriptionAccount(planId)
+        .accounts({
+          subscription: findSubscriptionPda(subscriber.publicKey, planId),
+          payer: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Should have refused to migrate");
+    } catch (error) {
+      assert.include(error.toString(), "AccountAlreadyCurrent");
+    }
+  });
+
+  it("Creates accounts on the current schema version", async () => {
+    const planPda = findPlanPda(creator.publicKey, planId);
+    const plan = await program.account.subscriptionPlan.fetch(planPda);
+    const subscription = await program.account.subscription.fetch(
+      findSubscriptionPda(subscriber.publicKey, planId)
+    );
+    assert.equal(plan.schemaVersion, 3);
+    assert.equal(subscription.schemaVersion, 3);
+
+    try {
+      await program.methods
+        .migrateAccount()
+        .accounts({
+          account: planPda,
+          payer: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("Should have refused to migrate");
+    } catch (error) {
+      assert.include(error.toString(), "AccountAlreadyCurrent");
+    }
+  });
+
+  it("Collects payment through a delegated funding authority", async () => {
+    const delegatedPlanId = new anchor.BN(23);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, delegatedPlanId);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const charge = plan.price.add(plan.setupFee);
+
+    // A treasury account owned by someone other than the subscriber, with a
+    // delegate approved for exactly one charge
+    const treasury = await createFundedSubscriber();
+    const delegate = Keypair.generate();
+    await approve(
+      provider.connection,
+      treasury.wallet,
+      treasury.tokenAccount,
+      delegate.publicKey,
+      treasury.wallet,
+      BigInt(charge.toString())
+    );
+    const member = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(member.publicKey, LAMPORTS_PER_SOL)
+    );
+    const subscriptionPda = findSubscriptionPda(member.publicKey, delegatedPlanId);
+
+    const subscribe = (fundingAuthority: Keypair) =>
+      program.methods
+        .subscribe(delegatedPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.publicKey,
+          rentPayer: member.publicKey,
+          fundingAuthority: fundingAuthority.publicKey,
+          subscriberTokenAccount: treasury.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member, fundingAuthority])
+        .rpc();
+
+    try {
+      await subscribe(Keypair.generate());
+      assert.fail("Should have rejected an unauthorized funding authority");
+    } catch (error) {
+      assert.include(error.toString(), "UnauthorizedFundingAuthority");
+    }
+
+    const before = await getAccount(provider.connection, treasury.tokenAccount);
+    await subscribe(delegate);
+    const after = await getAccount(provider.connection, treasury.tokenAccount);
+    assert.equal((before.amount - after.amount).toString(), charge.toString());
+    assert.equal(after.delegatedAmount.toString(), "0");
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.subscriber.equals(member.publicKey));
+    assert.isTrue(subscription.isActive);
+  });
+
+  it("Gives subscribers notice before a plan deactivates", async () => {
+    const noticePlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, noticePlanId);
+    const creatorAccounts = { subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey };
+
+    await program.methods
+      .createSubscriptionPlan(noticePlanId, price, intervalSeconds, maxSubscribers, metadataUri, metadataHash, currencySymbol)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        creatorRegistry: creatorRegistryPda,
+        programConfig: programConfigPda,
+        feeDestination: provider.publicKey,
+        paymentMint: tokenMint,
+        payoutTokenAccount: creatorTokenAccount,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([creator])
+      .rpc();
+    const notice = 7 * 24 * 60 * 60;
+    await program.methods
+      .setDeactivationNotice(noticePlanId, new anchor.BN(notice))
+      .accounts(creatorAccounts)
+      .signers([creator])
+      .rpc();
+
+    const deactivate = () =>
+      program.methods
+        .deactivatePlan(noticePlanId, null)
+        .accounts(creatorAccounts)
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+
+    const deactivateTx = await provider.connection.getTransaction(await deactivate(), {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const scheduled = [...parser.parseLogs(deactivateTx.meta.logMessages)].find(
+      (e) => e.name === "deactivationScheduled"
+    );
+    assert.ok(scheduled, "DeactivationScheduled event not found");
+
+    // Still active, and still open to new subscribers, during the notice
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.isActive);
+    assert.equal(
+      plan.deactivationEffectiveAt.toNumber(),
+      scheduled.data.timestamp.toNumber() + notice
+    );
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, noticePlanId);
+    await program.methods
+      .subscribe(noticePlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+    assert.isTrue((await program.account.subscription.fetch(subscriptionPda)).isActive);
+
+    try {
+      await deactivate();
+      assert.fail("Should have rejected a second deactivation");
+    } catch (error) {
+      assert.include(error.toString(), "DeactivationAlreadyScheduled");
+    }
+
+    await program.methods
+      .cancelDeactivation(noticePlanId)
+      .accounts(creatorAccounts)
+      .signers([creator])
+      .rpc();
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.isActive);
+    assert.equal(plan.deactivationEffectiveAt.toNumber(), 0);
+  });
+
+  it("Configures a secondary payment token", async () => {
+    const secondaryPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, secondaryPlanId);
+    const secondaryMint = await createMint(provider.connection, creator, creator.publicKey, null, 6);
+    const secondaryPayout = await createAccount(
+      provider.connection,
+      creator,
+      secondaryMint,
+      creator.publicKey
+    );
+    const secondaryPrice = new anchor.BN(2500000);
+
+    const setSecondary = (mint: PublicKey, payout: PublicKey) =>
+      program.methods
+        .setSecondaryPayment(secondaryPlanId, secondaryPrice)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          creator: creator.publicKey,
+          secondaryMint: mint,
+          secondaryPayoutTokenAccount: payout,
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await setSecondary(tokenMint, creatorTokenAccount);
+      assert.fail("Should have rejected the primary mint as secondary");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidSecondaryMint");
+    }
+
+    await setSecondary(secondaryMint, secondaryPayout);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.secondaryPaymentMint.equals(secondaryMint));
+    assert.ok(plan.secondaryPayoutTokenAccount.equals(secondaryPayout));
+    assert.ok(plan.secondaryPrice.eq(secondaryPrice));
+
+    await program.methods
+      .clearSecondaryPayment(secondaryPlanId)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isNull(plan.secondaryPaymentMint);
+  });
+
+  it("Reports plan health from the plan's counters", async () => {
+    const healthPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, healthPlanId);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+
+    const health = await program.methods
+      .planHealth(healthPlanId)
+      .accounts({ subscriptionPlan: subscriptionPlanPda })
+      .view();
+
+    assert.equal(health.currentSubscribers, plan.currentSubscribers);
+    assert.equal(health.maxSubscribers, plan.maxSubscribers);
+    assert.equal(
+      health.utilizationBps,
+      Math.floor((plan.currentSubscribers * 10000) / plan.maxSubscribers)
+    );
+    assert.isTrue(health.isActive);
+    assert.isFalse(health.isPaused);
+    assert.ok(health.accruedRevenue.eq(plan.accruedRevenue));
+    assert.ok(plan.accruedRevenue.gt(new anchor.BN(0)));
+    assert.equal(health.daysSinceLastPayment, 0);
+  });
+
+  it("Configures a capped loyalty discount", async () => {
+    const loyaltyPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, loyaltyPlanId);
+    const setLoyaltyDiscount = (bpsPerYear: number, maxBps: number) =>
+      program.methods
+        .setLoyaltyDiscount(loyaltyPlanId, bpsPerYear, maxBps)
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    for (const [bpsPerYear, maxBps] of [[600, 500], [0, 10001]]) {
+      try {
+        await setLoyaltyDiscount(bpsPerYear, maxBps);
+        assert.fail("Should have rejected the discount");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidLoyaltyDiscount");
+      }
+    }
+
+    await setLoyaltyDiscount(500, 1500);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.loyaltyDiscountBpsPerYear, 500);
+    assert.equal(plan.maxLoyaltyDiscountBps, 1500);
+
+    // Tenure is measured from the subscription's creation
+    const subscription = await program.account.subscription.fetch(
+      findSubscriptionPda(subscriber.publicKey, planId)
+    );
+    assert.ok(subscription.createdAt.gt(new anchor.BN(0)));
+    assert.ok(subscription.createdAt.lte(subscription.lastPayment));
+  });
+
+  it("Pays all due subscriptions, skipping ones not yet due", async () => {
+    const member = await createFundedSubscriber();
+    const planIds = [new anchor.BN(23), new anchor.BN(26)];
+    for (const id of planIds) {
+      await program.methods
+        .subscribe(id, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, id),
+          subscription: findSubscriptionPda(member.wallet.publicKey, id),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+    }
+
+    const group = (id: anchor.BN) => [
+      { pubkey: findSubscriptionPda(member.wallet.publicKey, id), isSigner: false, isWritable: true },
+      { pubkey: findPlanPda(creator.publicKey, id), isSigner: false, isWritable: true },
+      { pubkey: tokenMint, isSigner: false, isWritable: false },
+      { pubkey: member.tokenAccount, isSigner: false, isWritable: true },
+      { pubkey: creatorTokenAccount, isSigner: false, isWritable: true },
+    ];
+    const payAllDue = (signer: Keypair, remainingAccounts) =>
+      program.methods
+        .payAllDue()
+        .accounts({ subscriber: signer.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(remainingAccounts)
+        .signers([signer])
+        .rpc({ commitment: "confirmed" });
+
+    try {
+      await payAllDue(member.wallet, group(planIds[0]).slice(0, 4));
+      assert.fail("Should have rejected an incomplete group");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidPayAllDueAccounts");
+    }
+
+    const stranger = Keypair.generate();
+    try {
+      await payAllDue(stranger, group(planIds[0]));
+      assert.fail("Should have rejected another subscriber's subscription");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidSubscriber");
+    }
+
+    // Both subscriptions were just paid, so nothing is charged
+    const before = await getAccount(provider.connection, member.tokenAccount);
+    const signature = await payAllDue(member.wallet, planIds.flatMap(group));
+    const after = await getAccount(provider.connection, member.tokenAccount);
+    assert.equal(after.amount.toString(), before.amount.toString());
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const payments = [
+      ...new anchor.EventParser(program.programId, program.coder).parseLogs(tx.meta.logMessages),
+    ].filter((e) => e.name === "paymentProcessed");
+    assert.lengthOf(payments, 0);
+  });
+
+  it("Only clears a payment block that was recorded", async () => {
+    const member = await createFundedSubscriber();
+    const planId = new anchor.BN(26);
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, planId);
+    await program.methods
+      .subscribe(planId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: findPlanPda(creator.publicKey, planId),
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.isFalse(subscription.paymentBlocked);
+
+    try {
+      await program.methods
+        .clearPaymentBlocked(planId)
+        .accounts({
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          tokenAccount: member.tokenAccount,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected an account that never blocked a payment");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidBlockedTokenAccount");
+    }
+  });
+
+  it("Caps subscription lifetime above one interval", async () => {
+    const lifetimePlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, lifetimePlanId);
+    const setMaxLifetime = (seconds: anchor.BN) =>
+      program.methods
+        .setMaxLifetime(lifetimePlanId, seconds)
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    try {
+      await setMaxLifetime(plan.intervalSeconds);
+      assert.fail("Should have rejected a lifetime of one interval");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidMaxLifetime");
+    }
+
+    const lifetime = plan.intervalSeconds.muln(12);
+    await setMaxLifetime(lifetime);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.maxLifetimeSeconds.eq(lifetime));
+
+    await setMaxLifetime(new anchor.BN(0));
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.maxLifetimeSeconds.isZero());
+  });
+
+  it("Checks a relayed payment permit's signature, expiry and nonce", async () => {
+    // The provider wallet relays: it pays the fee and signs nothing else
+    const subscriptionPda = findSubscriptionPda(subscriber.publicKey, planId);
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    const now = Math.floor(Date.now() / 1000);
+
+    const permitFor = (nonce: anchor.BN, expiresAt: number) => ({
+      subscription: subscriptionPda,
+      maxAmount: price,
+      nonce,
+      expiresAt: new anchor.BN(expiresAt),
+    });
+    const permitMessage = (permit) =>
+      Buffer.concat([
+        Buffer.from("circulum:payment-permit:v1"),
+        program.programId.toBuffer(),
+        permit.subscription.toBuffer(),
+        permit.maxAmount.toArrayLike(Buffer, "le", 8),
+        permit.nonce.toArrayLike(Buffer, "le", 8),
+        permit.expiresAt.toTwos(64).toArrayLike(Buffer, "le", 8),
+      ]);
+    const payWithPermit = (permit, signer: Keypair) =>
+      program.methods
+        .processPaymentWithPermit(planId, permit)
+        .accounts({
+          subscriptionPlan: findPlanPda(creator.publicKey, planId),
+          subscription: subscriptionPda,
+          subscriberTokenAccount: subscriberTokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: signer.secretKey,
+            message: permitMessage(permit),
+          }),
+        ])
+        .rpc();
+
+    const expectError = async (permit, signer: Keypair, expected: string) => {
+      try {
+        await payWithPermit(permit, signer);
+        assert.fail(`Should have failed with ${expected}`);
+      } catch (error) {
+        assert.include(error.toString(), expected);
+      }
+    };
+
+    await expectError(permitFor(subscription.permitNonce, now - 60), subscriber, "PermitExpired");
+    await expectError(permitFor(subscription.permitNonce, now + 3600), Keypair.generate(), "InvalidPermitSignature");
+    // A genuine permit passes the signature checks and then follows the
+    // usual schedule: the subscription isn't due yet
+    await expectError(permitFor(subscription.permitNonce, now + 3600), subscriber, "PaymentNotDue");
+  });
+
+  it("Closes a plan to new subscribers while existing ones keep billing", async () => {
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, planId);
+    const setNewSubscriptionsEnabled = (enabled: boolean) =>
+      program.methods
+        .setNewSubscriptionsEnabled(planId, enabled)
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    await setNewSubscriptionsEnabled(false);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.newSubscriptionsDisabled);
+    assert.isFalse(plan.isPaused);
+
+    const member = await createFundedSubscriber();
+    try {
+      await program.methods
+        .subscribe(planId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(member.wallet.publicKey, planId),
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+      assert.fail("Should have rejected a new subscriber");
+    } catch (error) {
+      assert.include(error.toString(), "NewSubscriptionsDisabled");
+    }
+
+    // Renewals go through the usual checks only: the subscription just
+    // isn't due yet
+    try {
+      await program.methods
+        .processPayment(planId, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: findSubscriptionPda(subscriber.publicKey, planId),
+          subscriber: subscriber.publicKey,
+          fundingAuthority: subscriber.publicKey,
+          subscriberTokenAccount: subscriberTokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([subscriber])
+        .rpc();
+      assert.fail("Should not have been due");
+    } catch (error) {
+      assert.include(error.toString(), "PaymentNotDue");
+      assert.notInclude(error.toString(), "NewSubscriptionsDisabled");
+    }
+
+    await setNewSubscriptionsEnabled(true);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isFalse(plan.newSubscriptionsDisabled);
+  });
+
+  it("Updates metadata across a creator's plans in one batch", async () => {
+    const planIds = [new anchor.BN(26), new anchor.BN(27)];
+    const plans = planIds.map((id) => ({
+      pubkey: findPlanPda(creator.publicKey, id),
+      isSigner: false,
+      isWritable: true,
+    }));
+    const rebrandedUri = "https://example.com/rebrand.json";
+    const rebrandedHash = Array(32).fill(7);
+
+    try {
+      await program.methods
+        .updateMetadataBatch(rebrandedUri, rebrandedHash)
+        .accounts({ creator: subscriber.publicKey })
+        .remainingAccounts(plans)
+        .signers([subscriber])
+        .rpc();
+      assert.fail("Should have rejected plans the signer didn't create");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidCreator");
+    }
+
+    await program.methods
+      .updateMetadataBatch(rebrandedUri, rebrandedHash)
+      .accounts({ creator: creator.publicKey })
+      .remainingAccounts(plans)
+      .signers([creator])
+      .rpc();
+
+    for (const { pubkey } of plans) {
+      const plan = await program.account.subscriptionPlan.fetch(pubkey);
+      assert.equal(plan.metadataUri, rebrandedUri);
+      assert.deepEqual(plan.metadataHash, rebrandedHash);
+    }
+  });
+
+  it("Holds cancellation until the plan's minimum duration has passed", async () => {
+    const minDurationPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, minDurationPlanId);
+    const setMinDuration = (seconds: number) =>
+      program.methods
+        .setMinSubscriptionDuration(minDurationPlanId, new anchor.BN(seconds))
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    try {
+      await setMinDuration(plan.intervalSeconds.toNumber() + 1);
+      assert.fail("Should have rejected a minimum longer than the interval");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidMinSubscriptionDuration");
+    }
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, minDurationPlanId);
+    await program.methods
+      .subscribe(minDurationPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+
+    const cancel = () =>
+      program.methods
+        .cancelSubscription(minDurationPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+        })
+        .signers([member.wallet])
+        .rpc();
+
+    await setMinDuration(3600);
+    try {
+      await cancel();
+      assert.fail("Should have held the cancellation");
+    } catch (error) {
+      assert.include(error.toString(), "MinimumDurationNotMet");
+    }
+
+    await setMinDuration(0);
+    await cancel();
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.isFalse(subscription.isActive);
+  });
+
+  it("Writes immutable plan snapshots keyed by index", async () => {
+    const snapshotPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, snapshotPlanId);
+    const findSnapshotPda = (index: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("plan_snapshot"), subscriptionPlanPda.toBuffer(), index.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    const snapshot = (index: anchor.BN) =>
+      program.methods
+        .snapshotPlan(snapshotPlanId, index)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          planSnapshot: findSnapshotPda(index),
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+    const first = new anchor.BN(0);
+    await snapshot(first);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const recorded = await program.account.planSnapshot.fetch(findSnapshotPda(first));
+    assert.equal(recorded.currentSubscribers, plan.currentSubscribers);
+    assert.ok(recorded.totalRevenue.eq(plan.accruedRevenue));
+    assert.ok(recorded.snapshotIndex.eq(first));
+
+    try {
+      await snapshot(first);
+      assert.fail("Should not have overwritten an existing snapshot");
+    } catch (error) {
+      assert.include(error.logs.join("\n"), "already in use");
+    }
+
+    const second = new anchor.BN(1);
+    await snapshot(second);
+    const unchanged = await program.account.planSnapshot.fetch(findSnapshotPda(first));
+    assert.ok(unchanged.timestamp.eq(recorded.timestamp));
+    assert.ok((await program.account.planSnapshot.fetch(findSnapshotPda(second))).snapshotIndex.eq(second));
+  });
+
+  it("Bills a subscription in the accepted token it chose", async () => {
+    const multiTokenPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, multiTokenPlanId);
+    const altMint = await createMint(provider.connection, creator, creator.publicKey, null, 9);
+    const altPayout = await createAccount(provider.connection, creator, altMint, creator.publicKey);
+    const altPrice = new anchor.BN(3000000000);
+
+    await program.methods
+      .addAcceptedToken(multiTokenPlanId, altPrice)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        creator: creator.publicKey,
+        mint: altMint,
+        payoutTokenAccount: altPayout,
+      })
+      .signers([creator])
+      .rpc();
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.acceptedTokens.length, 1);
+    assert.ok(plan.acceptedTokens[0].price.eq(altPrice));
+
+    const member = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(member.publicKey, 2 * LAMPORTS_PER_SOL)
+    );
+    const memberAltAccount = await createAccount(provider.connection, member, altMint, member.publicKey);
+    await mintTo(provider.connection, creator, altMint, memberAltAccount, creator, 10000000000);
+    const subscriptionPda = findSubscriptionPda(member.publicKey, multiTokenPlanId);
+    const subscribeWith = (mint: PublicKey, tokenAccount: PublicKey, payout: PublicKey) =>
+      program.methods
+        .subscribe(multiTokenPlanId, emptyReference, new anchor.BN(0), null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.publicKey,
+          rentPayer: member.publicKey,
+          fundingAuthority: member.publicKey,
+          subscriberTokenAccount: tokenAccount,
+          paymentMint: mint,
+          creatorTokenAccount: payout,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member])
+        .rpc();
+
+    const unlistedMint = await createMint(provider.connection, creator, creator.publicKey, null, 9);
+    const unlistedAccount = await createAccount(provider.connection, member, unlistedMint, member.publicKey);
+    const unlistedPayout = await createAccount(provider.connection, creator, unlistedMint, creator.publicKey);
+    try {
+      await subscribeWith(unlistedMint, unlistedAccount, unlistedPayout);
+      assert.fail("Should have rejected a token the plan doesn't accept");
+    } catch (error) {
+      assert.include(error.toString(), "PaymentTokenNotAccepted");
+    }
+
+    await subscribeWith(altMint, memberAltAccount, altPayout);
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.paymentMint.equals(altMint));
+    assert.equal((await getAccount(provider.connection, altPayout)).amount, BigInt(altPrice.toString()));
+
+    // Renewals stay in the chosen token: the plan's own mint is refused,
+    // the chosen one only fails for not being due yet
+    const memberPrimary = await createFundedSubscriber();
+    const renewWith = (mint: PublicKey, tokenAccount: PublicKey, payout: PublicKey) =>
+      program.methods
+        .processPayment(multiTokenPlanId, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.publicKey,
+          fundingAuthority: member.publicKey,
+          subscriberTokenAccount: tokenAccount,
+          paymentMint: mint,
+          creatorTokenAccount: payout,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([member])
+        .rpc();
+    try {
+      await renewWith(tokenMint, memberPrimary.tokenAccount, creatorTokenAccount);
+      assert.fail("Should have refused the plan's own mint");
+    } catch (error) {
+      assert.include(error.toString(), "MintMismatch");
+    }
+    try {
+      await renewWith(altMint, memberAltAccount, altPayout);
+      assert.fail("Should not have been due");
+    } catch (error) {
+      assert.include(error.toString(), "PaymentNotDue");
+    }
+
+    await program.methods
+      .removeAcceptedToken(multiTokenPlanId, altMint)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.acceptedTokens.length, 0);
+  });
+
+  it("Lets subscribers choose and adjust their price above the floor", async () => {
+    const pwywPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, pwywPlanId);
+    const plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    const floor = plan.price;
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, pwywPlanId);
+    const subscribe = (chosenPrice: anchor.BN) =>
+      program.methods
+        .subscribe(pwywPlanId, emptyReference, new anchor.BN(0), null, chosenPrice)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+          rentPayer: member.wallet.publicKey,
+          fundingAuthority: member.wallet.publicKey,
+          subscriberTokenAccount: member.tokenAccount,
+          paymentMint: tokenMint,
+          creatorTokenAccount: creatorTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([member.wallet])
+        .rpc();
+    const updateChosenPrice = (newPrice: anchor.BN) =>
+      program.methods
+        .updateChosenPrice(pwywPlanId, newPrice)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+        })
+        .signers([member.wallet])
+        .rpc();
+
+    try {
+      await subscribe(floor.muln(2));
+      assert.fail("Should have required pay-what-you-want to be enabled");
+    } catch (error) {
+      assert.include(error.toString(), "PayWhatYouWantDisabled");
+    }
+
+    await program.methods
+      .setPayWhatYouWant(pwywPlanId, true)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+    try {
+      await subscribe(floor.subn(1));
+      assert.fail("Should have rejected a price below the floor");
+    } catch (error) {
+      assert.include(error.toString(), "ChosenPriceBelowFloor");
+    }
+
+    const before = (await getAccount(provider.connection, member.tokenAccount)).amount;
+    await subscribe(floor.muln(2));
+    const after = (await getAccount(provider.connection, member.tokenAccount)).amount;
+    assert.equal(before - after, BigInt(floor.muln(2).toString()));
+    let subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.chosenPrice.eq(floor.muln(2)));
+
+    await updateChosenPrice(floor.muln(3));
+    subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.chosenPrice.eq(floor.muln(3)));
+
+    await updateChosenPrice(floor);
+    subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.ok(subscription.chosenPrice.eq(floor));
+
+    try {
+      await updateChosenPrice(floor.subn(1));
+      assert.fail("Should have rejected lowering below the floor");
+    } catch (error) {
+      assert.include(error.toString(), "ChosenPriceBelowFloor");
+    }
+  });
+
+  it("Rejects a cancel bundled with the subscribe that started it", async () => {
+    const guardPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, guardPlanId);
+    await program.methods
+      .setBlockSameTxCancel(guardPlanId, true)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+
+    const member = await createFundedSubscriber();
+    const subscriptionPda = findSubscriptionPda(member.wallet.publicKey, guardPlanId);
+    const subscribeIx = await program.methods
+      .subscribe(guardPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    const cancel = () =>
+      program.methods
+        .cancelSubscription(guardPlanId)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          subscription: subscriptionPda,
+          subscriber: member.wallet.publicKey,
+        })
+        .signers([member.wallet]);
+
+    try {
+      await cancel().preInstructions([subscribeIx]).rpc();
+      assert.fail("Should have rejected subscribe and cancel in one transaction");
+    } catch (error) {
+      assert.include(error.toString(), "SameTransactionCancel");
+    }
+
+    // Subscribing and cancelling in separate transactions still works
+    await program.methods
+      .subscribe(guardPlanId, emptyReference, new anchor.BN(0), null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        subscription: subscriptionPda,
+        subscriber: member.wallet.publicKey,
+        rentPayer: member.wallet.publicKey,
+        fundingAuthority: member.wallet.publicKey,
+        subscriberTokenAccount: member.tokenAccount,
+        paymentMint: tokenMint,
+        creatorTokenAccount: creatorTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([member.wallet])
+      .rpc();
+    await cancel().rpc();
+    const subscription = await program.account.subscription.fetch(subscriptionPda);
+    assert.isFalse(subscription.isActive);
+
+    await program.methods
+      .setBlockSameTxCancel(guardPlanId, false)
+      .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+      .signers([creator])
+      .rpc();
+  });
+
+  it("Sizes the grace window in seconds or billing cycles", async () => {
+    const gracePlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, gracePlanId);
+    const setGraceWindow = (graceWindow) =>
+      program.methods
+        .setGraceWindow(gracePlanId, graceWindow)
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await setGraceWindow({ seconds: { seconds: new anchor.BN(-1) } });
+      assert.fail("Should have rejected a negative grace window");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidGraceWindow");
+    }
+
+    try {
+      await setGraceWindow({ seconds: { seconds: new anchor.BN(91 * 86400) } });
+      assert.fail("Should have rejected a grace window over 90 days");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidGraceWindow");
+    }
+
+    await setGraceWindow({ seconds: { seconds: new anchor.BN(86400) } });
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.graceWindow.seconds.seconds.eqn(86400));
+
+    await setGraceWindow({ cycles: { cycles: 0 } });
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.graceWindow.cycles.cycles, 0);
+
+    await setGraceWindow({ default: {} });
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.deepEqual(plan.graceWindow, { default: {} });
+  });
+
+  it("Numbers plan-level events with a per-plan sequence", async () => {
+    const seqPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, seqPlanId);
+    const planAccounts = { subscriptionPlan: subscriptionPlanPda, authority: creator.publicKey };
+
+    const before = (await program.account.subscriptionPlan.fetch(subscriptionPlanPda)).eventSeq;
+    assert.ok(before.gtn(0));
+
+    await program.methods.pausePlan(seqPlanId).accounts(planAccounts).signers([creator]).rpc();
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.eventSeq.eq(before.addn(1)));
+
+    await program.methods.unpausePlan(seqPlanId).accounts(planAccounts).signers([creator]).rpc();
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.eventSeq.eq(before.addn(2)));
+  });
+
+  it("Spaces out capacity changes with a cooldown", async () => {
+    const cooldownPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, cooldownPlanId);
+    const setCooldown = (seconds: number) =>
+      program.methods
+        .setCapacityChangeCooldown(cooldownPlanId, new anchor.BN(seconds))
+        .accounts({ subscriptionPlan: subscriptionPlanPda, creator: creator.publicKey })
+        .signers([creator])
+        .rpc();
+    const setCapacity = (maxSubscribers: number) =>
+      program.methods
+        .updateSubscriptionPlan(cooldownPlanId, null, null, maxSubscribers, null, null, null)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          authority: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    try {
+      await setCooldown(-1);
+      assert.fail("Should have rejected a negative cooldown");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidCapacityChangeCooldown");
+    }
+
+    const originalMax = (await program.account.subscriptionPlan.fetch(subscriptionPlanPda)).maxSubscribers;
+    await setCapacity(originalMax + 1);
+    await setCooldown(86400);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.equal(plan.maxSubscribers, originalMax + 1);
+    assert.ok(plan.lastCapacityChange.gtn(0));
+
+    try {
+      await setCapacity(originalMax + 2);
+      assert.fail("Should have rejected a second change inside the cooldown");
+    } catch (error) {
+      assert.include(error.toString(), "CapacityChangeTooSoon");
+    }
+
+    // Clearing the cooldown mid-window would let the change through
+    try {
+      await setCooldown(0);
+      assert.fail("Should have refused to lower a running cooldown");
+    } catch (error) {
+      assert.include(error.toString(), "CapacityChangeTooSoon");
+    }
+
+    await setCooldown(2 * 86400);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.ok(plan.capacityChangeCooldownSeconds.eqn(2 * 86400));
+    assert.equal(plan.maxSubscribers, originalMax + 1);
+  });
+
+  it("Lets only the program admin verify a plan", async () => {
+    const verifiedPlanId = new anchor.BN(26);
+    const subscriptionPlanPda = findPlanPda(creator.publicKey, verifiedPlanId);
+    const setVerified = (admin: Keypair | null, verified: boolean) =>
+      program.methods
+        .setPlanVerified(verifiedPlanId, verified)
+        .accounts({
+          subscriptionPlan: subscriptionPlanPda,
+          programConfig: programConfigPda,
+          admin: admin ? admin.publicKey : provider.publicKey,
+        })
+        .signers(admin ? [admin] : [])
+        .rpc();
+
+    try {
+      await setVerified(creator, true);
+      assert.fail("Should have rejected the creator");
+    } catch (error) {
+      assert.include(error.toString(), "InvalidAdmin");
+    }
+
+    await setVerified(null, true);
+    let plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.verified);
+
+    // Plan updates leave the mark in place
+    await program.methods
+      .updateSubscriptionPlan(verifiedPlanId, null, null, null, "https://example.com/verified.json", null, null)
+      .accounts({
+        subscriptionPlan: subscriptionPlanPda,
+        programConfig: programConfigPda,
+        authority: creator.publicKey,
+      })
+      .signers([creator])
+      .rpc();
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isTrue(plan.verified);
+
+    await setVerified(null, false);
+    plan = await program.account.subscriptionPlan.fetch(subscriptionPlanPda);
+    assert.isFalse(plan.verified);
+  });
 });